@@ -0,0 +1,129 @@
+//! Compares wire size and encode/decode time of plain `StratumCodec` against the zstd
+//! compressed stream on a pool-like message mix.
+//!
+//! `cargo bench --bench compression --features compression`
+use bytes::BytesMut;
+use json_rpc_types::Id;
+use std::time::Instant;
+use tokio_util::codec::{Decoder, Encoder};
+use zkmatrix_pool_protocol::message::response::ResponseMessage;
+use zkmatrix_pool_protocol::message::stratum::{StratumCodec, StratumMessage};
+use zkmatrix_pool_protocol::transport::compression::{
+    CompressedStratumCodec, DEFAULT_COMPRESSION_LEVEL, STRATUM_DICTIONARY,
+};
+
+const MESSAGES: usize = 20_000;
+
+struct Rng(u64);
+
+impl Rng {
+    fn hex(&mut self, len: usize) -> String {
+        let mut bytes = Vec::with_capacity(len + 8);
+        while bytes.len() < len {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            bytes.extend_from_slice(&self.0.to_le_bytes());
+        }
+        bytes.truncate(len);
+        hex::encode(bytes)
+    }
+}
+
+/// One job per 20 messages, the rest split between submits and their responses, with a
+/// stale share now and then.
+fn message_mix() -> Vec<StratumMessage> {
+    let mut rng = Rng(0x2545f4914f6cdd1d);
+    let mut messages = Vec::with_capacity(MESSAGES);
+    for i in 0..MESSAGES {
+        let height = 685514 + (i / 20) as u32;
+        let job_id = format!("{}_{}", hex::encode(height.to_le_bytes()), rng.hex(8));
+        messages.push(match i % 20 {
+            0 => StratumMessage::Notify(
+                job_id,
+                u64::MAX / 4096,
+                rng.hex(32),
+                rng.hex(32),
+                rng.hex(32),
+                rng.hex(32),
+                rng.hex(32),
+                true,
+                None,
+            ),
+            n if n % 2 == 1 => {
+                StratumMessage::Submit(Id::Num(i as u64), job_id, rng.hex(8), rng.hex(691), None)
+            }
+            13 => StratumMessage::Response(
+                Id::Num(i as u64 - 1),
+                None,
+                Some(json_rpc_types::Error::with_custom_msg(
+                    json_rpc_types::ErrorCode::ServerError(1),
+                    "StaleProof",
+                )),
+            ),
+            _ => StratumMessage::Response(
+                Id::Num(i as u64 - 1),
+                Some(ResponseMessage::Bool(true)),
+                None,
+            ),
+        });
+    }
+    messages
+}
+
+fn run<C>(name: &str, mut encoder: C, mut decoder: C, plain_len: Option<usize>) -> usize
+where
+    C: Encoder<StratumMessage, Error = std::io::Error>
+        + Decoder<Item = StratumMessage, Error = std::io::Error>,
+{
+    let messages = message_mix();
+    let start = Instant::now();
+    let mut wire = BytesMut::new();
+    for msg in messages {
+        encoder.encode(msg, &mut wire).unwrap();
+    }
+    let encode_time = start.elapsed();
+    let len = wire.len();
+
+    let start = Instant::now();
+    let mut decoded = 0;
+    while decoder.decode(&mut wire).unwrap().is_some() {
+        decoded += 1;
+    }
+    let decode_time = start.elapsed();
+    assert_eq!(decoded, MESSAGES);
+
+    let ratio = plain_len
+        .map(|plain| format!("{:.1}%", len as f64 * 100.0 / plain as f64))
+        .unwrap_or_else(|| "100.0%".to_string());
+    println!(
+        "{:<24} {:>12} bytes {:>7}  encode {:>8.1?}  decode {:>8.1?}",
+        name, len, ratio, encode_time, decode_time
+    );
+    len
+}
+
+fn main() {
+    println!("{} messages", MESSAGES);
+    let plain = run("json", StratumCodec::default(), StratumCodec::default(), None);
+    run(
+        "zstd",
+        CompressedStratumCodec::new(DEFAULT_COMPRESSION_LEVEL, &[]).unwrap(),
+        CompressedStratumCodec::new(DEFAULT_COMPRESSION_LEVEL, &[]).unwrap(),
+        Some(plain),
+    );
+    run(
+        "zstd + stratum dict",
+        CompressedStratumCodec::with_stratum_dictionary().unwrap(),
+        CompressedStratumCodec::with_stratum_dictionary().unwrap(),
+        Some(plain),
+    );
+    for level in [1, 9] {
+        run(
+            &format!("zstd level {} + dict", level),
+            CompressedStratumCodec::new(level, STRATUM_DICTIONARY).unwrap(),
+            CompressedStratumCodec::new(level, STRATUM_DICTIONARY).unwrap(),
+            Some(plain),
+        );
+    }
+}
@@ -94,6 +94,7 @@ async fn start_miner() {
                 _hashed_leaves_3,
                 _hashed_leaves_4,
                 _clean_jobs,
+                _signature,
             ) => {
                 println!("miner: received new job");
                 println!("miner: mining....");
@@ -105,6 +106,7 @@ async fn start_miner() {
                         "job_id".to_string(),
                         "nonce".to_string(),
                         "proof".to_string(),
+                        None,
                     ))
                     .await;
             }
@@ -153,6 +155,7 @@ async fn start_server() {
                             "hashed_leaves_3".to_string(),
                             "hashed_leaves_4".to_string(),
                             true,
+                            None,
                         )).await.unwrap()
                     }
                     Some(Ok(msg)) = framed.next() => {
@@ -171,13 +174,14 @@ async fn start_server() {
                                     "hashed_leaves_3".to_string(),
                                     "hashed_leaves_4".to_string(),
                                     true,
+                                    None,
                                 )
                                 ).await.unwrap()
                             }
                             StratumMessage::Notify(..) => {
                                 println!("server: Unsupported msg received from client");
                             }
-                            StratumMessage::Submit(id, _, _, _) => {
+                            StratumMessage::Submit(id, _, _, _, _) => {
                                 println!("server: received submit from miner");
                                 println!("server: submit passed");
                                 let _ = framed.send(StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)).await;
@@ -188,6 +192,12 @@ async fn start_server() {
                             StratumMessage::SetTarget(..) => {
                                 println!("difficulty_target will be sent with Notify")
                             }
+                            StratumMessage::SetExtranonce(..)
+                            | StratumMessage::PuzzleNotify(..)
+                            | StratumMessage::SetEpoch(..)
+                            | StratumMessage::SubmitSolution(..) => {
+                                println!("server: Unsupported msg received from client");
+                            }
                         }
                     }
                 }
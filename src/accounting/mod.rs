@@ -0,0 +1,372 @@
+pub mod share_log;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+/// Fees are in basis points of the reward.
+pub const FEE_BPS_DENOMINATOR: u32 = 10_000;
+
+/// The work a share stands for. A share at `u64::MAX`, the easiest target, weighs 1 and
+/// halving the target doubles the weight.
+pub fn share_weight(difficulty_target: u64) -> u128 {
+    (u64::MAX / difficulty_target.max(1)) as u128
+}
+
+/// A share the pool accepted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AcceptedShare {
+    pub account_name: String,
+    pub worker_name: String,
+    pub difficulty_target: u64,
+}
+
+impl AcceptedShare {
+    pub fn new(account_name: &str, worker_name: &str, difficulty_target: u64) -> Self {
+        Self {
+            account_name: account_name.to_string(),
+            worker_name: worker_name.to_string(),
+            difficulty_target,
+        }
+    }
+
+    pub fn weight(&self) -> u128 {
+        share_weight(self.difficulty_target)
+    }
+}
+
+/// How a reward was split: what each account earned and what the pool keeps, in the
+/// reward's smallest unit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Distribution {
+    pub payouts: BTreeMap<String, u64>,
+    /// The fee plus any rounding left over
+    pub fee: u64,
+}
+
+impl Distribution {
+    pub fn total(&self) -> u64 {
+        self.payouts.values().sum::<u64>() + self.fee
+    }
+}
+
+/// A payout or fee larger than `u64::MAX`, e.g. shares worth many blocks of a huge reward.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PayoutOverflow;
+
+impl fmt::Display for PayoutOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("payout does not fit in u64")
+    }
+}
+
+impl std::error::Error for PayoutOverflow {}
+
+/// The latest accepted shares, keeping at least `max_weight` of work for PPLNS.
+#[derive(Clone, Debug)]
+pub struct ShareWindow {
+    shares: VecDeque<AcceptedShare>,
+    total_weight: u128,
+    max_weight: u128,
+}
+
+impl ShareWindow {
+    pub fn new(max_weight: u128) -> Self {
+        Self {
+            shares: VecDeque::new(),
+            total_weight: 0,
+            max_weight,
+        }
+    }
+
+    /// Adds a share, dropping the oldest ones the window no longer needs.
+    pub fn record(&mut self, share: AcceptedShare) {
+        self.total_weight += share.weight();
+        self.shares.push_back(share);
+        while let Some(oldest) = self.shares.front() {
+            if self.total_weight - oldest.weight() < self.max_weight {
+                break;
+            }
+            self.total_weight -= oldest.weight();
+            self.shares.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shares.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shares.is_empty()
+    }
+
+    pub fn total_weight(&self) -> u128 {
+        self.total_weight
+    }
+
+    /// Weight of each (account, worker) among the latest `window_weight` of work, the
+    /// oldest share counting only in part if it straddles the edge.
+    pub fn weights(&self, window_weight: u128) -> BTreeMap<(String, String), u128> {
+        let mut weights = BTreeMap::new();
+        let mut left = window_weight;
+        for share in self.shares.iter().rev() {
+            if left == 0 {
+                break;
+            }
+            let counted = share.weight().min(left);
+            left -= counted;
+            *weights
+                .entry((share.account_name.clone(), share.worker_name.clone()))
+                .or_default() += counted;
+        }
+        weights
+    }
+
+    /// PPLNS: splits a found block's reward, less the fee, between the last
+    /// `window_weight` of work.
+    pub fn pplns(&self, window_weight: u128, block_reward: u64, fee_bps: u32) -> Distribution {
+        let mut weights = BTreeMap::new();
+        for ((account_name, _), weight) in self.weights(window_weight) {
+            *weights.entry(account_name).or_default() += weight;
+        }
+        let fee_bps = capped_fee_bps(fee_bps) as u128;
+        let fee = mul_div(block_reward as u128, fee_bps, FEE_BPS_DENOMINATOR as u128).0 as u64;
+        let mut distribution = split(&weights, block_reward - fee);
+        distribution.fee += fee;
+        distribution
+    }
+}
+
+fn capped_fee_bps(fee_bps: u32) -> u32 {
+    fee_bps.min(FEE_BPS_DENOMINATOR)
+}
+
+/// PPS: pays each share its expected part of `block_reward`, a block being `block_weight`
+/// of work on average, whether or not the pool finds it.
+pub fn pps(
+    shares: &[AcceptedShare],
+    block_reward: u64,
+    block_weight: u128,
+    fee_bps: u32,
+) -> Result<Distribution, PayoutOverflow> {
+    per_share(shares, block_reward as u128, block_weight, fee_bps)
+}
+
+/// FPPS: PPS that also pays out the expected transaction fees of a block.
+pub fn fpps(
+    shares: &[AcceptedShare],
+    block_reward: u64,
+    transaction_fees: u64,
+    block_weight: u128,
+    fee_bps: u32,
+) -> Result<Distribution, PayoutOverflow> {
+    per_share(
+        shares,
+        block_reward as u128 + transaction_fees as u128,
+        block_weight,
+        fee_bps,
+    )
+}
+
+/// Pays shares their part of `reward`, which may exceed a `u64` as long as the payouts
+/// don't.
+fn per_share(
+    shares: &[AcceptedShare],
+    reward: u128,
+    block_weight: u128,
+    fee_bps: u32,
+) -> Result<Distribution, PayoutOverflow> {
+    let mut weights: BTreeMap<String, u128> = BTreeMap::new();
+    for share in shares {
+        *weights.entry(share.account_name.clone()).or_default() += share.weight();
+    }
+    let net = reward * (FEE_BPS_DENOMINATOR - capped_fee_bps(fee_bps)) as u128;
+    let denominator = block_weight.max(1) * FEE_BPS_DENOMINATOR as u128;
+    let mut distribution = Distribution::default();
+    let mut gross = 0;
+    for (account_name, weight) in weights {
+        gross += weight;
+        let payout =
+            u64::try_from(mul_div(weight, net, denominator).0).map_err(|_| PayoutOverflow)?;
+        distribution.payouts.insert(account_name, payout);
+    }
+    let gross =
+        u64::try_from(mul_div(gross, reward, block_weight.max(1)).0).map_err(|_| PayoutOverflow)?;
+    distribution.fee = gross - distribution.payouts.values().sum::<u64>();
+    Ok(distribution)
+}
+
+/// Splits `amount` in proportion to `weights`, exactly: the units rounding leaves over go
+/// one each to the largest remainders, ties to the first account name.
+fn split(weights: &BTreeMap<String, u128>, amount: u64) -> Distribution {
+    let total: u128 = weights.values().sum();
+    if total == 0 {
+        return Distribution {
+            payouts: BTreeMap::new(),
+            fee: amount,
+        };
+    }
+    let mut payouts = BTreeMap::new();
+    let mut remainders = vec![];
+    let mut paid = 0;
+    for (account_name, weight) in weights {
+        let (payout, remainder) = mul_div(amount as u128, *weight, total);
+        paid += payout as u64;
+        payouts.insert(account_name.clone(), payout as u64);
+        remainders.push((remainder, account_name));
+    }
+    // stable, so equal remainders keep name order
+    remainders.sort_by_key(|(remainder, _)| std::cmp::Reverse(*remainder));
+    for (_, account_name) in remainders.into_iter().take((amount - paid) as usize) {
+        *payouts.get_mut(account_name).unwrap() += 1;
+    }
+    Distribution { payouts, fee: 0 }
+}
+
+/// `a * b / c` and its remainder, without overflowing: the product is kept in 256 bits.
+/// The quotient must fit in 128 bits.
+fn mul_div(a: u128, b: u128, c: u128) -> (u128, u128) {
+    const LOW: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (a >> 64, a & LOW);
+    let (b_hi, b_lo) = (b >> 64, b & LOW);
+    let (lo_lo, lo_hi, hi_lo, hi_hi) = (a_lo * b_lo, a_lo * b_hi, a_hi * b_lo, a_hi * b_hi);
+    let mid = (lo_lo >> 64) + (lo_hi & LOW) + (hi_lo & LOW);
+    let lo = (lo_lo & LOW) | (mid << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (mid >> 64);
+
+    let (mut quotient, mut remainder) = (0u128, 0u128);
+    for i in (0..256).rev() {
+        let bit = if i >= 128 {
+            (hi >> (i - 128)) & 1
+        } else {
+            (lo >> i) & 1
+        };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | bit;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            if i < 128 {
+                quotient |= 1 << i;
+            }
+        }
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+fn payouts(payouts: &[(&str, u64)]) -> BTreeMap<String, u64> {
+    payouts.iter().map(|(a, p)| (a.to_string(), *p)).collect()
+}
+
+#[test]
+fn test_share_weight() {
+    assert_eq!(share_weight(u64::MAX), 1);
+    assert_eq!(share_weight(u64::MAX / 3), 3);
+    assert_eq!(share_weight(1), u64::MAX as u128);
+    assert_eq!(share_weight(0), u64::MAX as u128);
+}
+
+#[test]
+fn test_mul_div() {
+    assert_eq!(mul_div(7, 3, 2), (10, 1));
+    assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), (u128::MAX, 0));
+    assert_eq!(mul_div(u128::MAX, 3, 6), (u128::MAX / 2, 3));
+    assert_eq!(mul_div(1 << 100, 1 << 100, 1 << 73), (1 << 127, 0));
+}
+
+#[test]
+fn test_pplns() {
+    let mut window = ShareWindow::new(6);
+    for (account_name, weight) in [("alice", 1), ("bob", 2), ("carol", 3)] {
+        window.record(AcceptedShare::new(account_name, "rig", u64::MAX / weight));
+    }
+    assert_eq!(window.total_weight(), 6);
+
+    // 1% fee, then split 1:2:3
+    let distribution = window.pplns(6, 1000, 100);
+    assert_eq!(
+        distribution.payouts,
+        payouts(&[("alice", 165), ("bob", 330), ("carol", 495)])
+    );
+    assert_eq!(distribution.fee, 10);
+
+    // the oldest share counts in part at the window's edge
+    let distribution = window.pplns(4, 400, 0);
+    assert_eq!(
+        distribution.payouts,
+        payouts(&[("bob", 100), ("carol", 300)])
+    );
+
+    // rounding dust goes to the largest remainders, ties by name
+    let mut window = ShareWindow::new(3);
+    for account_name in ["carol", "bob", "alice"] {
+        window.record(AcceptedShare::new(account_name, "rig", u64::MAX));
+    }
+    let distribution = window.pplns(3, 100, 0);
+    assert_eq!(
+        distribution.payouts,
+        payouts(&[("alice", 34), ("bob", 33), ("carol", 33)])
+    );
+    assert_eq!(distribution.total(), 100);
+
+    // big rewards and weights stay exact
+    let distribution = window.pplns(3, u64::MAX, 250);
+    assert_eq!(distribution.total(), u64::MAX);
+
+    let empty = ShareWindow::new(10).pplns(10, 50, 100);
+    assert_eq!(empty.fee, 50);
+}
+
+#[test]
+fn test_share_window() {
+    let mut window = ShareWindow::new(4);
+    for (worker_name, weight) in [("rig0", 3), ("rig1", 3), ("rig0", 1)] {
+        window.record(AcceptedShare::new("alice", worker_name, u64::MAX / weight));
+    }
+    // the first share is no longer needed to cover 4
+    assert_eq!(window.len(), 2);
+    assert_eq!(window.total_weight(), 4);
+    let weights = window.weights(4);
+    assert_eq!(weights[&("alice".to_string(), "rig0".to_string())], 1);
+    assert_eq!(weights[&("alice".to_string(), "rig1".to_string())], 3);
+}
+
+#[test]
+fn test_pps_and_fpps() {
+    let shares = [
+        AcceptedShare::new("alice", "rig0", u64::MAX / 5),
+        AcceptedShare::new("bob", "rig0", u64::MAX / 3),
+        AcceptedShare::new("bob", "rig1", u64::MAX / 7),
+    ];
+
+    // 5 and 10 of 100 per block, 2% fee
+    let distribution = pps(&shares, 1000, 100, 200).unwrap();
+    assert_eq!(distribution.payouts, payouts(&[("alice", 49), ("bob", 98)]));
+    assert_eq!(distribution.fee, 3);
+    assert_eq!(distribution.total(), 150);
+
+    let distribution = fpps(&shares, 1000, 200, 100, 200).unwrap();
+    assert_eq!(
+        distribution.payouts,
+        payouts(&[("alice", 58), ("bob", 117)])
+    );
+    assert_eq!(distribution.fee, 5);
+
+    // fees above 100% are capped
+    let distribution = pps(&shares, 1000, 100, 20_000).unwrap();
+    assert_eq!(distribution.payouts, payouts(&[("alice", 0), ("bob", 0)]));
+    assert_eq!(distribution.fee, 150);
+}
+
+#[test]
+fn test_pps_overflow() {
+    // a reward plus fees above u64::MAX, paid out in parts that fit
+    let shares = [AcceptedShare::new("alice", "rig0", u64::MAX / 2)];
+    let distribution = fpps(&shares, u64::MAX, u64::MAX, 4, 0).unwrap();
+    assert_eq!(distribution.payouts, payouts(&[("alice", u64::MAX)]));
+    assert_eq!(distribution.fee, 0);
+
+    // shares worth more than one block of u64::MAX can't be paid in a u64
+    let shares = [AcceptedShare::new("alice", "rig0", u64::MAX / 3)];
+    assert_eq!(pps(&shares, u64::MAX, 2, 0), Err(PayoutOverflow));
+    assert_eq!(fpps(&shares, u64::MAX, 0, 2, 100), Err(PayoutOverflow));
+}
@@ -0,0 +1,505 @@
+use super::{AcceptedShare, ShareWindow};
+use crate::message::error::PoolError;
+use bytes::{Buf, BufMut, BytesMut};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Every log file starts with the magic and the format version.
+const MAGIC: &[u8; 4] = b"ZKSL";
+const VERSION: u8 = 1;
+const HEADER_LEN: u64 = 5;
+/// Entries are far smaller, a bigger length means a corrupt file.
+const MAX_ENTRY_LEN: u32 = 1 << 20;
+
+const RESULT_ACCEPTED: u8 = 0;
+const RESULT_REJECTED: u8 = 1;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn checksum(body: &[u8]) -> u32 {
+    let digest = Sha256::digest(body);
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+/// One submit, accepted or rejected, as the pool judged it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShareLogEntry {
+    /// Milliseconds since the unix epoch
+    pub timestamp: u64,
+    pub account_name: String,
+    pub worker_name: String,
+    pub job_id: String,
+    pub height: u32,
+    pub difficulty_target: u64,
+    pub result: Result<(), PoolError>,
+}
+
+impl ShareLogEntry {
+    /// An entry stamped with the current time.
+    pub fn new(
+        account_name: &str,
+        worker_name: &str,
+        job_id: &str,
+        height: u32,
+        difficulty_target: u64,
+        result: Result<(), PoolError>,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        Self {
+            timestamp,
+            account_name: account_name.to_string(),
+            worker_name: worker_name.to_string(),
+            job_id: job_id.to_string(),
+            height,
+            difficulty_target,
+            result,
+        }
+    }
+
+    /// The share to credit, if the pool accepted it.
+    pub fn accepted_share(&self) -> Option<AcceptedShare> {
+        self.result.as_ref().ok().map(|_| {
+            AcceptedShare::new(
+                &self.account_name,
+                &self.worker_name,
+                self.difficulty_target,
+            )
+        })
+    }
+
+    /// `[u32 body length][u32 checksum of body][body]`, big endian.
+    fn encode(&self, dst: &mut BytesMut) -> io::Result<()> {
+        let mut body = BytesMut::new();
+        body.put_u64(self.timestamp);
+        put_str(&mut body, &self.account_name)?;
+        put_str(&mut body, &self.worker_name)?;
+        put_str(&mut body, &self.job_id)?;
+        body.put_u32(self.height);
+        body.put_u64(self.difficulty_target);
+        match &self.result {
+            Ok(()) => body.put_u8(RESULT_ACCEPTED),
+            Err(e) => {
+                body.put_u8(RESULT_REJECTED);
+                put_str(&mut body, &e.to_string())?;
+            }
+        }
+        dst.put_u32(body.len() as u32);
+        dst.put_u32(checksum(&body));
+        dst.put_slice(&body);
+        Ok(())
+    }
+
+    fn decode(mut body: &[u8]) -> io::Result<Self> {
+        let timestamp = get_u64(&mut body)?;
+        let account_name = get_str(&mut body)?;
+        let worker_name = get_str(&mut body)?;
+        let job_id = get_str(&mut body)?;
+        let height = get_u32(&mut body)?;
+        let difficulty_target = get_u64(&mut body)?;
+        let result =
+            match get_u8(&mut body)? {
+                RESULT_ACCEPTED => Ok(()),
+                RESULT_REJECTED => Err(PoolError::from_str(&get_str(&mut body)?)
+                    .map_err(|e| invalid(&e.to_string()))?),
+                _ => return Err(invalid("Invalid share result")),
+            };
+        if !body.is_empty() {
+            return Err(invalid("Trailing bytes in share log entry"));
+        }
+        Ok(Self {
+            timestamp,
+            account_name,
+            worker_name,
+            job_id,
+            height,
+            difficulty_target,
+            result,
+        })
+    }
+}
+
+fn put_str(dst: &mut BytesMut, s: &str) -> io::Result<()> {
+    if s.len() > u16::MAX as usize {
+        return Err(invalid("String too long"));
+    }
+    dst.put_u16(s.len() as u16);
+    dst.put_slice(s.as_bytes());
+    Ok(())
+}
+
+fn take<'a>(body: &mut &'a [u8], n: usize) -> io::Result<&'a [u8]> {
+    if body.len() < n {
+        return Err(invalid("Truncated share log entry"));
+    }
+    let (head, tail) = body.split_at(n);
+    *body = tail;
+    Ok(head)
+}
+
+fn get_u8(body: &mut &[u8]) -> io::Result<u8> {
+    Ok(take(body, 1)?[0])
+}
+
+fn get_u32(body: &mut &[u8]) -> io::Result<u32> {
+    Ok(take(body, 4)?.get_u32())
+}
+
+fn get_u64(body: &mut &[u8]) -> io::Result<u64> {
+    Ok(take(body, 8)?.get_u64())
+}
+
+fn get_str(body: &mut &[u8]) -> io::Result<String> {
+    let len = take(body, 2)?.get_u16() as usize;
+    String::from_utf8(take(body, len)?.to_vec()).map_err(|e| invalid(&e.to_string()))
+}
+
+fn log_file_name(sequence: u64) -> String {
+    format!("shares-{:010}.log", sequence)
+}
+
+fn log_file_sequence(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("shares-")?
+        .strip_suffix(".log")?
+        .parse()
+        .ok()
+}
+
+/// The log files of `dir`, oldest first.
+pub fn log_files(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if let Some(sequence) = log_file_sequence(&path) {
+            files.push((sequence, path));
+        }
+    }
+    files.sort();
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Appends entries to the log files of a directory, starting a new file once the current
+/// one reaches `max_file_size`.
+///
+/// Opening always starts a new file, so an entry torn by a crash is only ever at the end of
+/// a file. Each entry is written through to the OS as it is appended, so a crash of the
+/// process loses nothing; `flush` also syncs the file to disk.
+pub struct ShareLogWriter {
+    dir: PathBuf,
+    max_file_size: u64,
+    sequence: u64,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl ShareLogWriter {
+    pub fn open(dir: impl AsRef<Path>, max_file_size: u64) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let sequence = log_files(&dir)?
+            .last()
+            .and_then(|path| log_file_sequence(path))
+            .map_or(0, |sequence| sequence + 1);
+        let file = Self::create(&dir, sequence)?;
+        Ok(Self {
+            dir,
+            max_file_size,
+            sequence,
+            file,
+            size: HEADER_LEN,
+        })
+    }
+
+    fn create(dir: &Path, sequence: u64) -> io::Result<BufWriter<File>> {
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(log_file_name(sequence)))?;
+        let mut file = BufWriter::new(file);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.flush()?;
+        file.get_ref().sync_data()?;
+        Ok(file)
+    }
+
+    /// The file being written.
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(log_file_name(self.sequence))
+    }
+
+    pub fn append(&mut self, entry: &ShareLogEntry) -> io::Result<()> {
+        let mut buf = BytesMut::new();
+        entry.encode(&mut buf)?;
+        if self.size > HEADER_LEN && self.size + buf.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+        self.file.write_all(&buf)?;
+        self.file.flush()?;
+        self.size += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Closes the current file and starts the next one.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.sequence += 1;
+        self.file = Self::create(&self.dir, self.sequence)?;
+        self.size = HEADER_LEN;
+        Ok(())
+    }
+
+    /// Syncs appended entries to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+}
+
+impl Drop for ShareLogWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// Streams the entries of one log file.
+///
+/// A file cut off mid entry yields an `UnexpectedEof` error, a damaged entry an
+/// `InvalidData` one; either ends the stream.
+pub struct ShareLogReader<R> {
+    reader: R,
+    done: bool,
+}
+
+impl<R: Read> ShareLogReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; HEADER_LEN as usize];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("Not a share log"));
+        }
+        if header[4] != VERSION {
+            return Err(invalid("Unsupported share log version"));
+        }
+        Ok(Self {
+            reader,
+            done: false,
+        })
+    }
+
+    fn read_entry(&mut self) -> io::Result<Option<ShareLogEntry>> {
+        let mut head = [0u8; 8];
+        let mut read = 0;
+        while read < head.len() {
+            match self.reader.read(&mut head[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => read += n,
+            }
+        }
+        let mut head = &head[..];
+        let len = head.get_u32();
+        let expected = head.get_u32();
+        if len > MAX_ENTRY_LEN {
+            return Err(invalid("Share log entry too long"));
+        }
+        let mut body = vec![0u8; len as usize];
+        self.reader.read_exact(&mut body)?;
+        if checksum(&body) != expected {
+            return Err(invalid("Share log checksum mismatch"));
+        }
+        ShareLogEntry::decode(&body).map(Some)
+    }
+}
+
+impl ShareLogReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Iterator for ShareLogReader<R> {
+    type Item = io::Result<ShareLogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.read_entry().transpose();
+        if !matches!(entry, Some(Ok(_))) {
+            self.done = true;
+        }
+        entry
+    }
+}
+
+/// Feeds every entry of the log files of `dir` to `f`, oldest first, returning how many.
+///
+/// An entry torn by a crash at the end of a file is skipped, as is a file whose header a
+/// crash cut short; any other damage is an error.
+pub fn replay(dir: impl AsRef<Path>, mut f: impl FnMut(ShareLogEntry)) -> io::Result<usize> {
+    let mut count = 0;
+    for path in log_files(dir)? {
+        let reader = match ShareLogReader::open(&path) {
+            Ok(reader) => reader,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in reader {
+            match entry {
+                Ok(entry) => {
+                    f(entry);
+                    count += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Rebuilds a share window from the log, e.g. after a restart. Returns how many shares
+/// were accepted.
+pub fn replay_into(dir: impl AsRef<Path>, window: &mut ShareWindow) -> io::Result<usize> {
+    let mut accepted = 0;
+    replay(dir, |entry| {
+        if let Some(share) = entry.accepted_share() {
+            window.record(share);
+            accepted += 1;
+        }
+    })?;
+    Ok(accepted)
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("share-log-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[cfg(test)]
+fn test_entries() -> Vec<ShareLogEntry> {
+    vec![
+        ShareLogEntry::new("alice", "rig0", "job0", 100, u64::MAX / 2, Ok(())),
+        ShareLogEntry::new("bob", "rig0", "job0", 100, u64::MAX, Ok(())),
+        ShareLogEntry::new(
+            "bob",
+            "rig1",
+            "job1",
+            101,
+            u64::MAX,
+            Err(PoolError::InvalidProof(Some("proof mismatch".to_string()))),
+        ),
+        ShareLogEntry::new("carol", "", "job1", 101, u64::MAX / 3, Ok(())),
+        ShareLogEntry::new("alice", "rig0", "job0", 100, 1, Err(PoolError::StaleProof)),
+    ]
+}
+
+#[test]
+fn test_share_log() {
+    let dir = test_dir("round-trip");
+    let entries = test_entries();
+    let mut writer = ShareLogWriter::open(&dir, 100).unwrap();
+    for entry in &entries {
+        writer.append(entry).unwrap();
+    }
+    drop(writer);
+    // small files rotate, and reopening never appends to an old one
+    let files = log_files(&dir).unwrap();
+    assert!(files.len() > 1);
+    let mut writer = ShareLogWriter::open(&dir, 100).unwrap();
+    assert!(!files.contains(&writer.path()));
+    writer.append(&entries[0]).unwrap();
+    drop(writer);
+
+    let mut replayed = vec![];
+    assert_eq!(replay(&dir, |entry| replayed.push(entry)).unwrap(), 6);
+    assert_eq!(&replayed[..5], &entries[..]);
+
+    let mut window = ShareWindow::new(u128::MAX);
+    assert_eq!(replay_into(&dir, &mut window).unwrap(), 4);
+    assert_eq!(window.total_weight(), 2 + 1 + 3 + 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_share_log_damage() {
+    let dir = test_dir("damage");
+    let mut writer = ShareLogWriter::open(&dir, u64::MAX).unwrap();
+    for entry in &test_entries() {
+        writer.append(entry).unwrap();
+    }
+    let path = writer.path();
+    drop(writer);
+    let bytes = fs::read(&path).unwrap();
+
+    // a crash mid entry loses only that entry
+    fs::write(&path, &bytes[..bytes.len() - 3]).unwrap();
+    let mut reader = ShareLogReader::open(&path).unwrap();
+    assert_eq!(reader.by_ref().take(4).filter(|e| e.is_ok()).count(), 4);
+    let torn = reader.next().unwrap().unwrap_err();
+    assert_eq!(torn.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(reader.next().is_none());
+    assert_eq!(replay(&dir, |_| {}).unwrap(), 4);
+
+    // a flipped bit fails the checksum
+    let mut damaged = bytes.clone();
+    damaged[HEADER_LEN as usize + 12] ^= 1;
+    fs::write(&path, &damaged).unwrap();
+    let err = ShareLogReader::open(&path)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(replay(&dir, |_| {}).is_err());
+
+    fs::write(&path, b"nope!").unwrap();
+    assert!(ShareLogReader::open(&path).is_err());
+    assert!(replay(&dir, |_| {}).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_share_log_torn_header() {
+    let dir = test_dir("torn-header");
+    let mut writer = ShareLogWriter::open(&dir, u64::MAX).unwrap();
+    writer.append(&test_entries()[0]).unwrap();
+    // entries reach the file without a flush
+    let first = writer.path();
+    assert!(fs::metadata(&first).unwrap().len() > HEADER_LEN);
+    std::mem::forget(writer);
+
+    // a crash while creating the next file leaves it empty or with part of its header
+    let mut writer = ShareLogWriter::open(&dir, u64::MAX).unwrap();
+    let torn = writer.path();
+    assert_ne!(torn, first);
+    assert_eq!(fs::metadata(&torn).unwrap().len(), HEADER_LEN);
+    writer.append(&test_entries()[1]).unwrap();
+    drop(writer);
+    for len in [0, 3] {
+        fs::write(&torn, &MAGIC[..len]).unwrap();
+        assert_eq!(replay(&dir, |_| {}).unwrap(), 1);
+        let mut window = ShareWindow::new(u128::MAX);
+        assert_eq!(replay_into(&dir, &mut window).unwrap(), 1);
+    }
+
+    // and the log goes on in a new file after the restart
+    let mut writer = ShareLogWriter::open(&dir, u64::MAX).unwrap();
+    writer.append(&test_entries()[3]).unwrap();
+    drop(writer);
+    assert_eq!(replay(&dir, |_| {}).unwrap(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,425 @@
+use crate::message::error::PoolError;
+use crate::message::extranonce::NONCE_LENGTH;
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::{StratumCodec, StratumMessage};
+use crate::CURRENT_PROTOCOL_VERSION;
+use anyhow::anyhow;
+use futures_util::{SinkExt, StreamExt};
+use json_rpc_types::Id;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::task;
+use tokio::time::{interval_at, sleep_until, Instant, MissedTickBehavior};
+use tokio_util::codec::Framed;
+
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    /// Address of the pool under test
+    pub pool: String,
+    /// Simulated miners, one session each
+    pub miners: usize,
+    /// Time between two shares of a miner
+    pub share_interval: Duration,
+    /// Random proof bytes per share
+    pub proof_length: usize,
+    /// How long miners submit
+    pub duration: Duration,
+    /// Miners start evenly spread over this, so the pool is not hit by every connect at once
+    pub ramp_up: Duration,
+    /// How long to wait for answers to the last shares
+    pub drain_timeout: Duration,
+    pub user_agent: String,
+    pub protocol_version: String,
+    /// Miners authorize as `<account_name>.bench<index>`
+    pub account_name: String,
+}
+
+impl BenchConfig {
+    pub fn new(pool: String, miners: usize, share_interval: Duration, duration: Duration) -> Self {
+        Self {
+            pool,
+            miners,
+            share_interval,
+            proof_length: 32,
+            duration,
+            ramp_up: Duration::ZERO,
+            drain_timeout: Duration::from_secs(5),
+            user_agent: "ABMatrix_Stratum_Bench".to_string(),
+            protocol_version: CURRENT_PROTOCOL_VERSION.to_string(),
+            account_name: "aleo1bench".to_string(),
+        }
+    }
+
+    /// Rejects settings a run can't use, such as a zero `share_interval`.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.share_interval.is_zero() {
+            return Err(anyhow!("share_interval must not be zero"));
+        }
+        Ok(())
+    }
+}
+
+/// What one simulated miner saw.
+#[derive(Default)]
+struct MinerReport {
+    handshake: Option<Duration>,
+    error: Option<String>,
+    submitted: u64,
+    accepted: u64,
+    rejects: BTreeMap<String, u64>,
+    unanswered: u64,
+    latencies: Vec<Duration>,
+}
+
+impl MinerReport {
+    fn answer(
+        &mut self,
+        pending: &mut HashMap<u64, Instant>,
+        id: u64,
+        result: Option<ResponseMessage>,
+        error: Option<json_rpc_types::Error<()>>,
+    ) {
+        let sent = match pending.remove(&id) {
+            Some(sent) => sent,
+            None => return,
+        };
+        self.latencies.push(sent.elapsed());
+        let reject = match (error, result) {
+            (Some(error), _) => PoolError::from_str(&error.message)
+                .map(|e| e.name())
+                .unwrap_or("Unknown"),
+            (None, Some(ResponseMessage::Bool(false))) => "Rejected",
+            _ => {
+                self.accepted += 1;
+                return;
+            }
+        };
+        *self.rejects.entry(reject.to_string()).or_default() += 1;
+    }
+}
+
+/// Results of a run, latencies sorted.
+#[derive(Clone, Debug, Default)]
+pub struct BenchReport {
+    pub miners: usize,
+    pub connected: usize,
+    /// Why sessions failed, and how many did
+    pub errors: BTreeMap<String, usize>,
+    pub submitted: u64,
+    pub accepted: u64,
+    /// Rejected shares by `PoolError` name
+    pub rejects: BTreeMap<String, u64>,
+    pub unanswered: u64,
+    /// Submit to response
+    pub latencies: Vec<Duration>,
+    /// Connect to authorized
+    pub handshake_latencies: Vec<Duration>,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    pub fn rejected(&self) -> u64 {
+        self.rejects.values().sum()
+    }
+
+    /// Answered shares per second.
+    pub fn throughput(&self) -> f64 {
+        (self.accepted + self.rejected()) as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn add(&mut self, miner: MinerReport) {
+        if let Some(handshake) = miner.handshake {
+            self.connected += 1;
+            self.handshake_latencies.push(handshake);
+        }
+        if let Some(error) = miner.error {
+            *self.errors.entry(error).or_default() += 1;
+        }
+        self.submitted += miner.submitted;
+        self.accepted += miner.accepted;
+        for (reject, count) in miner.rejects {
+            *self.rejects.entry(reject).or_default() += count;
+        }
+        self.unanswered += miner.unanswered;
+        self.latencies.extend(miner.latencies);
+    }
+}
+
+/// Nearest-rank percentile of sorted durations.
+pub fn percentile(sorted: &[Duration], percent: u32) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (sorted.len() * percent.min(100) as usize).div_ceil(100);
+    Some(sorted[rank.max(1) - 1])
+}
+
+fn format_percentiles(f: &mut fmt::Formatter<'_>, sorted: &[Duration]) -> fmt::Result {
+    for (name, percent) in [("p50", 50), ("p90", 90), ("p99", 99), ("max", 100)] {
+        match percentile(sorted, percent) {
+            Some(latency) => write!(f, " {}: {:?}", name, latency)?,
+            None => write!(f, " {}: -", name)?,
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "miners: {} connected: {} failed: {}",
+            self.miners,
+            self.connected,
+            self.errors.values().sum::<usize>()
+        )?;
+        writeln!(
+            f,
+            "submitted: {} accepted: {} rejected: {} unanswered: {} in {:?}",
+            self.submitted,
+            self.accepted,
+            self.rejected(),
+            self.unanswered,
+            self.elapsed
+        )?;
+        writeln!(f, "throughput: {:.1} shares/s", self.throughput())?;
+        write!(f, "latency")?;
+        format_percentiles(f, &self.latencies)?;
+        write!(f, "\nhandshake")?;
+        format_percentiles(f, &self.handshake_latencies)?;
+        for (reject, count) in &self.rejects {
+            write!(f, "\nrejected {}: {}", reject, count)?;
+        }
+        for (error, count) in &self.errors {
+            write!(f, "\nfailed {}: {}", error, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `config.miners` simulated miners against the pool: each subscribes, authorizes,
+/// and submits random shares to the latest job every `share_interval` until `duration`
+/// is over. Fails without connecting if `config` does not pass `BenchConfig::validate`.
+pub async fn run(config: BenchConfig) -> anyhow::Result<BenchReport> {
+    config.validate()?;
+    let config = Arc::new(config);
+    let start = Instant::now();
+    let stop_at = start + config.ramp_up + config.duration;
+    let mut handles = Vec::with_capacity(config.miners);
+    for index in 0..config.miners {
+        let config = config.clone();
+        let start_at = start + config.ramp_up * index as u32 / config.miners.max(1) as u32;
+        handles.push(task::spawn(async move {
+            sleep_until(start_at).await;
+            let mut report = MinerReport::default();
+            if let Err(e) = run_miner(&config, index, stop_at, &mut report).await {
+                report.error = Some(e.to_string());
+            }
+            report
+        }));
+    }
+
+    let mut report = BenchReport {
+        miners: config.miners,
+        ..Default::default()
+    };
+    for handle in handles {
+        match handle.await {
+            Ok(miner) => report.add(miner),
+            Err(e) => *report.errors.entry(e.to_string()).or_default() += 1,
+        }
+    }
+    report.elapsed = start.elapsed();
+    report.latencies.sort();
+    report.handshake_latencies.sort();
+    Ok(report)
+}
+
+async fn run_miner(
+    config: &BenchConfig,
+    index: usize,
+    stop_at: Instant,
+    report: &mut MinerReport,
+) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let stream = TcpStream::connect(&config.pool).await?;
+    let mut framed = Framed::new(stream, StratumCodec::default());
+    let mut job_id = None;
+    framed
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            config.user_agent.clone(),
+            config.protocol_version.clone(),
+            None,
+        ))
+        .await?;
+    wait_response(&mut framed, &mut job_id).await?;
+    framed
+        .send(StratumMessage::Authorize(
+            Id::Num(1),
+            config.account_name.clone(),
+            format!("bench{}", index),
+            None,
+        ))
+        .await?;
+    wait_response(&mut framed, &mut job_id).await?;
+    report.handshake = Some(started.elapsed());
+
+    // spread the miners' shares over the interval
+    let offset = rand::thread_rng().gen_range(0..config.share_interval.as_micros().max(1) as u64);
+    let mut ticker = interval_at(
+        Instant::now() + Duration::from_micros(offset),
+        config.share_interval,
+    );
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut pending = HashMap::new();
+    let mut next_id = 2;
+    loop {
+        tokio::select! {
+            _ = sleep_until(stop_at) => break,
+            _ = ticker.tick(), if job_id.is_some() => {
+                let submit = random_share(next_id, job_id.clone().unwrap(), config.proof_length);
+                pending.insert(next_id, Instant::now());
+                next_id += 1;
+                report.submitted += 1;
+                framed.send(submit).await?;
+            }
+            res = framed.next() => match res {
+                Some(Ok(StratumMessage::Notify(id, ..))) => job_id = Some(id),
+                Some(Ok(StratumMessage::Response(Id::Num(id), result, error))) => {
+                    report.answer(&mut pending, id, result, error)
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(anyhow!("pool disconnected")),
+            }
+        }
+    }
+
+    let drain_until = Instant::now() + config.drain_timeout;
+    while !pending.is_empty() {
+        tokio::select! {
+            _ = sleep_until(drain_until) => break,
+            res = framed.next() => match res {
+                Some(Ok(StratumMessage::Response(Id::Num(id), result, error))) => {
+                    report.answer(&mut pending, id, result, error)
+                }
+                Some(Ok(_)) => {}
+                _ => break,
+            }
+        }
+    }
+    report.unanswered = pending.len() as u64;
+    Ok(())
+}
+
+async fn wait_response(
+    framed: &mut Framed<TcpStream, StratumCodec>,
+    job_id: &mut Option<String>,
+) -> anyhow::Result<()> {
+    loop {
+        match framed.next().await {
+            Some(Ok(StratumMessage::Response(_, _, Some(error)))) => {
+                return Err(anyhow!("pool rejected miner: {}", error.message));
+            }
+            Some(Ok(StratumMessage::Response(_, Some(ResponseMessage::Bool(false)), _))) => {
+                return Err(anyhow!("pool rejected miner"));
+            }
+            Some(Ok(StratumMessage::Response(..))) => return Ok(()),
+            Some(Ok(StratumMessage::Notify(id, ..))) => *job_id = Some(id),
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err(anyhow!("pool disconnected")),
+        }
+    }
+}
+
+fn random_share(id: u64, job_id: String, proof_length: usize) -> StratumMessage {
+    let mut rng = rand::thread_rng();
+    let nonce: Vec<u8> = (0..NONCE_LENGTH).map(|_| rng.gen()).collect();
+    let proof: Vec<u8> = (0..proof_length).map(|_| rng.gen()).collect();
+    StratumMessage::Submit(
+        Id::Num(id),
+        job_id,
+        hex::encode(nonce),
+        hex::encode(proof),
+        None,
+    )
+}
+
+#[test]
+fn test_percentile() {
+    let sorted: Vec<Duration> = (1..=10).map(Duration::from_millis).collect();
+    assert_eq!(percentile(&sorted, 50), Some(Duration::from_millis(5)));
+    assert_eq!(percentile(&sorted, 90), Some(Duration::from_millis(9)));
+    assert_eq!(percentile(&sorted, 99), Some(Duration::from_millis(10)));
+    assert_eq!(percentile(&sorted, 0), Some(Duration::from_millis(1)));
+    assert_eq!(percentile(&[], 50), None);
+}
+
+#[tokio::test]
+async fn test_bench() {
+    use crate::mock_pool::{MockPool, MockPoolConfig};
+    use tokio::net::TcpListener;
+
+    // every miner's first share is stale
+    let script = "
+        notify 100 18446744073709551615 clean
+        reject StaleProof
+    "
+    .parse()
+    .unwrap();
+    let pool = MockPool::new(MockPoolConfig::new(script));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    task::spawn(async move { pool.run(listener).await });
+
+    let mut config = BenchConfig::new(
+        addr.to_string(),
+        5,
+        Duration::from_millis(20),
+        Duration::from_millis(300),
+    );
+    config.ramp_up = Duration::from_millis(50);
+    config.proof_length = 64;
+    let report = run(config).await.unwrap();
+    assert_eq!(report.connected, 5);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.rejects["StaleProof"], 5);
+    assert!(report.accepted > 0);
+    assert_eq!(report.unanswered, 0);
+    assert_eq!(report.submitted, report.accepted + report.rejected());
+    assert_eq!(report.latencies.len() as u64, report.submitted);
+    assert!(report.throughput() > 0.0);
+    assert!(report.to_string().contains("rejected StaleProof: 5"));
+
+    // a pool that is not there fails every session
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let report = run(BenchConfig::new(
+        addr.to_string(),
+        2,
+        Duration::from_millis(20),
+        Duration::from_millis(10),
+    ))
+    .await
+    .unwrap();
+    assert_eq!(report.connected, 0);
+    assert_eq!(report.errors.values().sum::<usize>(), 2);
+
+    // a zero share interval is refused before any miner starts
+    let config = BenchConfig::new(
+        addr.to_string(),
+        2,
+        Duration::ZERO,
+        Duration::from_millis(10),
+    );
+    assert!(config.validate().is_err());
+    assert!(run(config).await.is_err());
+}
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+use tokio::net::TcpListener;
+use zkmatrix_pool_protocol::mock_pool::script::Script;
+use zkmatrix_pool_protocol::mock_pool::{MockPool, MockPoolConfig};
+
+const USAGE: &str = "usage: mock-pool <listen_addr> <script_file> [submissions_file]";
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.len() < 2 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let script = match std::fs::read_to_string(&args[1])
+        .map_err(anyhow::Error::from)
+        .and_then(|s| s.parse::<Script>())
+    {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("invalid script {}: {}", args[1], e);
+            std::process::exit(2);
+        }
+    };
+    let mut config = MockPoolConfig::new(script);
+    config.submissions_file = args.get(2).map(PathBuf::from);
+    let pool = MockPool::new(config);
+
+    let listener = match TcpListener::bind(&args[0]).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("bind {} failed: {}", args[0], e);
+            std::process::exit(1);
+        }
+    };
+    println!("mock-pool listening on {}, script {}", args[0], args[1]);
+
+    if let Err(e) = pool.run(listener).await {
+        eprintln!("mock-pool stopped: {}", e);
+        std::process::exit(1);
+    }
+}
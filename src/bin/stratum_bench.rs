@@ -0,0 +1,57 @@
+use std::time::Duration;
+use zkmatrix_pool_protocol::bench::{run, BenchConfig};
+
+const USAGE: &str =
+    "usage: stratum-bench <pool_addr> <miners> <share_interval_ms> <duration_secs> [proof_bytes]";
+
+fn parse<T: std::str::FromStr>(arg: &str, name: &str) -> T {
+    match arg.parse() {
+        Ok(value) => value,
+        Err(_) => {
+            eprintln!("invalid {}: {}\n{}", name, arg, USAGE);
+            std::process::exit(2);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.len() < 4 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let miners: usize = parse(&args[1], "miners");
+    let mut config = BenchConfig::new(
+        args[0].clone(),
+        miners,
+        Duration::from_millis(parse(&args[2], "share_interval_ms")),
+        Duration::from_secs(parse(&args[3], "duration_secs")),
+    );
+    if let Some(proof_length) = args.get(4) {
+        config.proof_length = parse(proof_length, "proof_bytes");
+    }
+    // connect about a thousand miners a second
+    config.ramp_up = Duration::from_millis(miners as u64);
+    if let Err(e) = config.validate() {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(2);
+    }
+    println!(
+        "stratum-bench: {} miners against {} for {:?}",
+        miners, args[0], config.duration
+    );
+
+    let report = match run(config).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    println!("{}", report);
+    if report.connected == 0 {
+        std::process::exit(1);
+    }
+}
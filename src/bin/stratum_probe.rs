@@ -0,0 +1,54 @@
+use std::time::Duration;
+use zkmatrix_pool_protocol::probe::{probe, ProbeConfig, ProbeReport};
+
+const USAGE: &str =
+    "usage: stratum-probe <pool_addr> <protocol_version> <account_name> <miner_name> [worker_password]";
+
+/// Jobs to wait for, 1 by default.
+const JOBS_ENV: &str = "STRATUM_PROBE_JOBS";
+/// Seconds each step may take, 10 by default.
+const TIMEOUT_ENV: &str = "STRATUM_PROBE_TIMEOUT_SECS";
+
+fn env_number(name: &str) -> Option<u64> {
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            eprintln!("invalid {}: {}", name, value);
+            std::process::exit(2);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.len() < 4 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let mut config = ProbeConfig::new(args[0].clone(), args[2].clone(), args[3].clone());
+    config.protocol_version = args[1].clone();
+    config.worker_password = args.get(4).cloned();
+    if let Some(jobs) = env_number(JOBS_ENV) {
+        config.jobs = jobs as usize;
+    }
+    if let Some(secs) = env_number(TIMEOUT_ENV) {
+        config.timeout = Duration::from_secs(secs);
+    }
+    println!(
+        "stratum-probe: {} with protocol version {}",
+        config.pool, config.protocol_version
+    );
+
+    let mut report = ProbeReport::default();
+    let result = probe(&config, &mut report).await;
+    if report.connected.is_some() {
+        println!("{}", report);
+    }
+    if let Err(e) = result {
+        eprintln!("stratum-probe: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
@@ -0,0 +1,80 @@
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task;
+use tokio::time::sleep;
+use zkmatrix_pool_protocol::message::token::TokenAuthority;
+use zkmatrix_pool_protocol::proxy::{Proxy, ProxyConfig};
+
+const USAGE: &str =
+    "usage: stratum-proxy <listen_addr> <upstream_addr> <account_name> <miner_name> [worker_password]";
+
+/// Hex HMAC key; when set, miners must authorize with a worker token issued with it.
+const TOKEN_KEY_ENV: &str = "STRATUM_PROXY_TOKEN_KEY";
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.len() < 4 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let mut config = ProxyConfig::new(args[1].clone(), args[2].clone(), args[3].clone());
+    config.worker_password = args.get(4).cloned();
+    if let Ok(key) = std::env::var(TOKEN_KEY_ENV) {
+        match TokenAuthority::from_key_hex(&key) {
+            Ok(authority) => config.worker_tokens = Some(authority),
+            Err(e) => {
+                eprintln!("invalid {}: {}", TOKEN_KEY_ENV, e);
+                std::process::exit(2);
+            }
+        }
+    }
+    let proxy = Proxy::new(config);
+
+    let listener = match TcpListener::bind(&args[0]).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("bind {} failed: {}", args[0], e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "stratum-proxy listening on {}, upstream {}",
+        args[0], args[1]
+    );
+
+    let reporter = proxy.clone();
+    task::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(30)).await;
+            for (addr, stats) in reporter.stats() {
+                let mut workers: Vec<&str> = stats
+                    .workers
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect();
+                workers.sort_unstable();
+                let worker = if workers.is_empty() {
+                    "-".to_string()
+                } else {
+                    workers.join(",")
+                };
+                println!(
+                    "{} {} submitted: {} accepted: {} rejected: {} pending: {}",
+                    addr,
+                    worker,
+                    stats.submitted,
+                    stats.accepted,
+                    stats.rejected,
+                    stats.pending(),
+                );
+            }
+        }
+    });
+
+    if let Err(e) = proxy.run(listener).await {
+        eprintln!("stratum-proxy stopped: {}", e);
+        std::process::exit(1);
+    }
+}
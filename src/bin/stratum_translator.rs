@@ -0,0 +1,31 @@
+use tokio::net::TcpListener;
+use zkmatrix_pool_protocol::translator::{Translator, TranslatorConfig};
+
+const USAGE: &str = "usage: stratum-translator <listen_addr> <upstream_v2_addr>";
+
+#[tokio::main]
+async fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.len() < 2 {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    }
+
+    let translator = Translator::new(TranslatorConfig::new(args[1].clone()));
+    let listener = match TcpListener::bind(&args[0]).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("bind {} failed: {}", args[0], e);
+            std::process::exit(1);
+        }
+    };
+    println!(
+        "stratum-translator listening on {}, upstream {}",
+        args[0], args[1]
+    );
+
+    if let Err(e) = translator.run(listener).await {
+        eprintln!("stratum-translator stopped: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,1027 @@
+use crate::message::error::PoolError;
+use crate::message::extranonce::{advertise_extranonce, extranonce_from_result, Extranonce};
+use crate::message::puzzle::advertise_testnet3;
+use crate::message::response::ResponseMessage;
+use crate::message::signing::{advertise_signing, signing_accepted, NotifyVerifier};
+use crate::message::stratum::{StratumCodec, StratumMessage};
+use crate::CURRENT_PROTOCOL_VERSION;
+use futures_util::{SinkExt, StreamExt};
+use json_rpc_types::Id;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio::time::{interval_at, sleep, timeout, Instant, MissedTickBehavior};
+use tokio_util::codec::Framed;
+
+#[derive(Clone, Debug)]
+pub struct PoolEndpoint {
+    pub address: String,
+    /// Lower values are tried first; the lowest is the primary pool.
+    pub priority: u32,
+    pub account_name: String,
+    pub miner_name: String,
+    pub worker_password: Option<String>,
+    /// Hex ed25519 key the pool signs its jobs with. When set, signed jobs are requested
+    /// and any `Notify` or `PuzzleNotify` that fails verification ends the session.
+    pub notify_public_key: Option<String>,
+}
+
+impl PoolEndpoint {
+    pub fn new(address: String, priority: u32, account_name: String, miner_name: String) -> Self {
+        Self {
+            address,
+            priority,
+            account_name,
+            miner_name,
+            worker_password: None,
+            notify_public_key: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    pub endpoints: Vec<PoolEndpoint>,
+    pub user_agent: String,
+    pub protocol_version: String,
+    pub connect_timeout: Duration,
+    /// Fail over when no `Notify` arrives for this long.
+    pub notify_timeout: Duration,
+    /// Fail over after this many consecutive `ServerNotReady` errors.
+    pub max_server_not_ready: u32,
+    /// How often higher priority pools are probed while on a backup.
+    pub failback_interval: Duration,
+    /// Delay before retrying once every endpoint has failed.
+    pub retry_delay: Duration,
+    /// Offer the testnet3 coinbase puzzle. Pools that accept send `PuzzleNotify` jobs,
+    /// answered with `ClientHandle::submit_solution`.
+    pub testnet3: bool,
+}
+
+impl ClientConfig {
+    pub fn new(endpoints: Vec<PoolEndpoint>) -> Self {
+        Self {
+            endpoints,
+            user_agent: "ABMatrix_Aleo_Miner".to_string(),
+            protocol_version: CURRENT_PROTOCOL_VERSION.to_string(),
+            connect_timeout: Duration::from_secs(10),
+            notify_timeout: Duration::from_secs(120),
+            max_server_not_ready: 3,
+            failback_interval: Duration::from_secs(60),
+            retry_delay: Duration::from_secs(5),
+            testnet3: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FailoverReason {
+    ConnectFailed(String),
+    /// Subscribe or authorize was refused by the pool.
+    Rejected(String),
+    NotifyTimeout,
+    /// A job was unsigned or not signed with the endpoint's `notify_public_key`.
+    InvalidJobSignature,
+    ServerNotReady,
+    Disconnected,
+}
+
+pub enum ClientEvent {
+    Connecting(String),
+    /// Subscribed and authorized on the endpoint.
+    Connected(String),
+    /// (from, to, reason)
+    Failover(String, String, FailoverReason),
+    /// A higher priority pool answered a probe and the client moved back to it.
+    /// (from, to)
+    Failback(String, String),
+    /// The nonce range the current pool assigned, sent after `Connected` and again when
+    /// the pool moves the session. Nonces should come from `Extranonce::nonce`. Pools that
+    /// do not partition the nonce space send none.
+    Extranonce(Extranonce),
+    /// The current pool moved to a new puzzle epoch, work on earlier epochs is stale.
+    EpochChanged(u32),
+    /// A `Notify`, or a `PuzzleNotify` on testnet3 sessions, from the current pool.
+    Job(StratumMessage),
+    /// (id returned by `ClientHandle::submit` or `submit_solution`, result)
+    ///
+    /// Every share gets one. Shares for jobs the current pool did not send, and shares the
+    /// pool had not answered when the client left it, fail with `PoolError::StaleProof`.
+    ShareResult(u64, Result<(), PoolError>),
+}
+
+/// A share queued by `ClientHandle`.
+enum QueuedShare {
+    /// (job_id, nonce, proof)
+    Proof(String, String, String),
+    /// (job_id, prover_solution)
+    Solution(String, String),
+}
+
+impl QueuedShare {
+    fn job_id(&self) -> &str {
+        match self {
+            QueuedShare::Proof(job_id, ..) | QueuedShare::Solution(job_id, _) => job_id,
+        }
+    }
+
+    fn into_message(self, id: Id) -> StratumMessage {
+        match self {
+            QueuedShare::Proof(job_id, nonce, proof) => {
+                StratumMessage::Submit(id, job_id, nonce, proof, None)
+            }
+            QueuedShare::Solution(job_id, prover_solution) => {
+                StratumMessage::SubmitSolution(id, job_id, prover_solution, None)
+            }
+        }
+    }
+}
+
+/// Submits shares to whichever pool the client is currently connected to.
+#[derive(Clone)]
+pub struct ClientHandle {
+    tx: mpsc::UnboundedSender<(u64, QueuedShare)>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ClientHandle {
+    /// Queues a share and returns the id its `ClientEvent::ShareResult` will carry.
+    pub fn submit(&self, job_id: String, nonce: String, proof: String) -> u64 {
+        self.queue(QueuedShare::Proof(job_id, nonce, proof))
+    }
+
+    /// Queues the hex prover solution to a `PuzzleNotify`, see `message::puzzle`, and
+    /// returns the id its `ClientEvent::ShareResult` will carry.
+    pub fn submit_solution(&self, job_id: String, prover_solution: String) -> u64 {
+        self.queue(QueuedShare::Solution(job_id, prover_solution))
+    }
+
+    fn queue(&self, share: QueuedShare) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let _ = self.tx.send((id, share));
+        id
+    }
+}
+
+enum SessionEnd {
+    Failover(FailoverReason),
+    Failback(usize),
+}
+
+type Session = Framed<TcpStream, StratumCodec>;
+
+/// Stratum client that keeps mining across an ordered list of pools.
+pub struct Client {
+    config: ClientConfig,
+    events: mpsc::UnboundedSender<ClientEvent>,
+    submits: mpsc::UnboundedReceiver<(u64, QueuedShare)>,
+}
+
+impl Client {
+    pub fn new(
+        mut config: ClientConfig,
+    ) -> (Self, ClientHandle, mpsc::UnboundedReceiver<ClientEvent>) {
+        config.endpoints.sort_by_key(|e| e.priority);
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let (tx, submits) = mpsc::unbounded_channel();
+        let handle = ClientHandle {
+            tx,
+            next_id: Default::default(),
+        };
+        (
+            Self {
+                config,
+                events,
+                submits,
+            },
+            handle,
+            events_rx,
+        )
+    }
+
+    /// Runs until the event receiver is dropped.
+    pub async fn run(mut self) {
+        if self.config.endpoints.is_empty() {
+            return;
+        }
+        let mut current = 0;
+        let mut pending = vec![];
+        loop {
+            let address = self.config.endpoints[current].address.clone();
+            if self
+                .events
+                .send(ClientEvent::Connecting(address.clone()))
+                .is_err()
+            {
+                return;
+            }
+            let end = self.session(current, &mut pending).await;
+            // the next pool can't answer shares sent to, or queued for jobs of, this one
+            for (_, share_id) in pending.drain(..) {
+                self.stale(share_id);
+            }
+            while let Ok((share_id, _)) = self.submits.try_recv() {
+                self.stale(share_id);
+            }
+            let next = match end {
+                SessionEnd::Failover(reason) => {
+                    let next = (current + 1) % self.config.endpoints.len();
+                    let to = self.config.endpoints[next].address.clone();
+                    let _ = self.events.send(ClientEvent::Failover(address, to, reason));
+                    if next == 0 {
+                        sleep(self.config.retry_delay).await;
+                    }
+                    next
+                }
+                SessionEnd::Failback(next) => {
+                    let to = self.config.endpoints[next].address.clone();
+                    let _ = self.events.send(ClientEvent::Failback(address, to));
+                    next
+                }
+            };
+            if self.events.is_closed() {
+                return;
+            }
+            current = next;
+        }
+    }
+
+    fn stale(&self, share_id: u64) {
+        let _ = self.events.send(ClientEvent::ShareResult(
+            share_id,
+            Err(PoolError::StaleProof),
+        ));
+    }
+
+    /// Mines on one endpoint. `pending` holds the (request id, share id) of shares sent and
+    /// not answered yet.
+    async fn session(&mut self, current: usize, pending: &mut Vec<(u64, u64)>) -> SessionEnd {
+        let endpoint = self.config.endpoints[current].clone();
+        let mut framed = match self.connect(&endpoint.address).await {
+            Ok(framed) => framed,
+            Err(reason) => return SessionEnd::Failover(reason),
+        };
+        let (verifier, extranonce) = match self.handshake(&mut framed, &endpoint).await {
+            Ok(handshake) => handshake,
+            Err(reason) => return SessionEnd::Failover(reason),
+        };
+        let _ = self
+            .events
+            .send(ClientEvent::Connected(endpoint.address.clone()));
+        if let Some(extranonce) = extranonce {
+            let _ = self.events.send(ClientEvent::Extranonce(extranonce));
+        }
+
+        let mut notify_deadline = Instant::now() + self.config.notify_timeout;
+        let mut server_not_ready = 0;
+        let mut jobs = HashSet::new();
+        let mut next_request_id = 2;
+
+        let (probe_tx, mut probe_rx) = mpsc::unbounded_channel();
+        let start = Instant::now() + self.config.failback_interval;
+        let mut failback = interval_at(start, self.config.failback_interval);
+        failback.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(notify_deadline) => {
+                    return SessionEnd::Failover(FailoverReason::NotifyTimeout);
+                }
+                _ = failback.tick(), if current > 0 => {
+                    let config = self.config.clone();
+                    let probe_tx = probe_tx.clone();
+                    task::spawn(async move {
+                        for (i, endpoint) in config.endpoints[..current].iter().enumerate() {
+                            if probe(&config, &endpoint.address).await {
+                                let _ = probe_tx.send(i);
+                                break;
+                            }
+                        }
+                    });
+                }
+                Some(better) = probe_rx.recv() => {
+                    return SessionEnd::Failback(better);
+                }
+                Some((share_id, share)) = self.submits.recv() => {
+                    if !jobs.contains(share.job_id()) {
+                        self.stale(share_id);
+                        continue;
+                    }
+                    let request_id = next_request_id;
+                    next_request_id += 1;
+                    pending.push((request_id, share_id));
+                    if framed.send(share.into_message(Id::Num(request_id))).await.is_err() {
+                        return SessionEnd::Failover(FailoverReason::Disconnected);
+                    }
+                }
+                res = framed.next() => {
+                    match res {
+                        Some(Ok(msg @ (StratumMessage::Notify(..) | StratumMessage::PuzzleNotify(..)))) => {
+                            if let Some(verifier) = &verifier {
+                                if verifier.verify(&msg).is_err() {
+                                    return SessionEnd::Failover(FailoverReason::InvalidJobSignature);
+                                }
+                            }
+                            notify_deadline = Instant::now() + self.config.notify_timeout;
+                            if let StratumMessage::Notify(job_id, ..)
+                            | StratumMessage::PuzzleNotify(job_id, ..) = &msg
+                            {
+                                jobs.insert(job_id.clone());
+                            }
+                            let _ = self.events.send(ClientEvent::Job(msg));
+                        }
+                        Some(Ok(msg @ StratumMessage::SetExtranonce(..))) => {
+                            match Extranonce::from_message(&msg) {
+                                Some(Ok(extranonce)) => {
+                                    let _ = self.events.send(ClientEvent::Extranonce(extranonce));
+                                }
+                                _ => {
+                                    return SessionEnd::Failover(FailoverReason::Rejected(
+                                        "invalid extranonce".to_string(),
+                                    ));
+                                }
+                            }
+                        }
+                        Some(Ok(StratumMessage::SetEpoch(epoch_number))) => {
+                            let _ = self.events.send(ClientEvent::EpochChanged(epoch_number));
+                        }
+                        Some(Ok(StratumMessage::Response(Id::Num(id), result, error))) => {
+                            let share_id = match pending.iter().position(|(r, _)| *r == id) {
+                                Some(i) => pending.remove(i).1,
+                                None => continue,
+                            };
+                            let result = share_result(&result, error);
+                            if result == Err(PoolError::ServerNotReady) {
+                                server_not_ready += 1;
+                            } else {
+                                server_not_ready = 0;
+                            }
+                            let _ = self.events.send(ClientEvent::ShareResult(share_id, result));
+                            if server_not_ready >= self.config.max_server_not_ready {
+                                return SessionEnd::Failover(FailoverReason::ServerNotReady);
+                            }
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(_)) | None => {
+                            return SessionEnd::Failover(FailoverReason::Disconnected);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn connect(&self, address: &str) -> Result<Session, FailoverReason> {
+        match timeout(self.config.connect_timeout, TcpStream::connect(address)).await {
+            Ok(Ok(stream)) => Ok(Framed::new(stream, StratumCodec::default())),
+            Ok(Err(e)) => Err(FailoverReason::ConnectFailed(e.to_string())),
+            Err(_) => Err(FailoverReason::ConnectFailed("timeout".to_string())),
+        }
+    }
+
+    /// Subscribes and authorizes, returning the job verifier if the endpoint has a key and
+    /// the nonce range if the pool assigned one.
+    async fn handshake(
+        &self,
+        framed: &mut Session,
+        endpoint: &PoolEndpoint,
+    ) -> Result<(Option<NotifyVerifier>, Option<Extranonce>), FailoverReason> {
+        let verifier = match &endpoint.notify_public_key {
+            Some(key) => Some(
+                NotifyVerifier::from_public_key_hex(key)
+                    .map_err(|e| FailoverReason::Rejected(e.to_string()))?,
+            ),
+            None => None,
+        };
+        let mut protocol_version = advertise_extranonce(&self.config.protocol_version);
+        if self.config.testnet3 {
+            protocol_version = advertise_testnet3(&protocol_version);
+        }
+        let protocol_version = match verifier {
+            Some(_) => advertise_signing(&protocol_version),
+            None => protocol_version,
+        };
+        let subscribe = StratumMessage::Subscribe(
+            Id::Num(0),
+            self.config.user_agent.clone(),
+            protocol_version,
+            None,
+        );
+        let result = request(framed, subscribe, self.config.connect_timeout).await?;
+        if verifier.is_some() && !signing_accepted(&result) {
+            return Err(FailoverReason::Rejected(
+                "pool does not sign jobs".to_string(),
+            ));
+        }
+        let authorize = StratumMessage::Authorize(
+            Id::Num(1),
+            endpoint.account_name.clone(),
+            endpoint.miner_name.clone(),
+            endpoint.worker_password.clone(),
+        );
+        request(framed, authorize, self.config.connect_timeout).await?;
+        Ok((verifier, extranonce_from_result(&result)))
+    }
+}
+
+/// Sends a handshake request and waits for its response, returning the result.
+async fn request(
+    framed: &mut Session,
+    msg: StratumMessage,
+    wait: Duration,
+) -> Result<Option<ResponseMessage>, FailoverReason> {
+    if framed.send(msg).await.is_err() {
+        return Err(FailoverReason::Disconnected);
+    }
+    let response = timeout(wait, async {
+        loop {
+            match framed.next().await {
+                Some(Ok(StratumMessage::Response(_, result, error))) => {
+                    return Ok(share_result(&result, error).map(|_| result));
+                }
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => return Err(FailoverReason::Disconnected),
+            }
+        }
+    })
+    .await
+    .map_err(|_| FailoverReason::Disconnected)??;
+    match response {
+        Ok(result) => Ok(result),
+        Err(PoolError::ServerNotReady) => Err(FailoverReason::ServerNotReady),
+        Err(e) => Err(FailoverReason::Rejected(e.to_string())),
+    }
+}
+
+/// Checks whether a pool accepts a subscribe, without staying connected.
+async fn probe(config: &ClientConfig, address: &str) -> bool {
+    let stream = match timeout(config.connect_timeout, TcpStream::connect(address)).await {
+        Ok(Ok(stream)) => stream,
+        _ => return false,
+    };
+    let mut framed = Framed::new(stream, StratumCodec::default());
+    let subscribe = StratumMessage::Subscribe(
+        Id::Num(0),
+        config.user_agent.clone(),
+        config.protocol_version.clone(),
+        None,
+    );
+    request(&mut framed, subscribe, config.connect_timeout)
+        .await
+        .is_ok()
+}
+
+fn share_result(
+    result: &Option<ResponseMessage>,
+    error: Option<json_rpc_types::Error<()>>,
+) -> Result<(), PoolError> {
+    match error {
+        Some(error) => {
+            Err(PoolError::from_str(&error.message).unwrap_or(PoolError::InternalServerError))
+        }
+        None => match result {
+            Some(ResponseMessage::Bool(false)) => Err(PoolError::InvalidProof(None)),
+            _ => Ok(()),
+        },
+    }
+}
+
+#[cfg(test)]
+async fn mock_pool(
+    listener: tokio::net::TcpListener,
+    notify: bool,
+    submit_error: Option<PoolError>,
+    signer: Option<Arc<crate::message::signing::NotifySigner>>,
+) {
+    use crate::message::signing::{supports_signing, SIGNING_PROTOCOL_EXTENSION};
+    use crate::utils::extension::accept_extensions;
+    use json_rpc_types::{Error, ErrorCode};
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(_) => return,
+        };
+        let submit_error = submit_error.clone();
+        let signer = signer.clone();
+        task::spawn(async move {
+            let mut framed = Framed::new(stream, StratumCodec::default());
+            while let Some(Ok(msg)) = framed.next().await {
+                let response = match msg {
+                    StratumMessage::Subscribe(id, _, version, _) => {
+                        let result = match &signer {
+                            Some(_) if supports_signing(&version) => {
+                                Some(accept_extensions(&[SIGNING_PROTOCOL_EXTENSION]))
+                            }
+                            _ => None,
+                        };
+                        StratumMessage::Response(id, result, None)
+                    }
+                    StratumMessage::Authorize(id, ..) => {
+                        let _ = framed
+                            .send(StratumMessage::Response(
+                                id,
+                                Some(ResponseMessage::Bool(true)),
+                                None,
+                            ))
+                            .await;
+                        if !notify {
+                            continue;
+                        }
+                        let notify = StratumMessage::Notify(
+                            "job_id".to_string(),
+                            u64::MAX,
+                            "block_header_root".to_string(),
+                            "hashed_leaves_1".to_string(),
+                            "hashed_leaves_2".to_string(),
+                            "hashed_leaves_3".to_string(),
+                            "hashed_leaves_4".to_string(),
+                            true,
+                            None,
+                        );
+                        match &signer {
+                            Some(signer) => signer.sign(notify),
+                            None => notify,
+                        }
+                    }
+                    StratumMessage::Submit(id, ..) => match &submit_error {
+                        Some(e) => StratumMessage::Response(
+                            id,
+                            None,
+                            Some(Error::with_custom_msg(
+                                ErrorCode::ServerError(e.id()),
+                                &e.to_string(),
+                            )),
+                        ),
+                        None => {
+                            StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)
+                        }
+                    },
+                    _ => continue,
+                };
+                if framed.send(response).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_failover_and_failback() {
+    use tokio::net::TcpListener;
+
+    // reserve an address for the primary pool, it comes up later
+    let primary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let primary_addr = primary.local_addr().unwrap().to_string();
+    drop(primary);
+    let backup = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let backup_addr = backup.local_addr().unwrap().to_string();
+    task::spawn(mock_pool(backup, true, None, None));
+
+    let mut config = ClientConfig::new(vec![
+        PoolEndpoint::new(
+            backup_addr.clone(),
+            10,
+            "account".to_string(),
+            "miner".to_string(),
+        ),
+        PoolEndpoint::new(
+            primary_addr.clone(),
+            0,
+            "account".to_string(),
+            "miner".to_string(),
+        ),
+    ]);
+    config.failback_interval = Duration::from_millis(100);
+    let (client, handle, mut events) = Client::new(config);
+    task::spawn(client.run());
+
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connecting(a)) if a == primary_addr));
+    match events.recv().await {
+        Some(ClientEvent::Failover(from, to, FailoverReason::ConnectFailed(_))) => {
+            assert_eq!((from, to), (primary_addr.clone(), backup_addr.clone()));
+        }
+        _ => panic!("expected failover"),
+    }
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connecting(a)) if a == backup_addr));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connected(a)) if a == backup_addr));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Job(_))));
+
+    let id = handle.submit(
+        "job_id".to_string(),
+        "nonce".to_string(),
+        "proof".to_string(),
+    );
+    assert!(matches!(events.recv().await, Some(ClientEvent::ShareResult(i, Ok(()))) if i == id));
+
+    let primary = TcpListener::bind(&primary_addr).await.unwrap();
+    task::spawn(mock_pool(primary, true, None, None));
+    match events.recv().await {
+        Some(ClientEvent::Failback(from, to)) => {
+            assert_eq!((from, to), (backup_addr, primary_addr.clone()));
+        }
+        _ => panic!("expected failback"),
+    }
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connecting(a)) if a == primary_addr));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connected(a)) if a == primary_addr));
+}
+
+#[tokio::test]
+async fn test_failover_on_pool_failures() {
+    use tokio::net::TcpListener;
+
+    let silent = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let silent_addr = silent.local_addr().unwrap().to_string();
+    task::spawn(mock_pool(silent, false, None, None));
+    let not_ready = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let not_ready_addr = not_ready.local_addr().unwrap().to_string();
+    task::spawn(mock_pool(
+        not_ready,
+        true,
+        Some(PoolError::ServerNotReady),
+        None,
+    ));
+
+    let mut config = ClientConfig::new(vec![
+        PoolEndpoint::new(
+            silent_addr.clone(),
+            0,
+            "account".to_string(),
+            "miner".to_string(),
+        ),
+        PoolEndpoint::new(
+            not_ready_addr.clone(),
+            1,
+            "account".to_string(),
+            "miner".to_string(),
+        ),
+    ]);
+    config.notify_timeout = Duration::from_millis(200);
+    config.max_server_not_ready = 2;
+    config.retry_delay = Duration::from_millis(10);
+    let (client, handle, mut events) = Client::new(config);
+    task::spawn(client.run());
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connected(a)) if a == silent_addr));
+    // this pool sent no job, so the share can't be for it
+    let id = handle.submit(
+        "job_id".to_string(),
+        "nonce".to_string(),
+        "proof".to_string(),
+    );
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::ShareResult(i, Err(PoolError::StaleProof))) if i == id
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Failover(_, _, FailoverReason::NotifyTimeout))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connected(a)) if a == not_ready_addr));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Job(_))));
+
+    for _ in 0..2 {
+        handle.submit(
+            "job_id".to_string(),
+            "nonce".to_string(),
+            "proof".to_string(),
+        );
+        assert!(matches!(
+            events.recv().await,
+            Some(ClientEvent::ShareResult(_, Err(PoolError::ServerNotReady)))
+        ));
+    }
+    match events.recv().await {
+        Some(ClientEvent::Failover(from, to, FailoverReason::ServerNotReady)) => {
+            assert_eq!((from, to), (not_ready_addr, silent_addr));
+        }
+        _ => panic!("expected failover"),
+    }
+}
+
+#[tokio::test]
+async fn test_unanswered_shares_on_failover() {
+    use tokio::net::TcpListener;
+
+    // a pool that drops the connection instead of answering a share
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            match msg {
+                StratumMessage::Subscribe(id, ..) => {
+                    framed
+                        .send(StratumMessage::Response(id, None, None))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Authorize(id, ..) => {
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    framed
+                        .send(StratumMessage::Notify(
+                            "job_id".to_string(),
+                            u64::MAX,
+                            "block_header_root".to_string(),
+                            "hashed_leaves_1".to_string(),
+                            "hashed_leaves_2".to_string(),
+                            "hashed_leaves_3".to_string(),
+                            "hashed_leaves_4".to_string(),
+                            true,
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                }
+                _ => return,
+            }
+        }
+    });
+
+    let endpoint = PoolEndpoint::new(addr, 0, "account".to_string(), "miner".to_string());
+    let (client, handle, mut events) = Client::new(ClientConfig::new(vec![endpoint]));
+    task::spawn(client.run());
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connected(_))
+    ));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Job(_))));
+    let id = handle.submit(
+        "job_id".to_string(),
+        "nonce".to_string(),
+        "proof".to_string(),
+    );
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::ShareResult(i, Err(PoolError::StaleProof))) if i == id
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Failover(_, _, FailoverReason::Disconnected))
+    ));
+}
+
+#[tokio::test]
+async fn test_signed_jobs() {
+    use crate::message::signing::NotifySigner;
+    use tokio::net::TcpListener;
+
+    let signer = Arc::new(NotifySigner::generate());
+    let mut addrs = vec![];
+    for pool_signer in [
+        None,
+        Some(Arc::new(NotifySigner::generate())),
+        Some(signer.clone()),
+    ] {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        addrs.push(listener.local_addr().unwrap().to_string());
+        task::spawn(mock_pool(listener, true, None, pool_signer));
+    }
+
+    let endpoints = addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| {
+            let mut endpoint = PoolEndpoint::new(
+                addr.clone(),
+                i as u32,
+                "account".to_string(),
+                "miner".to_string(),
+            );
+            endpoint.notify_public_key = Some(signer.public_key_hex());
+            endpoint
+        })
+        .collect();
+    let (client, _handle, mut events) = Client::new(ClientConfig::new(endpoints));
+    task::spawn(client.run());
+
+    // a pool that does not sign is refused during the handshake
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Failover(_, _, FailoverReason::Rejected(_)))
+    ));
+
+    // a pool signing with another key is left on its first job
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connected(a)) if a == addrs[1]));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Failover(_, _, FailoverReason::InvalidJobSignature))
+    ));
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(events.recv().await, Some(ClientEvent::Connected(a)) if a == addrs[2]));
+    match events.recv().await {
+        Some(ClientEvent::Job(StratumMessage::Notify(.., Some(_)))) => {}
+        _ => panic!("expected a signed job"),
+    }
+}
+
+#[tokio::test]
+async fn test_client_extranonce() {
+    use crate::message::extranonce::{extranonce_result, supports_extranonce};
+    use tokio::net::TcpListener;
+
+    let assigned = Extranonce::new(vec![0x42], None).unwrap();
+    let moved = Extranonce::new(vec![0x43, 0x01], Some(4)).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let (pool_assigned, pool_moved) = (assigned.clone(), moved.clone());
+    task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            match msg {
+                StratumMessage::Subscribe(id, _, version, _) => {
+                    assert!(supports_extranonce(&version));
+                    let result = Some(extranonce_result(&[], &pool_assigned));
+                    framed
+                        .send(StratumMessage::Response(id, result, None))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Authorize(id, ..) => {
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    framed.send(pool_moved.to_message()).await.unwrap();
+                }
+                _ => {}
+            }
+        }
+    });
+
+    let endpoints = vec![PoolEndpoint::new(
+        addr,
+        0,
+        "account".to_string(),
+        "miner".to_string(),
+    )];
+    let (client, _handle, mut events) = Client::new(ClientConfig::new(endpoints));
+    task::spawn(client.run());
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connected(_))
+    ));
+    match events.recv().await {
+        Some(ClientEvent::Extranonce(extranonce)) => {
+            assert_eq!(extranonce, assigned);
+            assert!(extranonce.nonce(7).starts_with("42"));
+        }
+        _ => panic!("expected the assigned extranonce"),
+    }
+    match events.recv().await {
+        Some(ClientEvent::Extranonce(extranonce)) => assert_eq!(extranonce, moved),
+        _ => panic!("expected the moved extranonce"),
+    }
+}
+
+#[tokio::test]
+async fn test_client_testnet3() {
+    use crate::message::puzzle::{supports_testnet3, PuzzleJob, TESTNET3_PROTOCOL_EXTENSION};
+    use crate::message::signing::{NotifySigner, SIGNING_PROTOCOL_EXTENSION};
+    use crate::utils::extension::accept_extensions;
+    use tokio::net::TcpListener;
+
+    let job = PuzzleJob {
+        job_id: "job".to_string(),
+        epoch_number: 3,
+        epoch_challenge: vec![5; 32],
+        address: "aleo1pool".to_string(),
+        proof_target: 1 << 20,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let signer = NotifySigner::generate();
+    let public_key = signer.public_key_hex();
+    let pool_job = job.clone();
+    task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            match msg {
+                StratumMessage::Subscribe(id, _, version, _) => {
+                    assert!(supports_testnet3(&version));
+                    let result = accept_extensions(&[
+                        SIGNING_PROTOCOL_EXTENSION,
+                        TESTNET3_PROTOCOL_EXTENSION,
+                    ]);
+                    framed
+                        .send(StratumMessage::Response(id, Some(result), None))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Authorize(id, ..) => {
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    framed
+                        .send(signer.sign(pool_job.to_message(true)))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::SubmitSolution(id, job_id, ..) => {
+                    assert_eq!(job_id, "job");
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    // an unsigned job, e.g. injected by a hijacked proxy
+                    framed.send(pool_job.to_message(false)).await.unwrap();
+                }
+                _ => panic!("expected a testnet3 session"),
+            }
+        }
+    });
+
+    // puzzle jobs are verified like block header jobs
+    let mut endpoint = PoolEndpoint::new(addr, 0, "account".to_string(), "miner".to_string());
+    endpoint.notify_public_key = Some(public_key);
+    let mut config = ClientConfig::new(vec![endpoint]);
+    config.testnet3 = true;
+    let (client, handle, mut events) = Client::new(config);
+    task::spawn(client.run());
+
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connecting(_))
+    ));
+    assert!(matches!(
+        events.recv().await,
+        Some(ClientEvent::Connected(_))
+    ));
+    match events.recv().await {
+        Some(ClientEvent::Job(msg)) => assert_eq!(PuzzleJob::from_message(&msg).unwrap(), job),
+        _ => panic!("expected a puzzle job"),
+    }
+
+    let id = handle.submit_solution("job".to_string(), "0102".to_string());
+    match events.recv().await {
+        Some(ClientEvent::ShareResult(share_id, result)) => {
+            assert_eq!((share_id, result), (id, Ok(())));
+        }
+        _ => panic!("expected a share result"),
+    }
+    match events.recv().await {
+        Some(ClientEvent::Failover(_, _, reason)) => {
+            assert_eq!(reason, FailoverReason::InvalidJobSignature)
+        }
+        _ => panic!("expected a failover on the unsigned job"),
+    }
+}
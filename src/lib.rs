@@ -4,8 +4,23 @@ extern crate lazy_static;
 
 use semver::Version;
 
+pub mod accounting;
+#[cfg(feature = "tools")]
+pub mod bench;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod message;
+#[cfg(feature = "tools")]
+pub mod mock_pool;
+#[cfg(feature = "tools")]
+pub mod probe;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+#[cfg(feature = "proxy")]
+pub mod translator;
+pub mod transport;
 pub mod utils;
+pub mod verifier;
 
 pub static PROTOCOL_PREFIX: &str = "ABMatrix";
 pub static MIN_SUPPORTED_PROTOCOL_VERSION: Version = Version::new(0, 2, 0);
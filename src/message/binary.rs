@@ -0,0 +1,650 @@
+use super::response::ResponseMessage;
+use super::stratum::{StratumCodec, StratumMessage};
+use crate::utils::extension::{accepted_extensions, add_extension, has_extension};
+use bytes::{Buf, BufMut, BytesMut};
+use json_rpc_types::{Error, Id, Response, Version};
+use serde_json::{json, Value};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+/// Semver build metadata a miner appends to its `mining.subscribe` protocol version to
+/// offer the binary framing, e.g. `0.2.0+binary`.
+pub static BINARY_PROTOCOL_EXTENSION: &str = "binary";
+
+/// Largest frame accepted by `BinaryStratumCodec`, excluding the length prefix.
+pub const MAX_BINARY_FRAME_LENGTH: usize = 1 << 20;
+
+const KIND_SUBSCRIBE: u8 = 1;
+const KIND_AUTHORIZE: u8 = 2;
+const KIND_SET_TARGET: u8 = 3;
+const KIND_NOTIFY: u8 = 4;
+const KIND_SUBMIT: u8 = 5;
+const KIND_RESPONSE: u8 = 6;
+const KIND_SET_EXTRANONCE: u8 = 7;
+const KIND_PUZZLE_NOTIFY: u8 = 8;
+const KIND_SUBMIT_SOLUTION: u8 = 9;
+const KIND_SET_EPOCH: u8 = 10;
+
+const HEX_RAW: u8 = 0;
+const HEX_BYTES: u8 = 1;
+const HEX_32: u8 = 2;
+
+/// Compact alternative to `StratumCodec` for the same `StratumMessage` set.
+///
+/// Every frame is `u32` length (big endian) | `u8` message kind | body. Hex encoded fields
+/// (job id, block header root, hashed leaves, nonce, proof) travel as raw bytes, 32 byte
+/// values without a length, and the difficulty target as a fixed `u64`. Strings that are
+/// not lowercase hex are sent verbatim, so every message decodes to exactly the values
+/// `StratumCodec` would produce.
+///
+/// The binary framing is negotiated: the miner subscribes over JSON with a protocol version
+/// carrying `BINARY_PROTOCOL_EXTENSION`, and a pool that accepts lists it in its subscribe
+/// result (see `utils::extension::accept_extensions`). Both sides then switch with
+/// `switch_to_binary`.
+#[derive(Default)]
+pub struct BinaryStratumCodec;
+
+/// Adds the binary extension to a protocol version, e.g. `0.2.0` -> `0.2.0+binary`.
+pub fn advertise_binary(protocol_version: &str) -> String {
+    add_extension(protocol_version, BINARY_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version offers the binary framing.
+pub fn supports_binary(protocol_version: &str) -> bool {
+    has_extension(protocol_version, BINARY_PROTOCOL_EXTENSION)
+}
+
+/// Whether the pool accepted the binary framing in its `mining.subscribe` result.
+pub fn binary_accepted(result: &Option<ResponseMessage>) -> bool {
+    accepted_extensions(result)
+        .iter()
+        .any(|e| e == BINARY_PROTOCOL_EXTENSION)
+}
+
+/// Swaps the codec of a negotiated connection, keeping any buffered bytes.
+pub fn switch_to_binary<T>(framed: Framed<T, StratumCodec>) -> Framed<T, BinaryStratumCodec> {
+    framed.map_codec(|_| BinaryStratumCodec)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn put_str(dst: &mut BytesMut, s: &str) -> Result<(), io::Error> {
+    if s.len() > u16::MAX as usize {
+        return Err(invalid("String too long"));
+    }
+    dst.put_u16(s.len() as u16);
+    dst.put_slice(s.as_bytes());
+    Ok(())
+}
+
+fn put_opt_str(dst: &mut BytesMut, s: &Option<String>) -> Result<(), io::Error> {
+    match s {
+        Some(s) => {
+            dst.put_u8(1);
+            put_str(dst, s)
+        }
+        None => {
+            dst.put_u8(0);
+            Ok(())
+        }
+    }
+}
+
+/// Lowercase hex is sent as raw bytes, anything else verbatim.
+fn put_hex(dst: &mut BytesMut, s: &str) -> Result<(), io::Error> {
+    let bytes = match hex::decode(s) {
+        Ok(bytes) if !bytes.is_empty() && hex::encode(&bytes) == s => bytes,
+        _ => {
+            dst.put_u8(HEX_RAW);
+            return put_str(dst, s);
+        }
+    };
+    if bytes.len() == 32 {
+        dst.put_u8(HEX_32);
+    } else {
+        if bytes.len() > u32::MAX as usize {
+            return Err(invalid("Hex field too long"));
+        }
+        dst.put_u8(HEX_BYTES);
+        dst.put_u32(bytes.len() as u32);
+    }
+    dst.put_slice(&bytes);
+    Ok(())
+}
+
+fn put_id(dst: &mut BytesMut, id: &Id) -> Result<(), io::Error> {
+    match id {
+        Id::Num(n) => {
+            dst.put_u8(0);
+            dst.put_u64(*n);
+            Ok(())
+        }
+        Id::Str(_) => {
+            let s = serde_json::to_value(id).map_err(|e| invalid(&e.to_string()))?;
+            dst.put_u8(1);
+            put_str(dst, s.as_str().unwrap_or_default())
+        }
+    }
+}
+
+/// Bounds-checked reads over a frame body.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], io::Error> {
+        if self.0.len() < n {
+            return Err(invalid("Truncated frame"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        Ok(self.take(2)?.get_u16())
+    }
+
+    fn u32(&mut self) -> Result<u32, io::Error> {
+        Ok(self.take(4)?.get_u32())
+    }
+
+    fn u64(&mut self) -> Result<u64, io::Error> {
+        Ok(self.take(8)?.get_u64())
+    }
+
+    fn i64(&mut self) -> Result<i64, io::Error> {
+        Ok(self.take(8)?.get_i64())
+    }
+
+    fn bool(&mut self) -> Result<bool, io::Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("Param is not bool")),
+        }
+    }
+
+    fn str(&mut self) -> Result<String, io::Error> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| invalid(&e.to_string()))
+    }
+
+    fn opt_str(&mut self) -> Result<Option<String>, io::Error> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(self.str()?)),
+            _ => Err(invalid("Invalid params")),
+        }
+    }
+
+    fn hex(&mut self) -> Result<String, io::Error> {
+        match self.u8()? {
+            HEX_RAW => self.str(),
+            HEX_BYTES => {
+                let len = self.u32()? as usize;
+                Ok(hex::encode(self.take(len)?))
+            }
+            HEX_32 => Ok(hex::encode(self.take(32)?)),
+            _ => Err(invalid("Invalid params")),
+        }
+    }
+
+    fn id(&mut self) -> Result<Id, io::Error> {
+        match self.u8()? {
+            0 => Ok(Id::Num(self.u64()?)),
+            1 => serde_json::from_value(Value::String(self.str()?))
+                .map_err(|e| invalid(&e.to_string())),
+            _ => Err(invalid("Invalid id")),
+        }
+    }
+}
+
+impl Encoder<StratumMessage> for BinaryStratumCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: StratumMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut body = BytesMut::new();
+        #[allow(deprecated)]
+        match item {
+            StratumMessage::Subscribe(id, user_agent, protocol_version, session_id) => {
+                body.put_u8(KIND_SUBSCRIBE);
+                put_id(&mut body, &id)?;
+                put_str(&mut body, &user_agent)?;
+                put_str(&mut body, &protocol_version)?;
+                put_opt_str(&mut body, &session_id)?;
+            }
+            StratumMessage::Authorize(id, account_name, miner_name, worker_password) => {
+                body.put_u8(KIND_AUTHORIZE);
+                put_id(&mut body, &id)?;
+                put_str(&mut body, &account_name)?;
+                put_str(&mut body, &miner_name)?;
+                put_opt_str(&mut body, &worker_password)?;
+            }
+            StratumMessage::SetTarget(difficulty_target) => {
+                body.put_u8(KIND_SET_TARGET);
+                body.put_u64(difficulty_target);
+            }
+            StratumMessage::Notify(
+                job_id,
+                difficulty_target,
+                block_header_root,
+                hashed_leaves_1,
+                hashed_leaves_2,
+                hashed_leaves_3,
+                hashed_leaves_4,
+                clean_jobs,
+                signature,
+            ) => {
+                body.put_u8(KIND_NOTIFY);
+                put_hex(&mut body, &job_id)?;
+                body.put_u64(difficulty_target);
+                put_hex(&mut body, &block_header_root)?;
+                put_hex(&mut body, &hashed_leaves_1)?;
+                put_hex(&mut body, &hashed_leaves_2)?;
+                put_hex(&mut body, &hashed_leaves_3)?;
+                put_hex(&mut body, &hashed_leaves_4)?;
+                body.put_u8(clean_jobs as u8);
+                put_opt_str(&mut body, &signature)?;
+            }
+            StratumMessage::PuzzleNotify(
+                job_id,
+                epoch_number,
+                epoch_challenge,
+                address,
+                proof_target,
+                clean_jobs,
+                signature,
+            ) => {
+                body.put_u8(KIND_PUZZLE_NOTIFY);
+                put_hex(&mut body, &job_id)?;
+                body.put_u32(epoch_number);
+                put_hex(&mut body, &epoch_challenge)?;
+                put_str(&mut body, &address)?;
+                body.put_u64(proof_target);
+                body.put_u8(clean_jobs as u8);
+                put_opt_str(&mut body, &signature)?;
+            }
+            StratumMessage::SetEpoch(epoch_number) => {
+                body.put_u8(KIND_SET_EPOCH);
+                body.put_u32(epoch_number);
+            }
+            StratumMessage::SetExtranonce(extranonce_prefix, extranonce_size) => {
+                body.put_u8(KIND_SET_EXTRANONCE);
+                put_hex(&mut body, &extranonce_prefix)?;
+                match extranonce_size {
+                    Some(size) => {
+                        body.put_u8(1);
+                        body.put_u32(size);
+                    }
+                    None => body.put_u8(0),
+                }
+            }
+            StratumMessage::Submit(id, job_id, nonce, proof, worker_name) => {
+                body.put_u8(KIND_SUBMIT);
+                put_id(&mut body, &id)?;
+                put_hex(&mut body, &job_id)?;
+                put_hex(&mut body, &nonce)?;
+                put_hex(&mut body, &proof)?;
+                put_opt_str(&mut body, &worker_name)?;
+            }
+            StratumMessage::SubmitSolution(id, job_id, prover_solution, worker_name) => {
+                body.put_u8(KIND_SUBMIT_SOLUTION);
+                put_id(&mut body, &id)?;
+                put_hex(&mut body, &job_id)?;
+                put_hex(&mut body, &prover_solution)?;
+                put_opt_str(&mut body, &worker_name)?;
+            }
+            StratumMessage::Response(id, result, error) => {
+                body.put_u8(KIND_RESPONSE);
+                put_id(&mut body, &id)?;
+                match result {
+                    None => body.put_u8(0),
+                    Some(ResponseMessage::Bool(b)) => body.put_u8(1 + b as u8),
+                    Some(ResponseMessage::Null) => body.put_u8(3),
+                    Some(array @ ResponseMessage::Array(_)) => {
+                        let json =
+                            serde_json::to_vec(&array).map_err(|e| invalid(&e.to_string()))?;
+                        body.put_u8(4);
+                        body.put_u32(json.len() as u32);
+                        body.put_slice(&json);
+                    }
+                }
+                match error {
+                    None => body.put_u8(0),
+                    Some(error) => {
+                        let (code, message) = error_to_parts(error)?;
+                        body.put_u8(1);
+                        body.put_i64(code);
+                        put_str(&mut body, &message)?;
+                    }
+                }
+            }
+        }
+        if body.len() > MAX_BINARY_FRAME_LENGTH {
+            return Err(invalid("Frame too long"));
+        }
+        dst.reserve(4 + body.len());
+        dst.put_u32(body.len() as u32);
+        dst.extend_from_slice(&body);
+        Ok(())
+    }
+}
+
+impl Decoder for BinaryStratumCodec {
+    type Item = StratumMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        if len > MAX_BINARY_FRAME_LENGTH {
+            return Err(invalid("Frame too long"));
+        }
+        if src.len() < 4 + len {
+            src.reserve(4 + len - src.len());
+            return Ok(None);
+        }
+        src.advance(4);
+        let frame = src.split_to(len);
+        let mut r = Reader(&frame);
+
+        #[allow(deprecated)]
+        let msg = match r.u8()? {
+            KIND_SUBSCRIBE => StratumMessage::Subscribe(r.id()?, r.str()?, r.str()?, r.opt_str()?),
+            KIND_AUTHORIZE => StratumMessage::Authorize(r.id()?, r.str()?, r.str()?, r.opt_str()?),
+            KIND_SET_TARGET => StratumMessage::SetTarget(r.u64()?),
+            KIND_NOTIFY => StratumMessage::Notify(
+                r.hex()?,
+                r.u64()?,
+                r.hex()?,
+                r.hex()?,
+                r.hex()?,
+                r.hex()?,
+                r.hex()?,
+                r.bool()?,
+                r.opt_str()?,
+            ),
+            KIND_PUZZLE_NOTIFY => StratumMessage::PuzzleNotify(
+                r.hex()?,
+                r.u32()?,
+                r.hex()?,
+                r.str()?,
+                r.u64()?,
+                r.bool()?,
+                r.opt_str()?,
+            ),
+            KIND_SET_EPOCH => StratumMessage::SetEpoch(r.u32()?),
+            KIND_SUBMIT_SOLUTION => {
+                StratumMessage::SubmitSolution(r.id()?, r.hex()?, r.hex()?, r.opt_str()?)
+            }
+            KIND_SET_EXTRANONCE => {
+                let extranonce_prefix = r.hex()?;
+                let extranonce_size = if r.bool()? { Some(r.u32()?) } else { None };
+                StratumMessage::SetExtranonce(extranonce_prefix, extranonce_size)
+            }
+            KIND_SUBMIT => {
+                StratumMessage::Submit(r.id()?, r.hex()?, r.hex()?, r.hex()?, r.opt_str()?)
+            }
+            KIND_RESPONSE => {
+                let id = r.id()?;
+                let result = match r.u8()? {
+                    0 => None,
+                    1 => Some(ResponseMessage::Bool(false)),
+                    2 => Some(ResponseMessage::Bool(true)),
+                    3 => Some(ResponseMessage::Null),
+                    4 => {
+                        let len = r.u32()? as usize;
+                        Some(
+                            serde_json::from_slice(r.take(len)?)
+                                .map_err(|e| invalid(&e.to_string()))?,
+                        )
+                    }
+                    _ => return Err(invalid("Invalid response params")),
+                };
+                let error = match r.u8()? {
+                    0 => None,
+                    1 => Some(error_from_parts(r.i64()?, &r.str()?)?),
+                    _ => return Err(invalid("Invalid response params")),
+                };
+                StratumMessage::Response(id, result, error)
+            }
+            _ => return Err(invalid("Unknown method")),
+        };
+        if !r.0.is_empty() {
+            return Err(invalid("Trailing bytes in frame"));
+        }
+        Ok(Some(msg))
+    }
+}
+
+/// Goes through the JSON-RPC response encoding so the error code mapping stays the one
+/// `StratumCodec` uses.
+fn error_to_parts(error: Error<()>) -> Result<(i64, String), io::Error> {
+    let response = Response::<(), ()>::error(Version::V2, error, None);
+    let value = serde_json::to_value(&response).map_err(|e| invalid(&e.to_string()))?;
+    let code = value["error"]["code"]
+        .as_i64()
+        .ok_or_else(|| invalid("Invalid error"))?;
+    let message = value["error"]["message"].as_str().unwrap_or_default();
+    Ok((code, message.to_string()))
+}
+
+fn error_from_parts(code: i64, message: &str) -> Result<Error<()>, io::Error> {
+    let response = json!({
+        "jsonrpc": "2.0",
+        "error": { "code": code, "message": message },
+        "id": null,
+    });
+    match serde_json::from_value::<Response<(), ()>>(response) {
+        Ok(Response {
+            payload: Err(error),
+            ..
+        }) => Ok(error),
+        _ => Err(invalid("Invalid error")),
+    }
+}
+
+#[cfg(test)]
+fn all_messages() -> Vec<StratumMessage> {
+    use crate::message::error::PoolError;
+    use crate::utils::extension::accept_extensions;
+    use json_rpc_types::ErrorCode;
+
+    let leaf = |i: u8| hex::encode([i; 32]);
+    #[allow(deprecated)]
+    let set_target = StratumMessage::SetTarget(100);
+    vec![
+        StratumMessage::Subscribe(
+            Id::Num(0),
+            "ABMatrix_Aleo_Miner".to_string(),
+            advertise_binary("0.2.0"),
+            Some("session".to_string()),
+        ),
+        StratumMessage::Subscribe(
+            serde_json::from_value(json!("sub")).unwrap(),
+            "ABMatrix_Aleo_Miner".to_string(),
+            "0.2.0".to_string(),
+            None,
+        ),
+        StratumMessage::Authorize(
+            Id::Num(1),
+            "account_name".to_string(),
+            "worker_name".to_string(),
+            Some("password".to_string()),
+        ),
+        set_target,
+        StratumMessage::Notify(
+            format!("{}_{}", hex::encode(685514u32.to_le_bytes()), "a4d8e"),
+            u64::MAX / 2,
+            leaf(0),
+            leaf(1),
+            leaf(2),
+            leaf(3),
+            leaf(4),
+            true,
+            None,
+        ),
+        StratumMessage::Notify(
+            "job_id".to_string(),
+            0,
+            "block_header_root".to_string(),
+            "ABCDEF".to_string(),
+            "abc".to_string(),
+            "".to_string(),
+            hex::encode([7u8; 48]),
+            false,
+            Some(hex::encode([8u8; 64])),
+        ),
+        StratumMessage::Submit(
+            Id::Num(u64::MAX),
+            hex::encode(7u32.to_le_bytes()),
+            hex::encode(12345u64.to_le_bytes()),
+            hex::encode(vec![9u8; 700]),
+            Some("account.rig0".to_string()),
+        ),
+        StratumMessage::Response(Id::Num(2), Some(ResponseMessage::Bool(true)), None),
+        StratumMessage::Response(
+            Id::Num(2),
+            Some(accept_extensions(&[BINARY_PROTOCOL_EXTENSION, "zstd"])),
+            None,
+        ),
+        StratumMessage::Response(Id::Num(3), Some(ResponseMessage::Bool(false)), None),
+        StratumMessage::Response(Id::Num(4), None, None),
+        StratumMessage::Response(Id::Num(5), Some(ResponseMessage::Null), None),
+        StratumMessage::Response(
+            Id::Num(6),
+            None,
+            Some(Error::with_custom_msg(
+                ErrorCode::InvalidParams,
+                &PoolError::InvalidProof(Some("test error".to_string())).to_string(),
+            )),
+        ),
+        StratumMessage::Response(
+            Id::Num(7),
+            None,
+            Some(Error::with_custom_msg(
+                ErrorCode::ServerError(PoolError::StaleProof.id()),
+                &PoolError::StaleProof.to_string(),
+            )),
+        ),
+        StratumMessage::SetExtranonce(hex::encode([1u8, 2]), None),
+        StratumMessage::SetExtranonce(String::new(), Some(8)),
+        StratumMessage::PuzzleNotify(
+            format!("{}_{}", hex::encode(3u32.to_le_bytes()), "a4d8e"),
+            3,
+            leaf(5),
+            "aleo1rhgdu77hgyqd3xjj8ucu3jj9r2krwz6mnzyd80gncr5fxcwlh5rsvzp9px".to_string(),
+            u64::MAX / 8,
+            false,
+            None,
+        ),
+        StratumMessage::SetEpoch(4),
+        StratumMessage::SubmitSolution(
+            Id::Num(8),
+            hex::encode(3u32.to_le_bytes()),
+            hex::encode(vec![6u8; 400]),
+            None,
+        ),
+    ]
+}
+
+#[test]
+fn test_binary_matches_json() {
+    let mut json = StratumCodec::default();
+    let mut binary = BinaryStratumCodec;
+    for (original, again) in all_messages().into_iter().zip(all_messages()) {
+        let name = original.name();
+
+        let mut buf = BytesMut::new();
+        json.encode(original, &mut buf).unwrap();
+        let via_json = json.decode(&mut buf).unwrap().unwrap();
+
+        let mut buf = BytesMut::new();
+        binary.encode(again, &mut buf).unwrap();
+        let via_binary = binary.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+
+        assert_eq!(via_json.to_json(), via_binary.to_json(), "{}", name);
+    }
+}
+
+#[test]
+fn test_binary_partial_frames() {
+    let mut binary = BinaryStratumCodec;
+    let mut encoded = BytesMut::new();
+    for msg in all_messages() {
+        binary.encode(msg, &mut encoded).unwrap();
+    }
+
+    let mut src = BytesMut::new();
+    let mut decoded = vec![];
+    for b in encoded.iter() {
+        src.put_u8(*b);
+        if let Some(msg) = binary.decode(&mut src).unwrap() {
+            decoded.push(msg.to_json());
+        }
+    }
+    let expected = all_messages()
+        .into_iter()
+        .map(|m| m.to_json())
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, expected);
+
+    let mut bad = BytesMut::from(&[0u8, 0, 0, 1, 99][..]);
+    assert!(binary.decode(&mut bad).is_err());
+    let mut truncated = BytesMut::from(&[0u8, 0, 0, 2, KIND_SET_TARGET, 0][..]);
+    assert!(binary.decode(&mut truncated).is_err());
+}
+
+#[test]
+fn test_binary_is_smaller() {
+    // the hex heavy Notify and Submit
+    for i in [4, 6] {
+        let mut json = BytesMut::new();
+        let mut binary = BytesMut::new();
+        StratumCodec::default()
+            .encode(all_messages().swap_remove(i), &mut json)
+            .unwrap();
+        BinaryStratumCodec
+            .encode(all_messages().swap_remove(i), &mut binary)
+            .unwrap();
+        assert!(binary.len() * 2 < json.len());
+    }
+}
+
+#[test]
+fn test_negotiation() {
+    assert_eq!(advertise_binary("0.2.0"), "0.2.0+binary");
+    assert_eq!(advertise_binary("0.2.0+binary"), "0.2.0+binary");
+    assert!(supports_binary("0.2.0+build.binary"));
+    assert!(!supports_binary("0.2.0"));
+    assert!(!supports_binary("0.2.0+binaryx"));
+    assert!(semver::Version::parse(&advertise_binary("0.2.0")).is_ok());
+}
+
+#[test]
+fn test_binary_accepted() {
+    use crate::utils::extension::accept_extensions;
+
+    let response = StratumMessage::Response(
+        Id::Num(0),
+        Some(accept_extensions(&["zstd", BINARY_PROTOCOL_EXTENSION])),
+        None,
+    );
+    match StratumMessage::from_json(&response.to_json()).unwrap() {
+        StratumMessage::Response(_, result, None) => assert!(binary_accepted(&result)),
+        _ => panic!("expected response"),
+    }
+    // a legacy pool answering `true` has not accepted anything
+    assert!(!binary_accepted(&Some(ResponseMessage::Bool(true))));
+    assert!(!binary_accepted(&Some(accept_extensions(&["zstd"]))));
+    assert!(!binary_accepted(&None));
+}
@@ -0,0 +1,449 @@
+use super::response::ResponseMessage;
+use super::stratum::StratumMessage;
+use crate::utils::extension::{
+    accept_extensions, accepted_extensions, add_extension, has_extension,
+};
+use anyhow::anyhow;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use json_rpc_types::Id;
+use rand::rngs::OsRng;
+use rand::RngCore;
+#[cfg(feature = "snarkvm")]
+use snarkvm_dpc::prelude::*;
+#[cfg(feature = "snarkvm")]
+use snarkvm_dpc::testnet2::Testnet2;
+#[cfg(feature = "snarkvm")]
+use snarkvm_utilities::{FromBytes, ToBytes};
+use std::fmt;
+#[cfg(feature = "snarkvm")]
+use std::marker::PhantomData;
+#[cfg(feature = "snarkvm")]
+use std::str::FromStr;
+
+/// Protocol extension offered in `mining.subscribe` by miners that can sign for their account.
+/// A pool that accepts lists it in the subscribe result along with `challenge=<hex nonce>`,
+/// and the `mining.authorize` password becomes `<scheme>:<hex signature>` over
+/// `challenge_payload` instead of a plaintext password.
+pub static CHALLENGE_PROTOCOL_EXTENSION: &str = "challenge";
+
+pub const CHALLENGE_LENGTH: usize = 32;
+
+const CHALLENGE_DOMAIN: &[u8] = b"ABMatrix.mining.authorize";
+const CHALLENGE_PREFIX: &str = "challenge=";
+
+pub fn advertise_challenge(protocol_version: &str) -> String {
+    add_extension(protocol_version, CHALLENGE_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version offers challenge authorization.
+pub fn supports_challenge(protocol_version: &str) -> bool {
+    has_extension(protocol_version, CHALLENGE_PROTOCOL_EXTENSION)
+}
+
+pub fn new_challenge() -> [u8; CHALLENGE_LENGTH] {
+    let mut challenge = [0u8; CHALLENGE_LENGTH];
+    OsRng.fill_bytes(&mut challenge);
+    challenge
+}
+
+/// Subscribe result item carrying the challenge, see `challenge_result`.
+pub fn challenge_extension(challenge: &[u8]) -> String {
+    format!("{}{}", CHALLENGE_PREFIX, hex::encode(challenge))
+}
+
+/// `mining.subscribe` result accepting `extensions` and challenge authorization.
+pub fn challenge_result(extensions: &[&str], challenge: &[u8]) -> ResponseMessage {
+    let challenge = challenge_extension(challenge);
+    let mut items = extensions.to_vec();
+    items.push(CHALLENGE_PROTOCOL_EXTENSION);
+    items.push(&challenge);
+    accept_extensions(&items)
+}
+
+/// The challenge from a pool's `mining.subscribe` result, if it accepted the extension.
+pub fn challenge_from_result(result: &Option<ResponseMessage>) -> Option<Vec<u8>> {
+    accepted_extensions(result)
+        .iter()
+        .find_map(|e| e.strip_prefix(CHALLENGE_PREFIX).map(hex::decode))
+        .and_then(|challenge| challenge.ok())
+        .filter(|challenge| challenge.len() == CHALLENGE_LENGTH)
+}
+
+/// The bytes the account key signs: a domain tag, the challenge, then account and miner
+/// name as `u32` big endian length and UTF-8 bytes.
+pub fn challenge_payload(challenge: &[u8], account_name: &str, miner_name: &str) -> Vec<u8> {
+    let mut payload = CHALLENGE_DOMAIN.to_vec();
+    payload.extend_from_slice(challenge);
+    for s in [account_name, miner_name] {
+        payload.extend_from_slice(&(s.len() as u32).to_be_bytes());
+        payload.extend_from_slice(s.as_bytes());
+    }
+    payload
+}
+
+/// Signs challenges with the key behind an account.
+pub trait AccountSigner {
+    /// Scheme name sent with the signature, e.g. `ed25519`.
+    fn scheme(&self) -> &str;
+    fn account_name(&self) -> String;
+    fn sign(&self, payload: &[u8]) -> Vec<u8>;
+}
+
+/// Checks that a signature was made by the key behind an account.
+pub trait AccountVerifier: Send + Sync {
+    fn scheme(&self) -> &str;
+    fn verify(&self, account_name: &str, payload: &[u8], signature: &[u8]) -> bool;
+}
+
+/// A `mining.authorize` answering `challenge`.
+pub fn signed_authorize(
+    id: Id,
+    signer: &dyn AccountSigner,
+    miner_name: String,
+    challenge: &[u8],
+) -> StratumMessage {
+    let account_name = signer.account_name();
+    let signature = signer.sign(&challenge_payload(challenge, &account_name, &miner_name));
+    let password = format!("{}:{}", signer.scheme(), hex::encode(signature));
+    StratumMessage::Authorize(id, account_name, miner_name, Some(password))
+}
+
+/// Pool side check of challenge authorizations, with one verifier per supported scheme.
+#[derive(Default)]
+pub struct ChallengeAuthorizer {
+    verifiers: Vec<Box<dyn AccountVerifier>>,
+}
+
+impl fmt::Debug for ChallengeAuthorizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let schemes = self
+            .verifiers
+            .iter()
+            .map(|v| v.scheme())
+            .collect::<Vec<_>>();
+        f.debug_struct("ChallengeAuthorizer")
+            .field("schemes", &schemes)
+            .finish()
+    }
+}
+
+impl ChallengeAuthorizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_verifier(mut self, verifier: Box<dyn AccountVerifier>) -> Self {
+        self.verifiers.push(verifier);
+        self
+    }
+
+    /// Accepts `authorize` if it carries a valid signature over the connection's challenge.
+    pub fn verify(&self, challenge: &[u8], authorize: &StratumMessage) -> anyhow::Result<()> {
+        match authorize {
+            StratumMessage::Authorize(_, account_name, miner_name, password) => {
+                self.verify_password(challenge, account_name, miner_name, password.as_deref())
+            }
+            _ => Err(anyhow!("not an authorize")),
+        }
+    }
+
+    /// `verify` on the fields of a `mining.authorize`.
+    pub fn verify_password(
+        &self,
+        challenge: &[u8],
+        account_name: &str,
+        miner_name: &str,
+        password: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let password = password.ok_or_else(|| anyhow!("missing challenge signature"))?;
+        let (scheme, signature) = password
+            .split_once(':')
+            .ok_or_else(|| anyhow!("invalid challenge signature"))?;
+        let verifier = self
+            .verifiers
+            .iter()
+            .find(|v| v.scheme() == scheme)
+            .ok_or_else(|| anyhow!("unsupported signature scheme {}", scheme))?;
+        let signature = hex::decode(signature)?;
+        let payload = challenge_payload(challenge, account_name, miner_name);
+        if verifier.verify(account_name, &payload, &signature) {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid challenge signature"))
+        }
+    }
+}
+
+/// ed25519 accounts, named by their hex encoded public key.
+///
+/// It is not an Aleo account signature, `aleo1…` accounts use `AleoAccountSigner` with the
+/// `snarkvm` feature.
+pub struct Ed25519AccountSigner(SigningKey);
+
+impl Ed25519AccountSigner {
+    pub fn new(key: SigningKey) -> Self {
+        Self(key)
+    }
+
+    pub fn generate() -> Self {
+        Self(SigningKey::generate(&mut OsRng))
+    }
+}
+
+impl AccountSigner for Ed25519AccountSigner {
+    fn scheme(&self) -> &str {
+        "ed25519"
+    }
+
+    fn account_name(&self) -> String {
+        hex::encode(self.0.verifying_key().to_bytes())
+    }
+
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        self.0.sign(payload).to_bytes().to_vec()
+    }
+}
+
+/// Checks `Ed25519AccountSigner` signatures.
+pub struct Ed25519AccountVerifier;
+
+impl AccountVerifier for Ed25519AccountVerifier {
+    fn scheme(&self) -> &str {
+        "ed25519"
+    }
+
+    fn verify(&self, account_name: &str, payload: &[u8], signature: &[u8]) -> bool {
+        let key = match hex::decode(account_name)
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+        {
+            Some(key) => key,
+            None => return false,
+        };
+        match <[u8; 64]>::try_from(signature) {
+            Ok(signature) => key
+                .verify(payload, &Signature::from_bytes(&signature))
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// The challenge payload as Aleo account signatures take it, least significant bit of each
+/// byte first.
+#[cfg(feature = "snarkvm")]
+fn payload_bits(payload: &[u8]) -> Vec<bool> {
+    payload
+        .iter()
+        .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1 == 1))
+        .collect()
+}
+
+/// Aleo accounts, named by their `aleo1…` address and signing with its private key.
+#[cfg(feature = "snarkvm")]
+pub struct AleoAccountSigner<N: Network = Testnet2>(PrivateKey<N>);
+
+#[cfg(feature = "snarkvm")]
+impl<N: Network> AleoAccountSigner<N> {
+    pub fn new(private_key: PrivateKey<N>) -> Self {
+        Self(private_key)
+    }
+}
+
+#[cfg(feature = "snarkvm")]
+impl<N: Network> AccountSigner for AleoAccountSigner<N> {
+    fn scheme(&self) -> &str {
+        "aleo"
+    }
+
+    fn account_name(&self) -> String {
+        Address::from_private_key(&self.0).to_string()
+    }
+
+    /// Empty if signing fails, which no verifier accepts.
+    fn sign(&self, payload: &[u8]) -> Vec<u8> {
+        self.0
+            .sign(&payload_bits(payload), &mut OsRng)
+            .ok()
+            .and_then(|signature| signature.to_bytes_le().ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Checks `AleoAccountSigner` signatures against the account's address.
+#[cfg(feature = "snarkvm")]
+pub struct AleoAccountVerifier<N: Network = Testnet2> {
+    _network: PhantomData<N>,
+}
+
+#[cfg(feature = "snarkvm")]
+impl<N: Network> Default for AleoAccountVerifier<N> {
+    fn default() -> Self {
+        Self {
+            _network: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "snarkvm")]
+impl<N: Network> AleoAccountVerifier<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "snarkvm")]
+impl<N: Network> AccountVerifier for AleoAccountVerifier<N> {
+    fn scheme(&self) -> &str {
+        "aleo"
+    }
+
+    fn verify(&self, account_name: &str, payload: &[u8], signature: &[u8]) -> bool {
+        let address = match Address::<N>::from_str(account_name) {
+            Ok(address) => address,
+            Err(_) => return false,
+        };
+        match N::AccountSignature::read_le(signature) {
+            Ok(signature) => address
+                .verify_signature(&payload_bits(payload), &signature)
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_challenge_authorize() {
+    use crate::message::stratum::StratumCodec;
+    use futures_util::{SinkExt, StreamExt};
+    use std::sync::Arc;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_util::codec::Framed;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let authorizer =
+        Arc::new(ChallengeAuthorizer::new().with_verifier(Box::new(Ed25519AccountVerifier)));
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let authorizer = authorizer.clone();
+            tokio::spawn(async move {
+                let mut framed = Framed::new(stream, StratumCodec::default());
+                let challenge = new_challenge();
+                while let Some(Ok(msg)) = framed.next().await {
+                    let response = match msg {
+                        StratumMessage::Subscribe(id, _, version, _) => {
+                            assert!(supports_challenge(&version));
+                            let result = challenge_result(&[], &challenge);
+                            StratumMessage::Response(id, Some(result), None)
+                        }
+                        StratumMessage::Authorize(ref id, ..) => {
+                            let accepted = authorizer.verify(&challenge, &msg).is_ok();
+                            StratumMessage::Response(
+                                id.clone(),
+                                Some(ResponseMessage::Bool(accepted)),
+                                None,
+                            )
+                        }
+                        _ => continue,
+                    };
+                    framed.send(response).await.unwrap();
+                }
+            });
+        }
+    });
+
+    let account = Ed25519AccountSigner::generate();
+    let connect = || async {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        framed
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                advertise_challenge("0.2.0"),
+                None,
+            ))
+            .await
+            .unwrap();
+        let challenge = match framed.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) => {
+                challenge_from_result(&result).unwrap()
+            }
+            _ => panic!("expected subscribe response"),
+        };
+        (framed, challenge)
+    };
+    let authorized = |msg: Option<Result<StratumMessage, std::io::Error>>| {
+        matches!(
+            msg,
+            Some(Ok(StratumMessage::Response(
+                _,
+                Some(ResponseMessage::Bool(true)),
+                None
+            )))
+        )
+    };
+
+    let (mut framed, challenge) = connect().await;
+    let authorize = signed_authorize(Id::Num(1), &account, "rig".to_string(), &challenge);
+    framed.send(authorize).await.unwrap();
+    assert!(authorized(framed.next().await));
+
+    // the account string alone is not enough
+    let (mut framed, _) = connect().await;
+    let authorize = StratumMessage::Authorize(
+        Id::Num(1),
+        account.account_name(),
+        "rig".to_string(),
+        Some("password".to_string()),
+    );
+    framed.send(authorize).await.unwrap();
+    assert!(!authorized(framed.next().await));
+
+    // a signature is bound to its connection's challenge and miner name
+    let (mut framed, challenge) = connect().await;
+    let replayed = signed_authorize(Id::Num(1), &account, "rig".to_string(), &new_challenge());
+    framed.send(replayed).await.unwrap();
+    assert!(!authorized(framed.next().await));
+    let renamed = match signed_authorize(Id::Num(1), &account, "rig".to_string(), &challenge) {
+        StratumMessage::Authorize(id, account_name, _, password) => {
+            StratumMessage::Authorize(id, account_name, "other".to_string(), password)
+        }
+        _ => unreachable!(),
+    };
+    framed.send(renamed).await.unwrap();
+    assert!(!authorized(framed.next().await));
+}
+
+#[cfg(feature = "snarkvm")]
+#[test]
+fn test_aleo_challenge() {
+    let account = AleoAccountSigner::<Testnet2>::new(PrivateKey::new(&mut OsRng));
+    assert!(account.account_name().starts_with("aleo1"));
+    let authorizer =
+        ChallengeAuthorizer::new().with_verifier(Box::new(AleoAccountVerifier::<Testnet2>::new()));
+
+    let challenge = new_challenge();
+    let authorize = signed_authorize(Id::Num(1), &account, "rig".to_string(), &challenge);
+    assert!(authorizer.verify(&challenge, &authorize).is_ok());
+    assert!(authorizer.verify(&new_challenge(), &authorize).is_err());
+
+    // another account can't sign for this one
+    let other = AleoAccountSigner::<Testnet2>::new(PrivateKey::new(&mut OsRng));
+    let signature = other.sign(&challenge_payload(
+        &challenge,
+        &account.account_name(),
+        "rig",
+    ));
+    let forged = StratumMessage::Authorize(
+        Id::Num(1),
+        account.account_name(),
+        "rig".to_string(),
+        Some(format!("aleo:{}", hex::encode(signature))),
+    );
+    assert!(authorizer.verify(&challenge, &forged).is_err());
+}
@@ -9,6 +9,10 @@ pub enum PoolError {
     InvalidProof(Option<String>),
     /// ServerNotReady usually occurs when the server is started
     ServerNotReady,
+    /// TokenExpired occurs when the worker token used to authorize has expired
+    TokenExpired,
+    /// Unauthorized(reason_message), e.g. a bad worker token or one without the needed scope
+    Unauthorized(Option<String>),
     InternalServerError,
 }
 
@@ -24,6 +28,11 @@ impl ToString for PoolError {
                 }
             }
             PoolError::ServerNotReady => "ServerNotReady".to_string(),
+            PoolError::TokenExpired => "TokenExpired".to_string(),
+            PoolError::Unauthorized(reason) => match reason {
+                Some(reason) => format!("Unauthorized{}", reason),
+                None => "Unauthorized".to_string(),
+            },
             PoolError::InternalServerError => "InternalServerError".to_string(),
         }
     }
@@ -49,6 +58,14 @@ impl FromStr for PoolError {
             Ok(Self::InternalServerError)
         } else if s.starts_with(&Self::ServerNotReady.name()) {
             Ok(Self::ServerNotReady)
+        } else if s.starts_with(Self::TokenExpired.name()) {
+            Ok(Self::TokenExpired)
+        } else if let Some(msg) = s.strip_prefix(Self::Unauthorized(None).name()) {
+            if msg.is_empty() {
+                Ok(Self::Unauthorized(None))
+            } else {
+                Ok(Self::Unauthorized(Some(msg.to_string())))
+            }
         } else {
             Err(anyhow!(format!("Unsupported message: {}", s)))
         }
@@ -61,6 +78,8 @@ impl PoolError {
             PoolError::StaleProof => 1,
             PoolError::InvalidProof(..) => 2,
             PoolError::ServerNotReady => 3,
+            PoolError::TokenExpired => 4,
+            PoolError::Unauthorized(..) => 5,
             PoolError::InternalServerError => 100,
         }
     }
@@ -70,6 +89,8 @@ impl PoolError {
             PoolError::StaleProof => "StaleProof",
             PoolError::InvalidProof(..) => "InvalidProof",
             PoolError::ServerNotReady => "ServerNotReady",
+            PoolError::TokenExpired => "TokenExpired",
+            PoolError::Unauthorized(..) => "Unauthorized",
             PoolError::InternalServerError => "InternalServerError",
         }
     }
@@ -107,6 +128,19 @@ fn test_pool_errors() {
     assert_eq!(e5, e5_r);
     assert_eq!(&m5, e5.name());
 
+    let e6 = PoolError::TokenExpired;
+    let m6 = e6.to_string();
+    assert_eq!(e6, PoolError::from_str(&m6).unwrap());
+    assert_eq!(&m6, e6.name());
+
+    let e7 = PoolError::Unauthorized(Some("missing scope".to_string()));
+    let m7 = e7.to_string();
+    assert_eq!(e7, PoolError::from_str(&m7).unwrap());
+    assert_eq!(
+        PoolError::from_str(PoolError::Unauthorized(None).name()).unwrap(),
+        PoolError::Unauthorized(None)
+    );
+
     let res = PoolError::from_str("test");
     assert!(res.is_err())
 }
@@ -0,0 +1,308 @@
+use super::error::PoolError;
+use super::response::ResponseMessage;
+use super::stratum::StratumMessage;
+use crate::utils::extension::{
+    accept_extensions, accepted_extensions, add_extension, has_extension,
+};
+use anyhow::anyhow;
+
+/// Protocol extension offered in `mining.subscribe` by miners that search only the nonce
+/// range the pool assigns. A pool that accepts lists it in the subscribe result along with
+/// `extranonce=<hex prefix>:<size>`, and may move the session with `mining.set_extranonce`.
+pub static EXTRANONCE_PROTOCOL_EXTENSION: &str = "extranonce";
+
+/// Bytes in a `Submit` nonce.
+pub const NONCE_LENGTH: usize = 8;
+
+const EXTRANONCE_PREFIX: &str = "extranonce=";
+
+pub fn advertise_extranonce(protocol_version: &str) -> String {
+    add_extension(protocol_version, EXTRANONCE_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version offers nonce partitioning.
+pub fn supports_extranonce(protocol_version: &str) -> bool {
+    has_extension(protocol_version, EXTRANONCE_PROTOCOL_EXTENSION)
+}
+
+/// The part of the nonce space a session searches: nonces that start with `prefix`, then
+/// `size` bytes the miner picks. Bytes after those are reserved and must be zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Extranonce {
+    pub prefix: Vec<u8>,
+    pub size: usize,
+}
+
+impl Default for Extranonce {
+    /// The whole nonce space.
+    fn default() -> Self {
+        Self {
+            prefix: vec![],
+            size: NONCE_LENGTH,
+        }
+    }
+}
+
+impl Extranonce {
+    /// `size` defaults to the rest of the nonce.
+    pub fn new(prefix: Vec<u8>, size: Option<usize>) -> anyhow::Result<Self> {
+        let rest = NONCE_LENGTH
+            .checked_sub(prefix.len())
+            .ok_or_else(|| anyhow!("extranonce prefix too long"))?;
+        let size = size.unwrap_or(rest);
+        if size == 0 || size > rest {
+            return Err(anyhow!("invalid extranonce size {}", size));
+        }
+        Ok(Self { prefix, size })
+    }
+
+    /// The `counter`th nonce of the range, counting wraps around once the range is used up.
+    pub fn nonce(&self, counter: u64) -> String {
+        let mut nonce = self.prefix.clone();
+        let counter = counter.to_le_bytes();
+        nonce.extend_from_slice(&counter[..self.size.min(counter.len())]);
+        nonce.resize(NONCE_LENGTH, 0);
+        hex::encode(nonce)
+    }
+
+    pub fn contains(&self, nonce: &str) -> bool {
+        match hex::decode(nonce) {
+            Ok(nonce) => {
+                nonce.len() == NONCE_LENGTH
+                    && nonce.starts_with(&self.prefix)
+                    && nonce[self.prefix.len() + self.size..]
+                        .iter()
+                        .all(|b| *b == 0)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Checks a submitted nonce lies in the session's range.
+    pub fn validate(&self, nonce: &str) -> Result<(), PoolError> {
+        if self.contains(nonce) {
+            Ok(())
+        } else {
+            Err(PoolError::InvalidProof(Some(
+                "nonce out of range".to_string(),
+            )))
+        }
+    }
+
+    /// The `index`th sub-range after fixing `bytes` more bytes of the prefix, `None` if it
+    /// does not fit.
+    pub fn split(&self, index: u64, bytes: usize) -> Option<Self> {
+        if bytes == 0 || bytes >= self.size || index >> (8 * bytes) != 0 {
+            return None;
+        }
+        let mut prefix = self.prefix.clone();
+        prefix.extend_from_slice(&index.to_be_bytes()[8 - bytes..]);
+        Some(Self {
+            prefix,
+            size: self.size - bytes,
+        })
+    }
+
+    /// Subscribe result item assigning the range, see `extranonce_result`.
+    pub fn to_extension(&self) -> String {
+        format!(
+            "{}{}:{}",
+            EXTRANONCE_PREFIX,
+            hex::encode(&self.prefix),
+            self.size
+        )
+    }
+
+    /// `mining.set_extranonce` moving a session to this range.
+    pub fn to_message(&self) -> StratumMessage {
+        StratumMessage::SetExtranonce(hex::encode(&self.prefix), Some(self.size as u32))
+    }
+
+    /// The range of a `mining.set_extranonce`, `None` for other messages.
+    pub fn from_message(msg: &StratumMessage) -> Option<anyhow::Result<Self>> {
+        match msg {
+            StratumMessage::SetExtranonce(prefix, size) => Some(
+                hex::decode(prefix)
+                    .map_err(|e| anyhow!(e))
+                    .and_then(|prefix| Self::new(prefix, size.map(|s| s as usize))),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// `mining.subscribe` result accepting `extensions` and assigning `extranonce`.
+pub fn extranonce_result(extensions: &[&str], extranonce: &Extranonce) -> ResponseMessage {
+    let assignment = extranonce.to_extension();
+    let mut items = extensions.to_vec();
+    items.push(EXTRANONCE_PROTOCOL_EXTENSION);
+    items.push(&assignment);
+    accept_extensions(&items)
+}
+
+/// The range assigned in a pool's `mining.subscribe` result, if it accepted the extension.
+pub fn extranonce_from_result(result: &Option<ResponseMessage>) -> Option<Extranonce> {
+    accepted_extensions(result).iter().find_map(|e| {
+        let (prefix, size) = e.strip_prefix(EXTRANONCE_PREFIX)?.split_once(':')?;
+        Extranonce::new(hex::decode(prefix).ok()?, Some(size.parse().ok()?)).ok()
+    })
+}
+
+/// Hands out non overlapping sub-ranges of a base range, one per session.
+#[derive(Clone, Debug)]
+pub struct ExtranonceAllocator {
+    base: Extranonce,
+    bytes: usize,
+    next: u64,
+    free: Vec<u64>,
+}
+
+impl ExtranonceAllocator {
+    /// Each session gets `bytes` more prefix bytes than `base`.
+    pub fn new(base: Extranonce, bytes: usize) -> Self {
+        Self {
+            base,
+            bytes,
+            next: 0,
+            free: vec![],
+        }
+    }
+
+    pub fn base(&self) -> &Extranonce {
+        &self.base
+    }
+
+    /// A range no other live session has, `None` once they are all taken.
+    pub fn allocate(&mut self) -> Option<(u64, Extranonce)> {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                let extranonce = self.base.split(self.next, self.bytes)?;
+                self.next += 1;
+                return Some((self.next - 1, extranonce));
+            }
+        };
+        self.base.split(index, self.bytes).map(|e| (index, e))
+    }
+
+    /// Returns the range of a session that ended.
+    pub fn release(&mut self, index: u64) {
+        if index < self.next && !self.free.contains(&index) {
+            self.free.push(index);
+        }
+    }
+
+    /// Moves every allocation under a new base, `get` gives their new ranges.
+    pub fn rebase(&mut self, base: Extranonce) {
+        self.base = base;
+    }
+
+    /// The current range of an allocated index.
+    pub fn get(&self, index: u64) -> Option<Extranonce> {
+        self.base.split(index, self.bytes)
+    }
+
+    /// Whether a nonce lies in the base range but outside every live allocation. Sessions
+    /// without a range of their own search the whole base, this keeps them off the others.
+    pub fn unallocated(&self, nonce: &str) -> bool {
+        if !self.base.contains(nonce) {
+            return false;
+        }
+        let nonce = match hex::decode(nonce) {
+            Ok(nonce) => nonce,
+            Err(_) => return false,
+        };
+        let start = self.base.prefix.len();
+        let index = nonce[start..start + self.bytes]
+            .iter()
+            .fold(0u64, |index, b| index << 8 | *b as u64);
+        index >= self.next || self.free.contains(&index)
+    }
+
+    /// Checks a nonce from a session without a range, see `unallocated`.
+    pub fn validate_unallocated(&self, nonce: &str) -> Result<(), PoolError> {
+        if self.unallocated(nonce) {
+            Ok(())
+        } else {
+            Err(PoolError::InvalidProof(Some(
+                "nonce out of range".to_string(),
+            )))
+        }
+    }
+}
+
+#[test]
+fn test_extranonce_range() {
+    let extranonce = Extranonce::new(vec![0xab, 0xcd], None).unwrap();
+    assert_eq!(extranonce.size, 6);
+    assert_eq!(extranonce.nonce(1), "abcd010000000000");
+    assert!(extranonce.contains(&extranonce.nonce(u64::MAX)));
+    assert!(extranonce.validate("abcd0102030405ff").is_ok());
+    assert_eq!(
+        extranonce.validate("abce000000000000"),
+        Err(PoolError::InvalidProof(Some(
+            "nonce out of range".to_string()
+        )))
+    );
+    assert!(!extranonce.contains("abcd"));
+    assert!(!extranonce.contains("not hex"));
+
+    // a smaller size reserves the trailing bytes
+    let small = Extranonce::new(vec![0xab], Some(2)).unwrap();
+    assert_eq!(small.nonce(0x10203), "ab03020000000000");
+    assert!(!small.contains("ab03020100000000"));
+
+    assert!(Extranonce::new(vec![0; 8], None).is_err());
+    assert!(Extranonce::new(vec![0; 4], Some(5)).is_err());
+    assert!(Extranonce::default().contains("0102030405060708"));
+}
+
+#[test]
+fn test_extranonce_negotiation() {
+    let extranonce = Extranonce::new(vec![1, 2, 3], Some(4)).unwrap();
+    assert!(supports_extranonce(&advertise_extranonce("0.2.0")));
+    assert!(!supports_extranonce("0.2.0"));
+
+    let result = Some(extranonce_result(&["signed"], &extranonce));
+    assert_eq!(accepted_extensions(&result)[..2], ["signed", "extranonce"]);
+    assert_eq!(extranonce_from_result(&result), Some(extranonce.clone()));
+    assert_eq!(extranonce_from_result(&None), None);
+
+    let msg = StratumMessage::from_json(&extranonce.to_message().to_json()).unwrap();
+    assert_eq!(Extranonce::from_message(&msg).unwrap().unwrap(), extranonce);
+    let default_size = StratumMessage::SetExtranonce("0102".to_string(), None);
+    assert_eq!(
+        Extranonce::from_message(&default_size)
+            .unwrap()
+            .unwrap()
+            .size,
+        6
+    );
+}
+
+#[test]
+fn test_extranonce_allocator() {
+    let mut allocator = ExtranonceAllocator::new(Extranonce::new(vec![9], None).unwrap(), 1);
+    let (a, first) = allocator.allocate().unwrap();
+    let (b, second) = allocator.allocate().unwrap();
+    assert_eq!(first.prefix, vec![9, 0]);
+    assert_eq!(second.prefix, vec![9, 1]);
+    assert!(!first.contains(&second.nonce(0)));
+
+    // sessions without a range only get the rest of the base
+    assert!(!allocator.unallocated(&second.nonce(0)));
+    assert!(allocator.unallocated("0902000000000000"));
+    assert!(!allocator.unallocated("0a02000000000000"));
+    assert!(allocator.validate_unallocated(&first.nonce(0)).is_err());
+    allocator.release(a);
+    assert!(allocator.validate_unallocated(&first.nonce(0)).is_ok());
+
+    assert_eq!(allocator.allocate().unwrap(), (a, first));
+    for _ in 2..256 {
+        assert!(allocator.allocate().is_some());
+    }
+    assert!(allocator.allocate().is_none());
+
+    allocator.rebase(Extranonce::new(vec![7], None).unwrap());
+    assert_eq!(allocator.get(b).unwrap().prefix, vec![7, 1]);
+}
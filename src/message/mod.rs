@@ -1,3 +1,15 @@
+pub mod binary;
+#[cfg(feature = "signing")]
+pub mod challenge;
 pub mod error;
+pub mod extranonce;
+pub mod proof;
+pub mod puzzle;
 pub mod response;
+#[cfg(feature = "signing")]
+pub mod signing;
 pub mod stratum;
+#[cfg(feature = "tokens")]
+pub mod token;
+pub mod v2;
+pub mod workers;
@@ -0,0 +1,386 @@
+use super::error::PoolError;
+use super::extranonce::NONCE_LENGTH;
+use super::stratum::{StratumCodec, StratumMessage};
+use bytes::BytesMut;
+use json_rpc_types::{Error, ErrorCode, Id};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The nonce of a `mining.submit`, hex on the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Nonce(Vec<u8>);
+
+impl Nonce {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Decodes a hex nonce, checking its length in bytes when one is expected.
+    pub fn from_hex(nonce: &str, length: Option<usize>) -> Result<Self, PoolError> {
+        decode_hex(nonce, length, "nonce").map(Self)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl fmt::Display for Nonce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+impl FromStr for Nonce {
+    type Err = PoolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s, None)
+    }
+}
+
+impl TryFrom<String> for Nonce {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse().map_err(|e: PoolError| e.to_string())
+    }
+}
+
+impl From<Nonce> for String {
+    fn from(nonce: Nonce) -> Self {
+        nonce.to_string()
+    }
+}
+
+/// The proof of a `mining.submit`, hex on the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ProofBytes(Vec<u8>);
+
+impl ProofBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    /// Decodes a hex proof, checking its length in bytes when one is expected. Without one
+    /// any non empty proof is accepted.
+    pub fn from_hex(proof: &str, length: Option<usize>) -> Result<Self, PoolError> {
+        let bytes = decode_hex(proof, length, "proof")?;
+        if bytes.is_empty() {
+            return Err(PoolError::InvalidProof(Some("empty proof".to_string())));
+        }
+        Ok(Self(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl fmt::Display for ProofBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(&self.0))
+    }
+}
+
+impl FromStr for ProofBytes {
+    type Err = PoolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s, None)
+    }
+}
+
+impl TryFrom<String> for ProofBytes {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse().map_err(|e: PoolError| e.to_string())
+    }
+}
+
+impl From<ProofBytes> for String {
+    fn from(proof: ProofBytes) -> Self {
+        proof.to_string()
+    }
+}
+
+/// Reasons are kept short, they travel in the JSON-RPC error message.
+fn decode_hex(s: &str, length: Option<usize>, what: &str) -> Result<Vec<u8>, PoolError> {
+    let bytes =
+        hex::decode(s).map_err(|_| PoolError::InvalidProof(Some(format!("{} not hex", what))))?;
+    match length {
+        Some(length) if bytes.len() != length => Err(PoolError::InvalidProof(Some(format!(
+            "bad {} length",
+            what
+        )))),
+        _ => Ok(bytes),
+    }
+}
+
+/// Lengths in bytes the nonce and proof of a `mining.submit` must have.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubmitFormat {
+    pub nonce_length: usize,
+    /// `None` accepts proofs of any length
+    pub proof_length: Option<usize>,
+}
+
+impl Default for SubmitFormat {
+    fn default() -> Self {
+        Self {
+            nonce_length: NONCE_LENGTH,
+            proof_length: None,
+        }
+    }
+}
+
+impl SubmitFormat {
+    pub fn validate(&self, nonce: &str, proof: &str) -> Result<(Nonce, ProofBytes), PoolError> {
+        Ok((
+            Nonce::from_hex(nonce, Some(self.nonce_length))?,
+            ProofBytes::from_hex(proof, self.proof_length)?,
+        ))
+    }
+
+    /// Checks a decoded `mining.submit`, other messages pass. The pool answers a rejected
+    /// submit with `InvalidSubmit::response` and keeps reading the connection.
+    pub fn check(&self, msg: &StratumMessage) -> Result<(), InvalidSubmit> {
+        match msg {
+            StratumMessage::Submit(id, _, nonce, proof, _) => self
+                .validate(nonce, proof)
+                .map(|_| ())
+                .map_err(|error| InvalidSubmit {
+                    id: id.clone(),
+                    error,
+                }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The `SubmitFormat` of each protocol version, by major and minor version.
+#[derive(Clone, Debug, Default)]
+pub struct SubmitFormats {
+    formats: HashMap<(u64, u64), SubmitFormat>,
+    default: SubmitFormat,
+}
+
+impl SubmitFormats {
+    pub fn new(default: SubmitFormat) -> Self {
+        Self {
+            formats: HashMap::new(),
+            default,
+        }
+    }
+
+    pub fn with_version(mut self, version: &Version, format: SubmitFormat) -> Self {
+        self.formats.insert((version.major, version.minor), format);
+        self
+    }
+
+    /// The format for a `mining.subscribe` protocol version, extensions included. Versions
+    /// without their own format, or that do not parse, get the default.
+    pub fn for_protocol_version(&self, protocol_version: &str) -> SubmitFormat {
+        Version::parse(protocol_version)
+            .ok()
+            .and_then(|v| self.formats.get(&(v.major, v.minor)).copied())
+            .unwrap_or(self.default)
+    }
+}
+
+/// A malformed `mining.submit` rejected by `SubmitFormat::check`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidSubmit {
+    pub id: Id,
+    pub error: PoolError,
+}
+
+impl InvalidSubmit {
+    /// Error response to the submit.
+    pub fn response(&self) -> StratumMessage {
+        StratumMessage::Response(
+            self.id.clone(),
+            None,
+            Some(Error::with_custom_msg(
+                ErrorCode::ServerError(self.error.id()),
+                &self.error.to_string(),
+            )),
+        )
+    }
+}
+
+impl fmt::Display for InvalidSubmit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid submit: {}", self.error.to_string())
+    }
+}
+
+impl std::error::Error for InvalidSubmit {}
+
+/// `StratumCodec` checking every decoded `mining.submit` with `format`. A malformed submit
+/// decodes to an `InvalidSubmit` instead of ending the stream, so a pool can answer it and
+/// keep reading. Set `format` from `SubmitFormats` once the miner has subscribed.
+#[derive(Default)]
+pub struct SubmitCheckCodec {
+    pub codec: StratumCodec,
+    pub format: SubmitFormat,
+}
+
+impl SubmitCheckCodec {
+    pub fn new(format: SubmitFormat) -> Self {
+        Self {
+            codec: StratumCodec::default(),
+            format,
+        }
+    }
+}
+
+impl Decoder for SubmitCheckCodec {
+    type Item = Result<StratumMessage, InvalidSubmit>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        Ok(self
+            .codec
+            .decode(src)?
+            .map(|msg| self.format.check(&msg).map(|_| msg)))
+    }
+}
+
+impl<T> Encoder<T> for SubmitCheckCodec
+where
+    StratumCodec: Encoder<T, Error = io::Error>,
+{
+    type Error = io::Error;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.codec.encode(item, dst)
+    }
+}
+
+#[test]
+fn test_nonce_and_proof() {
+    let nonce: Nonce = "0001020304050607".parse().unwrap();
+    assert_eq!(nonce.as_bytes(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(nonce.to_string(), "0001020304050607");
+    let json = serde_json::to_string(&nonce).unwrap();
+    assert_eq!(json, "\"0001020304050607\"");
+    assert_eq!(serde_json::from_str::<Nonce>(&json).unwrap(), nonce);
+    assert!(serde_json::from_str::<Nonce>("\"xyz\"").is_err());
+
+    assert_eq!(
+        Nonce::from_hex("0001", Some(8)),
+        Err(PoolError::InvalidProof(Some(
+            "bad nonce length".to_string()
+        )))
+    );
+    assert_eq!(
+        Nonce::from_hex("zz", None),
+        Err(PoolError::InvalidProof(Some("nonce not hex".to_string())))
+    );
+
+    let proof = ProofBytes::new(vec![0xab; 4]);
+    let json = serde_json::to_string(&proof).unwrap();
+    assert_eq!(serde_json::from_str::<ProofBytes>(&json).unwrap(), proof);
+    assert_eq!(
+        ProofBytes::from_hex("", None),
+        Err(PoolError::InvalidProof(Some("empty proof".to_string())))
+    );
+    assert!(ProofBytes::from_hex("abab", Some(3)).is_err());
+
+    // reasons must fit a JSON-RPC error message
+    for reason in ["bad proof length", "proof not hex"] {
+        let error = PoolError::InvalidProof(Some(reason.to_string()));
+        let _ =
+            Error::<()>::with_custom_msg(ErrorCode::ServerError(error.id()), &error.to_string());
+    }
+}
+
+#[test]
+fn test_submit_formats() {
+    let v3 = SubmitFormat {
+        nonce_length: 8,
+        proof_length: Some(4),
+    };
+    let formats = SubmitFormats::default().with_version(&Version::new(0, 3, 0), v3);
+    assert_eq!(formats.for_protocol_version("0.3.1+binary"), v3);
+    assert_eq!(
+        formats.for_protocol_version("0.2.0"),
+        SubmitFormat::default()
+    );
+    assert_eq!(
+        formats.for_protocol_version("unknown"),
+        SubmitFormat::default()
+    );
+
+    assert!(v3.validate("0001020304050607", "abababab").is_ok());
+    assert!(v3.validate("0001020304050607", "abab").is_err());
+    assert!(SubmitFormat::default()
+        .validate("0001020304050607", "ab")
+        .is_ok());
+}
+
+#[test]
+fn test_check_submits() {
+    let submit = |nonce: &str| {
+        StratumMessage::Submit(
+            Id::Num(7),
+            "job_id".to_string(),
+            nonce.to_string(),
+            "abcd".to_string(),
+            None,
+        )
+    };
+    let mut buf = BytesMut::new();
+    let mut codec = StratumCodec::default();
+    codec.encode(submit("0001"), &mut buf).unwrap();
+    codec.encode(submit("0001020304050607"), &mut buf).unwrap();
+
+    // a bad submit still decodes, so the stream goes on after it is answered
+    let format = SubmitFormat::default();
+    let bad = codec.decode(&mut buf).unwrap().unwrap();
+    let invalid = format.check(&bad).unwrap_err();
+    assert_eq!(invalid.id, Id::Num(7));
+    assert_eq!(
+        invalid.error,
+        PoolError::InvalidProof(Some("bad nonce length".to_string()))
+    );
+    match invalid.response() {
+        StratumMessage::Response(Id::Num(7), None, Some(error)) => {
+            assert_eq!(PoolError::from_str(&error.message).unwrap(), invalid.error)
+        }
+        _ => panic!("expected an error response"),
+    }
+    let good = codec.decode(&mut buf).unwrap().unwrap();
+    assert!(format.check(&good).is_ok());
+    assert!(format.check(&StratumMessage::SetEpoch(1)).is_ok());
+
+    // the checking codec yields the rejection as an item and goes on decoding
+    let mut codec = SubmitCheckCodec::new(format);
+    codec.encode(submit("0001"), &mut buf).unwrap();
+    codec.encode(submit("0001020304050607"), &mut buf).unwrap();
+    match codec.decode(&mut buf).unwrap().unwrap() {
+        Err(rejected) => assert_eq!(rejected, invalid),
+        Ok(_) => panic!("expected the submit to be rejected"),
+    }
+    assert!(matches!(
+        codec.decode(&mut buf).unwrap().unwrap(),
+        Ok(StratumMessage::Submit(..))
+    ));
+}
@@ -0,0 +1,175 @@
+use super::error::PoolError;
+use super::proof::ProofBytes;
+use super::response::ResponseMessage;
+use super::stratum::StratumMessage;
+use crate::utils::extension::{accepted_extensions, add_extension, has_extension};
+use anyhow::anyhow;
+use json_rpc_types::Id;
+
+/// Protocol extension offered in `mining.subscribe` by miners that solve the testnet3
+/// coinbase puzzle. A pool that accepts sends `PuzzleNotify` jobs and takes
+/// `SubmitSolution` shares instead of the block header `Notify` and `Submit`.
+pub static TESTNET3_PROTOCOL_EXTENSION: &str = "testnet3";
+
+pub fn advertise_testnet3(protocol_version: &str) -> String {
+    add_extension(protocol_version, TESTNET3_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version offers testnet3 jobs.
+pub fn supports_testnet3(protocol_version: &str) -> bool {
+    has_extension(protocol_version, TESTNET3_PROTOCOL_EXTENSION)
+}
+
+/// The shape of the jobs and shares of a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobFormat {
+    /// testnet2 block header tree, `Notify` and `Submit`
+    BlockHeader,
+    /// testnet3 coinbase puzzle, `PuzzleNotify` and `SubmitSolution`
+    CoinbasePuzzle,
+}
+
+impl JobFormat {
+    /// The format a pool serves a miner that subscribed with `protocol_version`.
+    pub fn for_protocol_version(protocol_version: &str) -> Self {
+        if supports_testnet3(protocol_version) {
+            Self::CoinbasePuzzle
+        } else {
+            Self::BlockHeader
+        }
+    }
+
+    /// The format a pool picked, read from its `mining.subscribe` result.
+    pub fn from_result(result: &Option<ResponseMessage>) -> Self {
+        if accepted_extensions(result)
+            .iter()
+            .any(|e| e == TESTNET3_PROTOCOL_EXTENSION)
+        {
+            Self::CoinbasePuzzle
+        } else {
+            Self::BlockHeader
+        }
+    }
+}
+
+/// A testnet3 coinbase puzzle job.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PuzzleJob {
+    pub job_id: String,
+    pub epoch_number: u32,
+    pub epoch_challenge: Vec<u8>,
+    /// Address the coinbase reward goes to, i.e. the pool's
+    pub address: String,
+    /// Solutions must reach this target to count as a share
+    pub proof_target: u64,
+}
+
+impl PuzzleJob {
+    pub fn from_message(msg: &StratumMessage) -> anyhow::Result<Self> {
+        match msg {
+            StratumMessage::PuzzleNotify(
+                job_id,
+                epoch_number,
+                epoch_challenge,
+                address,
+                proof_target,
+                ..,
+            ) => Ok(Self {
+                job_id: job_id.clone(),
+                epoch_number: *epoch_number,
+                epoch_challenge: hex::decode(epoch_challenge)
+                    .map_err(|e| anyhow!("decode epoch_challenge failed with error: {}", e))?,
+                address: address.clone(),
+                proof_target: *proof_target,
+            }),
+            _ => Err(anyhow!("not a puzzle notify")),
+        }
+    }
+
+    pub fn to_message(&self, clean_jobs: bool) -> StratumMessage {
+        StratumMessage::PuzzleNotify(
+            self.job_id.clone(),
+            self.epoch_number,
+            hex::encode(&self.epoch_challenge),
+            self.address.clone(),
+            self.proof_target,
+            clean_jobs,
+            None,
+        )
+    }
+}
+
+/// The prover solution of a `SubmitSolution`, serialized as snarkVM does.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PuzzleSolution {
+    pub job_id: String,
+    pub prover_solution: ProofBytes,
+}
+
+impl PuzzleSolution {
+    pub fn from_submit(msg: &StratumMessage) -> Result<Self, PoolError> {
+        match msg {
+            StratumMessage::SubmitSolution(_, job_id, prover_solution, _) => Ok(Self {
+                job_id: job_id.clone(),
+                prover_solution: ProofBytes::from_hex(prover_solution, None)?,
+            }),
+            _ => Err(PoolError::InvalidProof(Some("not a solution".to_string()))),
+        }
+    }
+
+    pub fn to_submit(&self, id: Id, worker_name: Option<String>) -> StratumMessage {
+        StratumMessage::SubmitSolution(
+            id,
+            self.job_id.clone(),
+            self.prover_solution.to_string(),
+            worker_name,
+        )
+    }
+}
+
+#[test]
+fn test_job_format() {
+    use crate::utils::extension::accept_extensions;
+
+    let version = advertise_testnet3("0.2.0");
+    assert_eq!(
+        JobFormat::for_protocol_version(&version),
+        JobFormat::CoinbasePuzzle
+    );
+    assert_eq!(
+        JobFormat::for_protocol_version("0.2.0"),
+        JobFormat::BlockHeader
+    );
+    let result = Some(accept_extensions(&[TESTNET3_PROTOCOL_EXTENSION]));
+    assert_eq!(JobFormat::from_result(&result), JobFormat::CoinbasePuzzle);
+    assert_eq!(JobFormat::from_result(&None), JobFormat::BlockHeader);
+}
+
+#[test]
+fn test_puzzle_messages() {
+    let job = PuzzleJob {
+        job_id: "job".to_string(),
+        epoch_number: 12,
+        epoch_challenge: vec![5; 32],
+        address: "aleo1pool".to_string(),
+        proof_target: 1 << 20,
+    };
+    let msg = StratumMessage::from_json(&job.to_message(true).to_json()).unwrap();
+    assert_eq!(PuzzleJob::from_message(&msg).unwrap(), job);
+    assert!(PuzzleJob::from_message(&StratumMessage::SetExtranonce(String::new(), None)).is_err());
+
+    let solution = PuzzleSolution {
+        job_id: job.job_id.clone(),
+        prover_solution: ProofBytes::new(vec![1, 2, 3]),
+    };
+    let submit = solution.to_submit(Id::Num(3), Some("account.rig0".to_string()));
+    let submit = StratumMessage::from_json(&submit.to_json()).unwrap();
+    assert_eq!(PuzzleSolution::from_submit(&submit).unwrap(), solution);
+
+    let bad =
+        StratumMessage::SubmitSolution(Id::Num(4), "job".to_string(), "xyz".to_string(), None);
+    assert!(matches!(
+        PuzzleSolution::from_submit(&bad),
+        Err(PoolError::InvalidProof(Some(_)))
+    ));
+}
@@ -48,11 +48,13 @@ impl<'de> Deserialize<'de> for ResponseMessage {
             Value::Bool(b) => Ok(ResponseMessage::Bool(b)),
             Value::Array(a) => {
                 let mut vec: Vec<Box<dyn ErasedSerialize + Send + Sync>> = Vec::new();
-                let _ = a.iter().map(|v| match v {
-                    Value::String(s) => vec.push(Box::new(s.clone())),
-                    Value::Number(n) => vec.push(Box::new(n.as_u64())),
-                    _ => {}
-                });
+                for v in a.iter() {
+                    match v {
+                        Value::String(s) => vec.push(Box::new(s.clone())),
+                        Value::Number(n) => vec.push(Box::new(n.as_u64())),
+                        _ => {}
+                    }
+                }
                 Ok(ResponseMessage::Array(vec))
             }
             Value::Null => Ok(ResponseMessage::Null),
@@ -60,3 +62,13 @@ impl<'de> Deserialize<'de> for ResponseMessage {
         }
     }
 }
+
+#[test]
+fn test_array_keeps_items() {
+    let result: ResponseMessage = serde_json::from_str(r#"["zstd", 7, null]"#).unwrap();
+    match &result {
+        ResponseMessage::Array(items) => assert_eq!(items.len(), 2),
+        _ => panic!("expected array"),
+    }
+    assert_eq!(serde_json::to_string(&result).unwrap(), r#"["zstd",7]"#);
+}
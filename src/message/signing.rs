@@ -0,0 +1,307 @@
+use super::response::ResponseMessage;
+use super::stratum::StratumMessage;
+use crate::utils::extension::{accepted_extensions, add_extension, has_extension};
+use anyhow::anyhow;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+/// Protocol extension offered in `mining.subscribe` by miners that verify job signatures,
+/// and listed in the subscribe result by pools that will sign their `Notify`s and
+/// `PuzzleNotify`s.
+pub static SIGNING_PROTOCOL_EXTENSION: &str = "signed";
+
+const SIGNING_DOMAIN: &[u8] = b"ABMatrix.mining.notify";
+const PUZZLE_SIGNING_DOMAIN: &[u8] = b"ABMatrix.mining.puzzle_notify";
+
+pub fn advertise_signing(protocol_version: &str) -> String {
+    add_extension(protocol_version, SIGNING_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version asks for signed jobs.
+pub fn supports_signing(protocol_version: &str) -> bool {
+    has_extension(protocol_version, SIGNING_PROTOCOL_EXTENSION)
+}
+
+/// Whether the pool promised signed jobs in its `mining.subscribe` result.
+pub fn signing_accepted(result: &Option<ResponseMessage>) -> bool {
+    accepted_extensions(result)
+        .iter()
+        .any(|e| e == SIGNING_PROTOCOL_EXTENSION)
+}
+
+/// The bytes a `Notify` or `PuzzleNotify` signature covers: a domain tag for the kind of
+/// job, then every job field in order, strings as `u32` big endian length and UTF-8 bytes.
+/// `None` for other messages.
+pub fn signing_payload(notify: &StratumMessage) -> Option<Vec<u8>> {
+    fn put_str(payload: &mut Vec<u8>, s: &str) {
+        payload.extend_from_slice(&(s.len() as u32).to_be_bytes());
+        payload.extend_from_slice(s.as_bytes());
+    }
+    match notify {
+        StratumMessage::Notify(job_id, target, root, l1, l2, l3, l4, clean_jobs, _) => {
+            let mut payload = SIGNING_DOMAIN.to_vec();
+            put_str(&mut payload, job_id);
+            put_str(&mut payload, root);
+            for leaf in [l1, l2, l3, l4] {
+                put_str(&mut payload, leaf);
+            }
+            payload.extend_from_slice(&target.to_be_bytes());
+            payload.push(*clean_jobs as u8);
+            Some(payload)
+        }
+        StratumMessage::PuzzleNotify(
+            job_id,
+            epoch_number,
+            epoch_challenge,
+            address,
+            proof_target,
+            clean_jobs,
+            _,
+        ) => {
+            let mut payload = PUZZLE_SIGNING_DOMAIN.to_vec();
+            put_str(&mut payload, job_id);
+            payload.extend_from_slice(&epoch_number.to_be_bytes());
+            put_str(&mut payload, epoch_challenge);
+            put_str(&mut payload, address);
+            payload.extend_from_slice(&proof_target.to_be_bytes());
+            payload.push(*clean_jobs as u8);
+            Some(payload)
+        }
+        _ => None,
+    }
+}
+
+/// The pool's ed25519 job signing key.
+pub struct NotifySigner(SigningKey);
+
+impl NotifySigner {
+    pub fn generate() -> Self {
+        Self(SigningKey::generate(&mut OsRng))
+    }
+
+    pub fn from_secret_hex(secret: &str) -> anyhow::Result<Self> {
+        let bytes: [u8; 32] = hex::decode(secret.trim())?
+            .try_into()
+            .map_err(|_| anyhow!("signing key must be 32 bytes"))?;
+        Ok(Self(SigningKey::from_bytes(&bytes)))
+    }
+
+    /// Reads the hex encoded secret key at `path`, or creates it. A new file is only readable
+    /// by its owner.
+    pub fn load_or_generate(path: &Path) -> anyhow::Result<Self> {
+        if path.exists() {
+            return Self::from_secret_hex(&fs::read_to_string(path)?);
+        }
+        let signer = Self::generate();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(path)?
+            .write_all(signer.secret_hex().as_bytes())?;
+        Ok(signer)
+    }
+
+    pub fn secret_hex(&self) -> String {
+        hex::encode(self.0.to_bytes())
+    }
+
+    /// The key miners configure to verify jobs, hex encoded.
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.0.verifying_key().to_bytes())
+    }
+
+    /// Fills in the signature of a `Notify` or `PuzzleNotify`, other messages are returned
+    /// unchanged.
+    pub fn sign(&self, msg: StratumMessage) -> StratumMessage {
+        let payload = match signing_payload(&msg) {
+            Some(payload) => payload,
+            None => return msg,
+        };
+        let signature = hex::encode(self.0.sign(&payload).to_bytes());
+        match msg {
+            StratumMessage::Notify(job_id, target, root, l1, l2, l3, l4, clean_jobs, _) => {
+                StratumMessage::Notify(
+                    job_id,
+                    target,
+                    root,
+                    l1,
+                    l2,
+                    l3,
+                    l4,
+                    clean_jobs,
+                    Some(signature),
+                )
+            }
+            StratumMessage::PuzzleNotify(
+                job_id,
+                epoch_number,
+                epoch_challenge,
+                address,
+                proof_target,
+                clean_jobs,
+                _,
+            ) => StratumMessage::PuzzleNotify(
+                job_id,
+                epoch_number,
+                epoch_challenge,
+                address,
+                proof_target,
+                clean_jobs,
+                Some(signature),
+            ),
+            msg => msg,
+        }
+    }
+}
+
+/// Checks `Notify` and `PuzzleNotify` signatures against the pool's published key.
+#[derive(Clone, Debug)]
+pub struct NotifyVerifier(VerifyingKey);
+
+impl NotifyVerifier {
+    pub fn from_public_key_hex(public_key: &str) -> anyhow::Result<Self> {
+        let bytes: [u8; 32] = hex::decode(public_key.trim())?
+            .try_into()
+            .map_err(|_| anyhow!("public key must be 32 bytes"))?;
+        Ok(Self(VerifyingKey::from_bytes(&bytes)?))
+    }
+
+    pub fn verify(&self, notify: &StratumMessage) -> anyhow::Result<()> {
+        let payload = signing_payload(notify).ok_or_else(|| anyhow!("not a notify"))?;
+        let signature = match notify {
+            StratumMessage::Notify(.., Some(signature))
+            | StratumMessage::PuzzleNotify(.., Some(signature)) => signature,
+            _ => return Err(anyhow!("notify is not signed")),
+        };
+        let signature: [u8; 64] = hex::decode(signature)?
+            .try_into()
+            .map_err(|_| anyhow!("signature must be 64 bytes"))?;
+        self.0
+            .verify(&payload, &Signature::from_bytes(&signature))
+            .map_err(|_| anyhow!("invalid notify signature"))
+    }
+}
+
+#[cfg(test)]
+fn notify(target: u64) -> StratumMessage {
+    StratumMessage::Notify(
+        "job_id".to_string(),
+        target,
+        "block_header_root".to_string(),
+        "hashed_leaves_1".to_string(),
+        "hashed_leaves_2".to_string(),
+        "hashed_leaves_3".to_string(),
+        "hashed_leaves_4".to_string(),
+        true,
+        None,
+    )
+}
+
+#[test]
+fn test_sign_and_verify() {
+    let signer = NotifySigner::generate();
+    let verifier = NotifyVerifier::from_public_key_hex(&signer.public_key_hex()).unwrap();
+
+    assert!(verifier.verify(&notify(100)).is_err());
+    let signed = signer.sign(notify(100));
+    verifier.verify(&signed).unwrap();
+
+    // the signature survives the wire
+    let decoded = StratumMessage::from_json(&signed.to_json()).unwrap();
+    verifier.verify(&decoded).unwrap();
+
+    // a tampered target, or another pool's key, fails
+    let signature = match signer.sign(notify(100)) {
+        StratumMessage::Notify(.., signature) => signature,
+        _ => unreachable!(),
+    };
+    let tampered = match notify(1) {
+        StratumMessage::Notify(a, b, c, d, e, f, g, h, _) => {
+            StratumMessage::Notify(a, b, c, d, e, f, g, h, signature)
+        }
+        _ => unreachable!(),
+    };
+    assert!(verifier.verify(&tampered).is_err());
+    let other =
+        NotifyVerifier::from_public_key_hex(&NotifySigner::generate().public_key_hex()).unwrap();
+    assert!(other.verify(&signer.sign(notify(100))).is_err());
+
+    // keys round trip through their hex form
+    let restored = NotifySigner::from_secret_hex(&signer.secret_hex()).unwrap();
+    assert_eq!(restored.public_key_hex(), signer.public_key_hex());
+}
+
+#[test]
+fn test_sign_and_verify_puzzle() {
+    use super::puzzle::PuzzleJob;
+
+    let signer = NotifySigner::generate();
+    let verifier = NotifyVerifier::from_public_key_hex(&signer.public_key_hex()).unwrap();
+    let job = PuzzleJob {
+        job_id: "job_id".to_string(),
+        epoch_number: 3,
+        epoch_challenge: vec![1, 2, 3],
+        address: "aleo1pool".to_string(),
+        proof_target: 100,
+    };
+
+    assert!(verifier.verify(&job.to_message(true)).is_err());
+    let json = signer.sign(job.to_message(true)).to_json();
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(value["params"].as_array().unwrap().len(), 7);
+    let decoded = StratumMessage::from_json(&json).unwrap();
+    verifier.verify(&decoded).unwrap();
+
+    // a redirected payout address fails
+    let signature = match decoded {
+        StratumMessage::PuzzleNotify(.., signature) => signature,
+        _ => unreachable!(),
+    };
+    let redirected = PuzzleJob {
+        address: "aleo1thief".to_string(),
+        ..job
+    };
+    let tampered = match redirected.to_message(true) {
+        StratumMessage::PuzzleNotify(a, b, c, d, e, f, _) => {
+            StratumMessage::PuzzleNotify(a, b, c, d, e, f, signature)
+        }
+        _ => unreachable!(),
+    };
+    assert!(verifier.verify(&tampered).is_err());
+}
+
+#[test]
+fn test_unsigned_notify_stays_compatible() {
+    let json = notify(100).to_json();
+    let value: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(value["params"].as_array().unwrap().len(), 8);
+
+    let signed = NotifySigner::generate().sign(notify(100)).to_json();
+    let value: serde_json::Value = serde_json::from_slice(&signed).unwrap();
+    assert_eq!(value["params"].as_array().unwrap().len(), 9);
+}
+
+#[test]
+fn test_load_or_generate() {
+    let path = std::env::temp_dir().join(format!("notify-key-{}", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let signer = NotifySigner::load_or_generate(&path).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    let loaded = NotifySigner::load_or_generate(&path).unwrap();
+    assert_eq!(loaded.public_key_hex(), signer.public_key_hex());
+    fs::remove_file(&path).unwrap();
+}
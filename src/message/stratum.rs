@@ -1,5 +1,5 @@
 use super::response::ResponseMessage;
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use json_rpc_types::{Error, Id, Request, Response, Version};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -13,19 +13,43 @@ pub enum StratumMessage {
     /// (id, account_name, miner_name, worker_password)
     Authorize(Id, String, String, Option<String>),
 
-    #[deprecated(since="0.2.0", note="difficulty_target will be sent with Notify")]
+    #[deprecated(since = "0.2.0", note = "difficulty_target will be sent with Notify")]
     /// This is the difficulty target for the next job.
     /// (difficulty_target)
     SetTarget(u64),
 
     /// New job from the mining pool.
     /// (job_id, difficulty_target, block_header_root, hashed_leaves_1, hashed_leaves_2, hashed_leaves_3,
-    ///  hashed_leaves_4, clean_jobs)
-    Notify(String, u64, String, String, String, String, String, bool),
+    ///  hashed_leaves_4, clean_jobs, signature)
+    ///
+    /// The signature is only sent to miners that negotiated it, see `message::signing`.
+    Notify(String, u64, String, String, String, String, String, bool, Option<String>),
+
+    /// New testnet3 coinbase puzzle job, sent instead of `Notify` on sessions that negotiated
+    /// it, see `message::puzzle`.
+    /// (job_id, epoch_number, epoch_challenge, address, proof_target, clean_jobs, signature)
+    ///
+    /// Like `Notify`, the signature is only sent to miners that negotiated it.
+    PuzzleNotify(String, u32, String, String, u64, bool, Option<String>),
+
+    /// The puzzle moved to a new epoch: work on earlier epochs is stale, while shares for
+    /// earlier blocks of the current epoch are still accepted. See `utils::job_registry`.
+    /// (epoch_number)
+    SetEpoch(u32),
+
+    /// Moves the session to another nonce range, see `message::extranonce`.
+    /// (extranonce_prefix, extranonce_size)
+    SetExtranonce(String, Option<u32>),
 
     /// Submit shares to the pool.
-    /// (id, job_id, nonce, proof)
-    Submit(Id, String, String, String),
+    /// (id, job_id, nonce, proof, worker_name)
+    ///
+    /// The worker name is only sent on sessions with several workers, see `message::workers`.
+    Submit(Id, String, String, String, Option<String>),
+
+    /// Submit a testnet3 prover solution for a `PuzzleNotify` job.
+    /// (id, job_id, prover_solution, worker_name)
+    SubmitSolution(Id, String, String, Option<String>),
 
     /// (id, result, error)
     Response(Id, Option<ResponseMessage>, Option<Error<()>>),
@@ -38,38 +62,18 @@ impl StratumMessage {
             StratumMessage::Authorize(..) => "mining.authorize",
             StratumMessage::SetTarget(..) => "mining.set_target",
             StratumMessage::Notify(..) => "mining.notify",
+            StratumMessage::PuzzleNotify(..) => "mining.notify",
+            StratumMessage::SetEpoch(..) => "mining.set_epoch",
+            StratumMessage::SetExtranonce(..) => "mining.set_extranonce",
             StratumMessage::Submit(..) => "mining.submit",
+            StratumMessage::SubmitSolution(..) => "mining.submit_solution",
             StratumMessage::Response(..) => "mining.response",
         }
     }
-}
-
-pub struct StratumCodec {
-    pub codec: AnyDelimiterCodec,
-}
-
-impl Default for StratumCodec {
-    fn default() -> Self {
-        Self {
-            codec: AnyDelimiterCodec::new_with_max_length(vec![b'\n'], vec![b'\n'], 4096),
-        }
-    }
-}
 
-#[derive(Serialize, Deserialize)]
-struct NotifyParams(String, u64, String, String, String, String, String, bool);
-
-#[derive(Serialize, Deserialize)]
-struct SubscribeParams(String, String, Option<String>);
-
-#[derive(Serialize, Deserialize)]
-struct AuthorizeParams(String, String, Option<String>);
-
-impl Encoder<StratumMessage> for StratumCodec {
-    type Error = io::Error;
-
-    fn encode(&mut self, item: StratumMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let bytes = match item {
+    /// JSON-RPC encoding of the message, without the line delimiter used by `StratumCodec`.
+    pub fn to_json(self) -> Vec<u8> {
+        match self {
             StratumMessage::Subscribe(id, user_agent, protocol_version, session_id) => {
                 let request = Request {
                     jsonrpc: Version::V2,
@@ -106,29 +110,122 @@ impl Encoder<StratumMessage> for StratumCodec {
                 hashed_leaves_3,
                 hashed_leaves_4,
                 clean_jobs,
+                signature,
             ) => {
+                // unsigned jobs keep the 8 params older miners expect
+                let request = match signature {
+                    None => serde_json::to_vec(&Request {
+                        jsonrpc: Version::V2,
+                        method: "mining.notify",
+                        params: Some(NotifyParams(
+                            job_id,
+                            difficulty_target,
+                            block_header_root,
+                            hashed_leaves_1,
+                            hashed_leaves_2,
+                            hashed_leaves_3,
+                            hashed_leaves_4,
+                            clean_jobs,
+                        )),
+                        id: None,
+                    }),
+                    Some(signature) => serde_json::to_vec(&Request {
+                        jsonrpc: Version::V2,
+                        method: "mining.notify",
+                        params: Some(SignedNotifyParams(
+                            job_id,
+                            difficulty_target,
+                            block_header_root,
+                            hashed_leaves_1,
+                            hashed_leaves_2,
+                            hashed_leaves_3,
+                            hashed_leaves_4,
+                            clean_jobs,
+                            signature,
+                        )),
+                        id: None,
+                    }),
+                };
+                request.unwrap_or_default()
+            }
+            StratumMessage::PuzzleNotify(
+                job_id,
+                epoch_number,
+                epoch_challenge,
+                address,
+                proof_target,
+                clean_jobs,
+                signature,
+            ) => {
+                let request = match signature {
+                    None => serde_json::to_vec(&Request {
+                        jsonrpc: Version::V2,
+                        method: "mining.notify",
+                        params: Some(PuzzleNotifyParams(
+                            job_id,
+                            epoch_number,
+                            epoch_challenge,
+                            address,
+                            proof_target,
+                            clean_jobs,
+                        )),
+                        id: None,
+                    }),
+                    Some(signature) => serde_json::to_vec(&Request {
+                        jsonrpc: Version::V2,
+                        method: "mining.notify",
+                        params: Some(SignedPuzzleNotifyParams(
+                            job_id,
+                            epoch_number,
+                            epoch_challenge,
+                            address,
+                            proof_target,
+                            clean_jobs,
+                            signature,
+                        )),
+                        id: None,
+                    }),
+                };
+                request.unwrap_or_default()
+            }
+            StratumMessage::SetEpoch(epoch_number) => {
                 let request = Request {
                     jsonrpc: Version::V2,
-                    method: "mining.notify",
-                    params: Some(NotifyParams(
-                        job_id,
-                        difficulty_target,
-                        block_header_root,
-                        hashed_leaves_1,
-                        hashed_leaves_2,
-                        hashed_leaves_3,
-                        hashed_leaves_4,
-                        clean_jobs,
-                    )),
+                    method: "mining.set_epoch",
+                    params: Some(vec![epoch_number]),
+                    id: None,
+                };
+                serde_json::to_vec(&request).unwrap_or_default()
+            }
+            StratumMessage::SetExtranonce(extranonce_prefix, extranonce_size) => {
+                let mut params = vec![Value::String(extranonce_prefix)];
+                params.extend(extranonce_size.map(Value::from));
+                let request = Request {
+                    jsonrpc: Version::V2,
+                    method: "mining.set_extranonce",
+                    params: Some(params),
                     id: None,
                 };
                 serde_json::to_vec(&request).unwrap_or_default()
             }
-            StratumMessage::Submit(id, job_id, nonce, proof) => {
+            StratumMessage::Submit(id, job_id, nonce, proof, worker_name) => {
+                let mut params = vec![job_id, nonce, proof];
+                params.extend(worker_name);
                 let request = Request {
                     jsonrpc: Version::V2,
                     method: "mining.submit",
-                    params: Some(vec![job_id, nonce, proof]),
+                    params: Some(params),
+                    id: Some(id),
+                };
+                serde_json::to_vec(&request).unwrap_or_default()
+            }
+            StratumMessage::SubmitSolution(id, job_id, prover_solution, worker_name) => {
+                let mut params = vec![job_id, prover_solution];
+                params.extend(worker_name);
+                let request = Request {
+                    jsonrpc: Version::V2,
+                    method: "mining.submit_solution",
+                    params: Some(params),
                     id: Some(id),
                 };
                 serde_json::to_vec(&request).unwrap_or_default()
@@ -147,30 +244,12 @@ impl Encoder<StratumMessage> for StratumCodec {
                     serde_json::to_vec(&response).unwrap_or_default()
                 }
             },
-        };
-        let string = std::str::from_utf8(&bytes)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-        self.codec
-            .encode(string, dst)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-        Ok(())
+        }
     }
-}
 
-impl Decoder for StratumCodec {
-    type Item = StratumMessage;
-    type Error = io::Error;
-
-    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
-        let string = self
-            .codec
-            .decode(src)
-            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-        if string.is_none() {
-            return Ok(None);
-        }
-        let bytes = string.unwrap();
-        let json = serde_json::from_slice::<Value>(&bytes)
+    /// Parses a single JSON-RPC message, without the line delimiter used by `StratumCodec`.
+    pub fn from_json(bytes: &[u8]) -> Result<Self, io::Error> {
+        let json = serde_json::from_slice::<Value>(bytes)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
         if !json.is_object() {
             return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an object"));
@@ -240,8 +319,37 @@ impl Decoder for StratumCodec {
                     let difficulty_target = unwrap_u64_value(&params[0])?;
                     StratumMessage::SetTarget(difficulty_target)
                 }
+                // testnet3 puzzle jobs have fewer params than block header jobs
+                "mining.notify" if params.len() == 6 || params.len() == 7 => {
+                    let job_id = unwrap_str_value(&params[0])?;
+                    let epoch_number = u32::try_from(unwrap_u64_value(&params[1])?)
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid params"))?;
+                    let epoch_challenge = unwrap_str_value(&params[2])?;
+                    let address = unwrap_str_value(&params[3])?;
+                    let proof_target = unwrap_u64_value(&params[4])?;
+                    let clean_jobs = unwrap_bool_value(&params[5])?;
+                    let signature = match params.get(6) {
+                        Some(Value::String(s)) => Some(s.clone()),
+                        Some(Value::Null) | None => None,
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "Invalid params",
+                            ));
+                        }
+                    };
+                    StratumMessage::PuzzleNotify(
+                        job_id,
+                        epoch_number,
+                        epoch_challenge,
+                        address,
+                        proof_target,
+                        clean_jobs,
+                        signature,
+                    )
+                }
                 "mining.notify" => {
-                    if params.len() != 8 {
+                    if params.len() != 8 && params.len() != 9 {
                         return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                     }
                     let job_id = unwrap_str_value(&params[0])?;
@@ -252,6 +360,16 @@ impl Decoder for StratumCodec {
                     let hashed_leaves_3 = unwrap_str_value(&params[5])?;
                     let hashed_leaves_4 = unwrap_str_value(&params[6])?;
                     let clean_jobs = unwrap_bool_value(&params[7])?;
+                    let signature = match params.get(8) {
+                        Some(Value::String(s)) => Some(s.clone()),
+                        Some(Value::Null) | None => None,
+                        _ => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "Invalid params",
+                            ));
+                        }
+                    };
 
                     StratumMessage::Notify(
                         job_id,
@@ -262,18 +380,69 @@ impl Decoder for StratumCodec {
                         hashed_leaves_3,
                         hashed_leaves_4,
                         clean_jobs,
+                        signature,
                     )
                 }
+                "mining.set_epoch" => {
+                    if params.len() != 1 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
+                    }
+                    let epoch_number = u32::try_from(unwrap_u64_value(&params[0])?)
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid params"))?;
+                    StratumMessage::SetEpoch(epoch_number)
+                }
+                "mining.set_extranonce" => {
+                    if params.is_empty() || params.len() > 2 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
+                    }
+                    let extranonce_prefix = unwrap_str_value(&params[0])?;
+                    let extranonce_size = match params.get(1) {
+                        Some(value) => Some(
+                            u32::try_from(unwrap_u64_value(value)?).map_err(|_| {
+                                io::Error::new(io::ErrorKind::InvalidData, "Invalid params")
+                            })?,
+                        ),
+                        None => None,
+                    };
+                    StratumMessage::SetExtranonce(extranonce_prefix, extranonce_size)
+                }
                 "mining.submit" => {
-                    if params.len() != 3 {
+                    if params.len() != 3 && params.len() != 4 {
                         return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                     }
 
                     let job_id = unwrap_str_value(&params[0])?;
                     let nonce = unwrap_str_value(&params[1])?;
                     let proof = unwrap_str_value(&params[2])?;
+                    let worker_name = match params.get(3) {
+                        Some(value) => Some(unwrap_str_value(value)?),
+                        None => None,
+                    };
 
-                    StratumMessage::Submit(id.unwrap_or(Id::Num(0)), job_id, nonce, proof)
+                    StratumMessage::Submit(
+                        id.unwrap_or(Id::Num(0)),
+                        job_id,
+                        nonce,
+                        proof,
+                        worker_name,
+                    )
+                }
+                "mining.submit_solution" => {
+                    if params.len() != 2 && params.len() != 3 {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
+                    }
+                    let job_id = unwrap_str_value(&params[0])?;
+                    let prover_solution = unwrap_str_value(&params[1])?;
+                    let worker_name = match params.get(2) {
+                        Some(value) => Some(unwrap_str_value(value)?),
+                        None => None,
+                    };
+                    StratumMessage::SubmitSolution(
+                        id.unwrap_or(Id::Num(0)),
+                        job_id,
+                        prover_solution,
+                        worker_name,
+                    )
                 }
                 _ => {
                     return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown method"));
@@ -290,7 +459,116 @@ impl Decoder for StratumCodec {
                 Err(error) => StratumMessage::Response(id.unwrap_or(Id::Num(0)), None, Some(error)),
             }
         };
-        Ok(Some(result))
+        Ok(result)
+    }
+}
+
+/// Longest line `StratumCodec` accepts, without the delimiter.
+pub const MAX_STRATUM_LINE_LENGTH: usize = 4096;
+
+pub struct StratumCodec {
+    pub codec: AnyDelimiterCodec,
+}
+
+impl Default for StratumCodec {
+    fn default() -> Self {
+        Self {
+            codec: AnyDelimiterCodec::new_with_max_length(
+                vec![b'\n'],
+                vec![b'\n'],
+                MAX_STRATUM_LINE_LENGTH,
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct NotifyParams(String, u64, String, String, String, String, String, bool);
+
+#[derive(Serialize)]
+struct PuzzleNotifyParams(String, u32, String, String, u64, bool);
+
+#[derive(Serialize)]
+struct SignedPuzzleNotifyParams(String, u32, String, String, u64, bool, String);
+
+#[derive(Serialize)]
+struct SignedNotifyParams(String, u64, String, String, String, String, String, bool, String);
+
+#[derive(Serialize, Deserialize)]
+struct SubscribeParams(String, String, Option<String>);
+
+#[derive(Serialize, Deserialize)]
+struct AuthorizeParams(String, String, Option<String>);
+
+impl Encoder<StratumMessage> for StratumCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: StratumMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let bytes = item.to_json();
+        let string = std::str::from_utf8(&bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        self.codec
+            .encode(string, dst)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// A `StratumMessage` that has already been encoded into its wire form.
+///
+/// Broadcasting a `Notify` to many connections only needs to serialize the message once:
+/// clone the frame (a cheap reference-counted copy) and send it through the codec, or write
+/// `as_ref()` straight to a socket.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedFrame(Bytes);
+
+impl EncodedFrame {
+    pub fn new(item: StratumMessage) -> Result<Self, io::Error> {
+        let mut buf = BytesMut::new();
+        StratumCodec::default().encode(item, &mut buf)?;
+        Ok(Self(buf.freeze()))
+    }
+
+    pub fn into_bytes(self) -> Bytes {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for EncodedFrame {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<EncodedFrame> for Bytes {
+    fn from(frame: EncodedFrame) -> Self {
+        frame.0
+    }
+}
+
+impl Encoder<EncodedFrame> for StratumCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: EncodedFrame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        dst.extend_from_slice(&item.0);
+        Ok(())
+    }
+}
+
+impl Decoder for StratumCodec {
+    type Item = StratumMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let string = self
+            .codec
+            .decode(src)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        if string.is_none() {
+            return Ok(None);
+        }
+        let bytes = string.unwrap();
+        Ok(Some(StratumMessage::from_json(&bytes)?))
     }
 }
 
@@ -379,6 +657,7 @@ fn test_encode_decode() {
         "hashed_leaves_3".to_string(),
         "hashed_leaves_4".to_string(),
         false,
+        None,
     );
     let mut buf1 = BytesMut::new();
     codec.encode(msg, &mut buf1).unwrap();
@@ -387,20 +666,77 @@ fn test_encode_decode() {
     codec.encode(res, &mut buf2).unwrap();
     assert_eq!(buf1, buf2);
 
-    // Submit
-    let msg = StratumMessage::Submit(
-        Id::Num(0),
+    // PuzzleNotify
+    let msg = StratumMessage::PuzzleNotify(
         "job_id".to_string(),
-        "nonce".to_string(),
-        "proof".to_string(),
+        7,
+        "epoch_challenge".to_string(),
+        "aleo1address".to_string(),
+        u64::MAX / 4,
+        true,
+        None,
     );
     let mut buf1 = BytesMut::new();
     codec.encode(msg, &mut buf1).unwrap();
     let res = codec.decode(&mut buf1.clone()).unwrap().unwrap();
+    assert!(matches!(res, StratumMessage::PuzzleNotify(..)));
     let mut buf2 = BytesMut::new();
     codec.encode(res, &mut buf2).unwrap();
     assert_eq!(buf1, buf2);
 
+    // SetEpoch
+    let msg = StratumMessage::SetEpoch(8);
+    let mut buf1 = BytesMut::new();
+    codec.encode(msg, &mut buf1).unwrap();
+    let res = codec.decode(&mut buf1.clone()).unwrap().unwrap();
+    let mut buf2 = BytesMut::new();
+    codec.encode(res, &mut buf2).unwrap();
+    assert_eq!(buf1, buf2);
+
+    // SetExtranonce
+    for extranonce_size in [None, Some(6)] {
+        let msg = StratumMessage::SetExtranonce("abcd".to_string(), extranonce_size);
+        let mut buf1 = BytesMut::new();
+        codec.encode(msg, &mut buf1).unwrap();
+        let res = codec.decode(&mut buf1.clone()).unwrap().unwrap();
+        let mut buf2 = BytesMut::new();
+        codec.encode(res, &mut buf2).unwrap();
+        assert_eq!(buf1, buf2);
+    }
+
+    // Submit
+    for worker_name in [None, Some("account.rig0".to_string())] {
+        let msg = StratumMessage::Submit(
+            Id::Num(0),
+            "job_id".to_string(),
+            "nonce".to_string(),
+            "proof".to_string(),
+            worker_name,
+        );
+        let mut buf1 = BytesMut::new();
+        codec.encode(msg, &mut buf1).unwrap();
+        let res = codec.decode(&mut buf1.clone()).unwrap().unwrap();
+        let mut buf2 = BytesMut::new();
+        codec.encode(res, &mut buf2).unwrap();
+        assert_eq!(buf1, buf2);
+    }
+
+    // SubmitSolution
+    for worker_name in [None, Some("account.rig0".to_string())] {
+        let msg = StratumMessage::SubmitSolution(
+            Id::Num(0),
+            "job_id".to_string(),
+            "prover_solution".to_string(),
+            worker_name,
+        );
+        let mut buf1 = BytesMut::new();
+        codec.encode(msg, &mut buf1).unwrap();
+        let res = codec.decode(&mut buf1.clone()).unwrap().unwrap();
+        let mut buf2 = BytesMut::new();
+        codec.encode(res, &mut buf2).unwrap();
+        assert_eq!(buf1, buf2);
+    }
+
     // Response(Id, Option<ResponseMessage>, Option<Error<()>>),
     let error = Error::with_custom_msg(
         ErrorCode::InvalidParams,
@@ -415,6 +751,43 @@ fn test_encode_decode() {
     assert_eq!(buf1, buf2);
 }
 
+#[test]
+fn test_encoded_frame() {
+    let notify = || {
+        StratumMessage::Notify(
+            "job_id".to_string(),
+            u64::MAX / 2,
+            "block_header_root".to_string(),
+            "hashed_leaves_1".to_string(),
+            "hashed_leaves_2".to_string(),
+            "hashed_leaves_3".to_string(),
+            "hashed_leaves_4".to_string(),
+            true,
+            None,
+        )
+    };
+
+    let mut codec = StratumCodec::default();
+    let mut expected = BytesMut::new();
+    codec.encode(notify(), &mut expected).unwrap();
+
+    let frame = EncodedFrame::new(notify()).unwrap();
+    assert_eq!(frame.as_ref(), &expected[..]);
+
+    let mut buf = BytesMut::new();
+    for _ in 0..3 {
+        codec.encode(frame.clone(), &mut buf).unwrap();
+    }
+    for _ in 0..3 {
+        let res = codec.decode(&mut buf).unwrap().unwrap();
+        let mut reencoded = BytesMut::new();
+        codec.encode(res, &mut reencoded).unwrap();
+        assert_eq!(reencoded, expected);
+    }
+    assert!(buf.is_empty());
+    assert_eq!(Bytes::from(frame), expected.freeze());
+}
+
 #[test]
 fn test_request() {
     use crate::{MIN_SUPPORTED_PROTOCOL_VERSION, PROTOCOL_PREFIX};
@@ -0,0 +1,290 @@
+use super::error::PoolError;
+use anyhow::anyhow;
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Scope a worker token needs to submit shares.
+pub static SCOPE_SUBMIT: &str = "mining.submit";
+
+/// What a worker token allows, sent as the `mining.authorize` password.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenClaims {
+    pub account_name: String,
+    /// Miner names the token is valid for, `*` matches any run of characters
+    pub worker_pattern: String,
+    /// Unix time in seconds
+    pub expires_at: u64,
+    pub scopes: Vec<String>,
+}
+
+impl TokenClaims {
+    /// Claims valid for `ttl` from now, without any scope.
+    pub fn new(account_name: String, worker_pattern: String, ttl: Duration) -> Self {
+        Self {
+            account_name,
+            worker_pattern,
+            expires_at: unix_now() + ttl.as_secs(),
+            scopes: vec![],
+        }
+    }
+
+    pub fn with_scope(mut self, scope: &str) -> Self {
+        self.scopes.push(scope.to_string());
+        self
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= unix_now()
+    }
+
+    pub fn matches_worker(&self, miner_name: &str) -> bool {
+        matches_pattern(self.worker_pattern.as_bytes(), miner_name.as_bytes())
+    }
+
+    /// Checks the claims still allow `scope`.
+    pub fn check_scope(&self, scope: &str) -> Result<(), PoolError> {
+        if self.is_expired() {
+            Err(PoolError::TokenExpired)
+        } else if !self.has_scope(scope) {
+            Err(PoolError::Unauthorized(Some("missing scope".to_string())))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Shortest HMAC key `TokenAuthority` accepts, in bytes.
+pub const MIN_TOKEN_KEY_LENGTH: usize = 32;
+
+/// Issues and checks worker tokens with an HMAC-SHA256 key only the pool knows.
+///
+/// A token is `<hex json claims>.<hex mac>`.
+#[derive(Clone)]
+pub struct TokenAuthority {
+    key: Vec<u8>,
+}
+
+impl fmt::Debug for TokenAuthority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenAuthority").finish_non_exhaustive()
+    }
+}
+
+impl TokenAuthority {
+    /// Fails on keys shorter than `MIN_TOKEN_KEY_LENGTH`, anyone could forge their tokens.
+    pub fn new(key: &[u8]) -> anyhow::Result<Self> {
+        if key.len() < MIN_TOKEN_KEY_LENGTH {
+            return Err(anyhow!(
+                "token key must be at least {} bytes",
+                MIN_TOKEN_KEY_LENGTH
+            ));
+        }
+        Ok(Self { key: key.to_vec() })
+    }
+
+    pub fn generate() -> Self {
+        let mut key = [0u8; MIN_TOKEN_KEY_LENGTH];
+        OsRng.fill_bytes(&mut key);
+        Self { key: key.to_vec() }
+    }
+
+    pub fn from_key_hex(key: &str) -> anyhow::Result<Self> {
+        Self::new(&hex::decode(key.trim())?)
+    }
+
+    pub fn key_hex(&self) -> String {
+        hex::encode(&self.key)
+    }
+
+    pub fn issue(&self, claims: &TokenClaims) -> String {
+        let payload = serde_json::to_vec(claims).expect("claims serialize");
+        format!(
+            "{}.{}",
+            hex::encode(&payload),
+            hex::encode(self.mac(&payload))
+        )
+    }
+
+    /// The claims of a token this authority issued, `TokenExpired` once it has expired.
+    pub fn verify(&self, token: &str) -> Result<TokenClaims, PoolError> {
+        let invalid = || PoolError::Unauthorized(Some("invalid token".to_string()));
+        let (payload, mac) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = hex::decode(payload).map_err(|_| invalid())?;
+        let mac = hex::decode(mac).map_err(|_| invalid())?;
+        self.hmac(&payload)
+            .verify_slice(&mac)
+            .map_err(|_| invalid())?;
+        let claims: TokenClaims = serde_json::from_slice(&payload).map_err(|_| invalid())?;
+        if claims.is_expired() {
+            return Err(PoolError::TokenExpired);
+        }
+        Ok(claims)
+    }
+
+    /// Verifies the token of a `mining.authorize` from `account_name.miner_name`.
+    pub fn authorize(
+        &self,
+        token: &str,
+        account_name: &str,
+        miner_name: &str,
+    ) -> Result<TokenClaims, PoolError> {
+        let claims = self.verify(token)?;
+        if claims.account_name != account_name {
+            return Err(PoolError::Unauthorized(Some("wrong account".to_string())));
+        }
+        if !claims.matches_worker(miner_name) {
+            return Err(PoolError::Unauthorized(Some("wrong worker".to_string())));
+        }
+        Ok(claims)
+    }
+
+    fn hmac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac takes any key length");
+        mac.update(payload);
+        mac
+    }
+
+    fn mac(&self, payload: &[u8]) -> Vec<u8> {
+        self.hmac(payload).finalize().into_bytes().to_vec()
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// `*` matches any run of bytes. On a mismatch only the latest `*` is retried, one byte
+/// further along the name, so the work is bounded by `pattern.len() * name.len()`.
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // pattern position after the latest `*`, and the name position it is matched up to
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(c) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, n));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+#[test]
+fn test_worker_tokens() {
+    let authority = TokenAuthority::generate();
+    let claims = TokenClaims::new(
+        "account".to_string(),
+        "rig-*".to_string(),
+        Duration::from_secs(3600),
+    )
+    .with_scope(SCOPE_SUBMIT);
+    let token = authority.issue(&claims);
+
+    assert_eq!(
+        authority.authorize(&token, "account", "rig-1"),
+        Ok(claims.clone())
+    );
+    assert!(claims.check_scope(SCOPE_SUBMIT).is_ok());
+    assert!(matches!(
+        authority.authorize(&token, "other", "rig-1"),
+        Err(PoolError::Unauthorized(_))
+    ));
+    assert!(matches!(
+        authority.authorize(&token, "account", "gpu-1"),
+        Err(PoolError::Unauthorized(_))
+    ));
+
+    // another key, or edited claims, are rejected
+    let other = TokenAuthority::generate();
+    assert!(matches!(
+        other.verify(&token),
+        Err(PoolError::Unauthorized(_))
+    ));
+    let (_, mac) = token.split_once('.').unwrap();
+    let mut forged = claims.clone();
+    forged.worker_pattern = "*".to_string();
+    let forged = format!(
+        "{}.{}",
+        hex::encode(serde_json::to_vec(&forged).unwrap()),
+        mac
+    );
+    assert!(matches!(
+        authority.verify(&forged),
+        Err(PoolError::Unauthorized(_))
+    ));
+
+    // expiry and scopes
+    let mut expired = claims.clone();
+    expired.expires_at = unix_now() - 1;
+    assert_eq!(
+        authority.verify(&authority.issue(&expired)),
+        Err(PoolError::TokenExpired)
+    );
+    assert_eq!(
+        expired.check_scope(SCOPE_SUBMIT),
+        Err(PoolError::TokenExpired)
+    );
+    let read_only = TokenClaims::new(
+        "account".to_string(),
+        "*".to_string(),
+        Duration::from_secs(60),
+    );
+    assert!(matches!(
+        read_only.check_scope(SCOPE_SUBMIT),
+        Err(PoolError::Unauthorized(Some(_)))
+    ));
+
+    let restored = TokenAuthority::from_key_hex(&authority.key_hex()).unwrap();
+    assert!(restored.verify(&token).is_ok());
+
+    // an empty or short key would let anyone mint tokens
+    assert!(TokenAuthority::from_key_hex("").is_err());
+    assert!(TokenAuthority::from_key_hex(&"ab".repeat(MIN_TOKEN_KEY_LENGTH - 1)).is_err());
+    assert!(TokenAuthority::new(&[7; MIN_TOKEN_KEY_LENGTH]).is_ok());
+}
+
+#[test]
+fn test_worker_patterns() {
+    assert!(matches_pattern(b"*", b""));
+    assert!(matches_pattern(b"rig-*", b"rig-1"));
+    assert!(matches_pattern(b"*-gpu-*", b"a-gpu-7"));
+    assert!(matches_pattern(b"rig", b"rig"));
+    assert!(!matches_pattern(b"rig", b"rig1"));
+    assert!(!matches_pattern(b"rig-*", b"gpu-1"));
+    assert!(matches_pattern(b"a*b*c", b"aXbYbZc"));
+    assert!(matches_pattern(b"**", b"rig"));
+    assert!(!matches_pattern(b"*a", b"b"));
+    assert!(!matches_pattern(b"a*b", b"a"));
+
+    // many stars against a long near miss stay fast
+    let pattern = "*a".repeat(32);
+    let name = "a".repeat(4096) + "b";
+    assert!(!matches_pattern(pattern.as_bytes(), name.as_bytes()));
+}
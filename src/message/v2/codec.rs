@@ -0,0 +1,450 @@
+use super::*;
+use bytes::{Buf, BufMut, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Largest payload accepted, well below what the `u24` length field allows.
+pub const MAX_V2_PAYLOAD_LENGTH: usize = 1 << 20;
+
+const HEADER_LENGTH: usize = 6;
+
+/// Frames `V2Message`s, see the module documentation for the layout.
+///
+/// Strings are `u8` length prefixed, the nonce too, and the proof `u16` length prefixed.
+/// Header root and hashed leaves are fixed 32 byte fields.
+#[derive(Default)]
+pub struct V2Codec;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn put_str(dst: &mut BytesMut, s: &str) -> Result<(), io::Error> {
+    put_bytes_u8(dst, s.as_bytes())
+}
+
+fn put_bytes_u8(dst: &mut BytesMut, b: &[u8]) -> Result<(), io::Error> {
+    if b.len() > u8::MAX as usize {
+        return Err(invalid("Field longer than 255 bytes"));
+    }
+    dst.put_u8(b.len() as u8);
+    dst.put_slice(b);
+    Ok(())
+}
+
+fn put_bytes_u16(dst: &mut BytesMut, b: &[u8]) -> Result<(), io::Error> {
+    if b.len() > u16::MAX as usize {
+        return Err(invalid("Field longer than 65535 bytes"));
+    }
+    dst.put_u16_le(b.len() as u16);
+    dst.put_slice(b);
+    Ok(())
+}
+
+/// Bounds-checked little endian reads over a payload.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], io::Error> {
+        if self.0.len() < n {
+            return Err(invalid("Truncated payload"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8, io::Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, io::Error> {
+        Ok(self.take(2)?.get_u16_le())
+    }
+
+    fn u32(&mut self) -> Result<u32, io::Error> {
+        Ok(self.take(4)?.get_u32_le())
+    }
+
+    fn u64(&mut self) -> Result<u64, io::Error> {
+        Ok(self.take(8)?.get_u64_le())
+    }
+
+    fn bool(&mut self) -> Result<bool, io::Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("Invalid bool")),
+        }
+    }
+
+    fn u256(&mut self) -> Result<[u8; 32], io::Error> {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(self.take(32)?);
+        Ok(out)
+    }
+
+    fn bytes_u8(&mut self) -> Result<Vec<u8>, io::Error> {
+        let len = self.u8()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn bytes_u16(&mut self) -> Result<Vec<u8>, io::Error> {
+        let len = self.u16()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn str(&mut self) -> Result<String, io::Error> {
+        String::from_utf8(self.bytes_u8()?).map_err(|e| invalid(&e.to_string()))
+    }
+}
+
+fn encode_payload(item: V2Message, body: &mut BytesMut) -> Result<(), io::Error> {
+    match item {
+        V2Message::SetupConnection(m) => {
+            body.put_u32_le(m.request_id);
+            body.put_u16_le(m.min_version);
+            body.put_u16_le(m.max_version);
+            body.put_u32_le(m.flags);
+            put_str(body, &m.user_agent)?;
+        }
+        V2Message::SetupConnectionSuccess(m) => {
+            body.put_u32_le(m.request_id);
+            body.put_u16_le(m.used_version);
+            body.put_u32_le(m.flags);
+        }
+        V2Message::SetupConnectionError(m) => {
+            body.put_u32_le(m.request_id);
+            body.put_u32_le(m.flags);
+            put_str(body, &m.error_code)?;
+        }
+        V2Message::OpenMiningChannel(m) => {
+            body.put_u32_le(m.request_id);
+            put_str(body, &m.account)?;
+            put_str(body, &m.worker)?;
+            put_str(body, &m.password)?;
+        }
+        V2Message::OpenMiningChannelSuccess(m) => {
+            body.put_u32_le(m.request_id);
+            body.put_u32_le(m.channel_id);
+            body.put_u64_le(m.target);
+        }
+        V2Message::OpenMiningChannelError(m) => {
+            body.put_u32_le(m.request_id);
+            put_str(body, &m.error_code)?;
+        }
+        V2Message::NewMiningJob(m) => {
+            body.put_u32_le(m.channel_id);
+            body.put_u32_le(m.job_id);
+            body.put_u32_le(m.height);
+            body.put_u64_le(m.target);
+            body.put_u8(m.clean_jobs as u8);
+            body.put_slice(&m.header_root);
+            for leaf in &m.hashed_leaves {
+                body.put_slice(leaf);
+            }
+        }
+        V2Message::SetTarget(m) => {
+            body.put_u32_le(m.channel_id);
+            body.put_u64_le(m.target);
+        }
+        V2Message::SetExtranoncePrefix(m) => {
+            body.put_u32_le(m.channel_id);
+            put_bytes_u8(body, &m.extranonce_prefix)?;
+        }
+        V2Message::SubmitShares(m) => {
+            body.put_u32_le(m.channel_id);
+            body.put_u32_le(m.sequence_number);
+            body.put_u32_le(m.job_id);
+            put_bytes_u8(body, &m.nonce)?;
+            put_bytes_u16(body, &m.proof)?;
+        }
+        V2Message::SubmitSharesSuccess(m) => {
+            body.put_u32_le(m.channel_id);
+            body.put_u32_le(m.sequence_number);
+        }
+        V2Message::SubmitSharesError(m) => {
+            body.put_u32_le(m.channel_id);
+            body.put_u32_le(m.sequence_number);
+            put_str(body, &m.error_code)?;
+        }
+    }
+    Ok(())
+}
+
+fn decode_payload(message_type: u8, r: &mut Reader) -> Result<V2Message, io::Error> {
+    let msg = match message_type {
+        MSG_SETUP_CONNECTION => V2Message::SetupConnection(SetupConnection {
+            request_id: r.u32()?,
+            min_version: r.u16()?,
+            max_version: r.u16()?,
+            flags: r.u32()?,
+            user_agent: r.str()?,
+        }),
+        MSG_SETUP_CONNECTION_SUCCESS => V2Message::SetupConnectionSuccess(SetupConnectionSuccess {
+            request_id: r.u32()?,
+            used_version: r.u16()?,
+            flags: r.u32()?,
+        }),
+        MSG_SETUP_CONNECTION_ERROR => V2Message::SetupConnectionError(SetupConnectionError {
+            request_id: r.u32()?,
+            flags: r.u32()?,
+            error_code: r.str()?,
+        }),
+        MSG_OPEN_MINING_CHANNEL => V2Message::OpenMiningChannel(OpenMiningChannel {
+            request_id: r.u32()?,
+            account: r.str()?,
+            worker: r.str()?,
+            password: r.str()?,
+        }),
+        MSG_OPEN_MINING_CHANNEL_SUCCESS => {
+            V2Message::OpenMiningChannelSuccess(OpenMiningChannelSuccess {
+                request_id: r.u32()?,
+                channel_id: r.u32()?,
+                target: r.u64()?,
+            })
+        }
+        MSG_OPEN_MINING_CHANNEL_ERROR => {
+            V2Message::OpenMiningChannelError(OpenMiningChannelError {
+                request_id: r.u32()?,
+                error_code: r.str()?,
+            })
+        }
+        MSG_NEW_MINING_JOB => V2Message::NewMiningJob(NewMiningJob {
+            channel_id: r.u32()?,
+            job_id: r.u32()?,
+            height: r.u32()?,
+            target: r.u64()?,
+            clean_jobs: r.bool()?,
+            header_root: r.u256()?,
+            hashed_leaves: [r.u256()?, r.u256()?, r.u256()?, r.u256()?],
+        }),
+        MSG_SET_TARGET => V2Message::SetTarget(SetTarget {
+            channel_id: r.u32()?,
+            target: r.u64()?,
+        }),
+        MSG_SET_EXTRANONCE_PREFIX => V2Message::SetExtranoncePrefix(SetExtranoncePrefix {
+            channel_id: r.u32()?,
+            extranonce_prefix: r.bytes_u8()?,
+        }),
+        MSG_SUBMIT_SHARES => V2Message::SubmitShares(SubmitShares {
+            channel_id: r.u32()?,
+            sequence_number: r.u32()?,
+            job_id: r.u32()?,
+            nonce: r.bytes_u8()?,
+            proof: r.bytes_u16()?,
+        }),
+        MSG_SUBMIT_SHARES_SUCCESS => V2Message::SubmitSharesSuccess(SubmitSharesSuccess {
+            channel_id: r.u32()?,
+            sequence_number: r.u32()?,
+        }),
+        MSG_SUBMIT_SHARES_ERROR => V2Message::SubmitSharesError(SubmitSharesError {
+            channel_id: r.u32()?,
+            sequence_number: r.u32()?,
+            error_code: r.str()?,
+        }),
+        _ => return Err(invalid("Unknown message type")),
+    };
+    Ok(msg)
+}
+
+impl Encoder<V2Message> for V2Codec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: V2Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let message_type = item.message_type();
+        let mut body = BytesMut::new();
+        encode_payload(item, &mut body)?;
+        if body.len() > MAX_V2_PAYLOAD_LENGTH {
+            return Err(invalid("Payload too long"));
+        }
+        dst.reserve(HEADER_LENGTH + body.len());
+        // no extensions are defined yet
+        dst.put_u16_le(0);
+        dst.put_u8(message_type);
+        dst.put_uint_le(body.len() as u64, 3);
+        dst.extend_from_slice(&body);
+        Ok(())
+    }
+}
+
+impl Decoder for V2Codec {
+    type Item = V2Message;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        let extension_type = u16::from_le_bytes([src[0], src[1]]);
+        let message_type = src[2];
+        let len = u32::from_le_bytes([src[3], src[4], src[5], 0]) as usize;
+        if extension_type != 0 {
+            return Err(invalid("Unsupported extension"));
+        }
+        if len > MAX_V2_PAYLOAD_LENGTH {
+            return Err(invalid("Payload too long"));
+        }
+        if src.len() < HEADER_LENGTH + len {
+            src.reserve(HEADER_LENGTH + len - src.len());
+            return Ok(None);
+        }
+        src.advance(HEADER_LENGTH);
+        let payload = src.split_to(len);
+        let mut r = Reader(&payload);
+        let msg = decode_payload(message_type, &mut r)?;
+        if !r.0.is_empty() {
+            return Err(invalid("Trailing bytes in payload"));
+        }
+        Ok(Some(msg))
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn all_messages() -> Vec<V2Message> {
+    let job = NewMiningJob {
+        channel_id: 7,
+        job_id: 42,
+        height: 685514,
+        target: u64::MAX / 4096,
+        clean_jobs: true,
+        header_root: [1; 32],
+        hashed_leaves: [[2; 32], [3; 32], [4; 32], [5; 32]],
+    };
+    vec![
+        V2Message::SetupConnection(SetupConnection {
+            request_id: 0,
+            min_version: V2_PROTOCOL_VERSION,
+            max_version: V2_PROTOCOL_VERSION,
+            flags: 0,
+            user_agent: "ABMatrix_Aleo_Miner".to_string(),
+        }),
+        V2Message::SetupConnectionSuccess(SetupConnectionSuccess {
+            request_id: 0,
+            used_version: V2_PROTOCOL_VERSION,
+            flags: 0,
+        }),
+        V2Message::SetupConnectionError(SetupConnectionError {
+            request_id: 0,
+            flags: 1,
+            error_code: "unsupported-protocol".to_string(),
+        }),
+        V2Message::OpenMiningChannel(OpenMiningChannel {
+            request_id: 1,
+            account: "aleo1account".to_string(),
+            worker: "rig-01".to_string(),
+            password: String::new(),
+        }),
+        V2Message::OpenMiningChannelSuccess(OpenMiningChannelSuccess {
+            request_id: 1,
+            channel_id: 7,
+            target: u64::MAX / 4096,
+        }),
+        V2Message::OpenMiningChannelError(OpenMiningChannelError {
+            request_id: 1,
+            error_code: "unknown-user".to_string(),
+        }),
+        V2Message::NewMiningJob(job),
+        V2Message::SetTarget(SetTarget {
+            channel_id: 7,
+            target: u64::MAX / 8192,
+        }),
+        V2Message::SetExtranoncePrefix(SetExtranoncePrefix {
+            channel_id: 7,
+            extranonce_prefix: vec![0xab, 0xcd],
+        }),
+        V2Message::SubmitShares(SubmitShares {
+            channel_id: 7,
+            sequence_number: 2,
+            job_id: 42,
+            nonce: vec![9; 8],
+            proof: vec![0xab; 691],
+        }),
+        V2Message::SubmitSharesSuccess(SubmitSharesSuccess {
+            channel_id: 7,
+            sequence_number: 2,
+        }),
+        V2Message::SubmitSharesError(SubmitSharesError {
+            channel_id: 7,
+            sequence_number: 3,
+            error_code: "StaleProof".to_string(),
+        }),
+    ]
+}
+
+#[test]
+fn test_v2_roundtrip() {
+    let mut codec = V2Codec;
+    let mut wire = BytesMut::new();
+    for msg in all_messages() {
+        codec.encode(msg, &mut wire).unwrap();
+    }
+
+    // byte by byte, every frame waits until complete
+    let mut src = BytesMut::new();
+    let mut decoded = vec![];
+    for byte in wire.iter() {
+        src.put_u8(*byte);
+        if let Some(msg) = codec.decode(&mut src).unwrap() {
+            decoded.push(msg);
+        }
+    }
+    assert!(src.is_empty());
+    assert_eq!(decoded, all_messages());
+}
+
+#[test]
+fn test_v2_wire_format() {
+    let mut dst = BytesMut::new();
+    let msg = V2Message::SubmitSharesSuccess(SubmitSharesSuccess {
+        channel_id: 7,
+        sequence_number: 0x0102,
+    });
+    V2Codec.encode(msg, &mut dst).unwrap();
+    assert_eq!(
+        &dst[..],
+        &[0, 0, 0x1c, 8, 0, 0, 7, 0, 0, 0, 0x02, 0x01, 0, 0][..]
+    );
+
+    let mut dst = BytesMut::new();
+    let msg = V2Message::SetupConnection(SetupConnection {
+        request_id: 1,
+        min_version: 2,
+        max_version: 2,
+        flags: 0,
+        user_agent: "ab".to_string(),
+    });
+    V2Codec.encode(msg, &mut dst).unwrap();
+    assert_eq!(
+        &dst[..],
+        &[0, 0, 0x00, 15, 0, 0, 1, 0, 0, 0, 2, 0, 2, 0, 0, 0, 0, 0, 2, b'a', b'b'][..]
+    );
+
+    // a job is a fixed 181 byte payload
+    let job = all_messages().remove(6);
+    let mut dst = BytesMut::new();
+    V2Codec.encode(job, &mut dst).unwrap();
+    assert_eq!(dst.len(), HEADER_LENGTH + 181);
+    assert_eq!(&dst[..6], &[0, 0, MSG_NEW_MINING_JOB, 181, 0, 0][..]);
+}
+
+#[test]
+fn test_v2_invalid_frames() {
+    let decode = |bytes: &[u8]| V2Codec.decode(&mut BytesMut::from(bytes));
+
+    // unknown message type
+    assert!(decode(&[0, 0, 0x7f, 0, 0, 0]).is_err());
+    // extension frames are not supported
+    assert!(decode(&[1, 0, 0x1c, 8, 0, 0, 7, 0, 0, 0, 2, 0, 0, 0]).is_err());
+    // payload shorter than the message
+    assert!(decode(&[0, 0, 0x1c, 4, 0, 0, 7, 0, 0, 0]).is_err());
+    // trailing bytes
+    assert!(decode(&[0, 0, 0x1c, 9, 0, 0, 7, 0, 0, 0, 2, 0, 0, 0, 0]).is_err());
+    // invalid bool in a job
+    let mut dst = BytesMut::new();
+    V2Codec.encode(all_messages().remove(6), &mut dst).unwrap();
+    dst[HEADER_LENGTH + 20] = 2;
+    assert!(V2Codec.decode(&mut dst).is_err());
+    // oversized length is rejected before buffering
+    assert!(decode(&[0, 0, 0x15, 0xff, 0xff, 0xff]).is_err());
+}
@@ -0,0 +1,187 @@
+//! Binary, channel based protocol modelled on Stratum V2 mining messages, with jobs carrying
+//! Aleo's block header root and hashed leaves instead of a Bitcoin header.
+//!
+//! Frames follow the Stratum V2 layout: `u16` extension type | `u8` message type | `u24`
+//! payload length | payload, all integers little endian. `translate` maps the messages to
+//! and from `StratumMessage`.
+
+pub mod codec;
+pub mod translate;
+
+pub use codec::V2Codec;
+
+/// Version negotiated in `SetupConnection`.
+pub const V2_PROTOCOL_VERSION: u16 = 2;
+
+pub const MSG_SETUP_CONNECTION: u8 = 0x00;
+pub const MSG_SETUP_CONNECTION_SUCCESS: u8 = 0x01;
+pub const MSG_SETUP_CONNECTION_ERROR: u8 = 0x02;
+pub const MSG_OPEN_MINING_CHANNEL: u8 = 0x10;
+pub const MSG_OPEN_MINING_CHANNEL_SUCCESS: u8 = 0x11;
+pub const MSG_OPEN_MINING_CHANNEL_ERROR: u8 = 0x12;
+pub const MSG_NEW_MINING_JOB: u8 = 0x15;
+pub const MSG_SET_EXTRANONCE_PREFIX: u8 = 0x19;
+pub const MSG_SUBMIT_SHARES: u8 = 0x1a;
+pub const MSG_SUBMIT_SHARES_SUCCESS: u8 = 0x1c;
+pub const MSG_SUBMIT_SHARES_ERROR: u8 = 0x1d;
+pub const MSG_SET_TARGET: u8 = 0x21;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetupConnection {
+    pub request_id: u32,
+    pub min_version: u16,
+    pub max_version: u16,
+    pub flags: u32,
+    pub user_agent: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetupConnectionSuccess {
+    pub request_id: u32,
+    pub used_version: u16,
+    pub flags: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetupConnectionError {
+    pub request_id: u32,
+    pub flags: u32,
+    pub error_code: String,
+}
+
+/// Opens a channel for one worker of an Aleo account. An empty password means none.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenMiningChannel {
+    pub request_id: u32,
+    pub account: String,
+    pub worker: String,
+    pub password: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenMiningChannelSuccess {
+    pub request_id: u32,
+    pub channel_id: u32,
+    pub target: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenMiningChannelError {
+    pub request_id: u32,
+    pub error_code: String,
+}
+
+/// An Aleo job: the block header root and its four hashed leaves, 32 bytes each.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NewMiningJob {
+    pub channel_id: u32,
+    pub job_id: u32,
+    pub height: u32,
+    pub target: u64,
+    pub clean_jobs: bool,
+    pub header_root: [u8; 32],
+    pub hashed_leaves: [[u8; 32]; 4],
+}
+
+/// Changes the channel's target for the current and following jobs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetTarget {
+    pub channel_id: u32,
+    pub target: u64,
+}
+
+/// Moves the channel to nonces starting with `extranonce_prefix`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetExtranoncePrefix {
+    pub channel_id: u32,
+    pub extranonce_prefix: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmitShares {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub job_id: u32,
+    pub nonce: Vec<u8>,
+    pub proof: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmitSharesSuccess {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+}
+
+/// `error_code` is a `PoolError` in its string form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubmitSharesError {
+    pub channel_id: u32,
+    pub sequence_number: u32,
+    pub error_code: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum V2Message {
+    SetupConnection(SetupConnection),
+    SetupConnectionSuccess(SetupConnectionSuccess),
+    SetupConnectionError(SetupConnectionError),
+    OpenMiningChannel(OpenMiningChannel),
+    OpenMiningChannelSuccess(OpenMiningChannelSuccess),
+    OpenMiningChannelError(OpenMiningChannelError),
+    NewMiningJob(NewMiningJob),
+    SetTarget(SetTarget),
+    SetExtranoncePrefix(SetExtranoncePrefix),
+    SubmitShares(SubmitShares),
+    SubmitSharesSuccess(SubmitSharesSuccess),
+    SubmitSharesError(SubmitSharesError),
+}
+
+impl V2Message {
+    pub fn message_type(&self) -> u8 {
+        match self {
+            V2Message::SetupConnection(..) => MSG_SETUP_CONNECTION,
+            V2Message::SetupConnectionSuccess(..) => MSG_SETUP_CONNECTION_SUCCESS,
+            V2Message::SetupConnectionError(..) => MSG_SETUP_CONNECTION_ERROR,
+            V2Message::OpenMiningChannel(..) => MSG_OPEN_MINING_CHANNEL,
+            V2Message::OpenMiningChannelSuccess(..) => MSG_OPEN_MINING_CHANNEL_SUCCESS,
+            V2Message::OpenMiningChannelError(..) => MSG_OPEN_MINING_CHANNEL_ERROR,
+            V2Message::NewMiningJob(..) => MSG_NEW_MINING_JOB,
+            V2Message::SetTarget(..) => MSG_SET_TARGET,
+            V2Message::SetExtranoncePrefix(..) => MSG_SET_EXTRANONCE_PREFIX,
+            V2Message::SubmitShares(..) => MSG_SUBMIT_SHARES,
+            V2Message::SubmitSharesSuccess(..) => MSG_SUBMIT_SHARES_SUCCESS,
+            V2Message::SubmitSharesError(..) => MSG_SUBMIT_SHARES_ERROR,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            V2Message::SetupConnection(..) => "SetupConnection",
+            V2Message::SetupConnectionSuccess(..) => "SetupConnection.Success",
+            V2Message::SetupConnectionError(..) => "SetupConnection.Error",
+            V2Message::OpenMiningChannel(..) => "OpenMiningChannel",
+            V2Message::OpenMiningChannelSuccess(..) => "OpenMiningChannel.Success",
+            V2Message::OpenMiningChannelError(..) => "OpenMiningChannel.Error",
+            V2Message::NewMiningJob(..) => "NewMiningJob",
+            V2Message::SetTarget(..) => "SetTarget",
+            V2Message::SetExtranoncePrefix(..) => "SetExtranoncePrefix",
+            V2Message::SubmitShares(..) => "SubmitShares",
+            V2Message::SubmitSharesSuccess(..) => "SubmitShares.Success",
+            V2Message::SubmitSharesError(..) => "SubmitShares.Error",
+        }
+    }
+
+    /// The channel a message belongs to, `None` for connection level messages.
+    pub fn channel_id(&self) -> Option<u32> {
+        match self {
+            V2Message::OpenMiningChannelSuccess(m) => Some(m.channel_id),
+            V2Message::NewMiningJob(m) => Some(m.channel_id),
+            V2Message::SetTarget(m) => Some(m.channel_id),
+            V2Message::SetExtranoncePrefix(m) => Some(m.channel_id),
+            V2Message::SubmitShares(m) => Some(m.channel_id),
+            V2Message::SubmitSharesSuccess(m) => Some(m.channel_id),
+            V2Message::SubmitSharesError(m) => Some(m.channel_id),
+            _ => None,
+        }
+    }
+}
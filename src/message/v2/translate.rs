@@ -0,0 +1,381 @@
+//! Mapping between `V2Message` and the JSON `StratumMessage` dialect.
+//!
+//! Requests and jobs translate one to one. Two messages need context the other side does
+//! not carry: a V1 `Response` only makes sense next to its request, see
+//! `submit_result_to_v2`, and a `SubmitShares` lacks the job height that V1 job ids
+//! include, see `submit_to_v1`.
+
+use super::*;
+use crate::message::error::PoolError;
+use crate::message::extranonce::NONCE_LENGTH;
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::StratumMessage;
+use crate::utils::job_id::get_height;
+use crate::MIN_SUPPORTED_PROTOCOL_VERSION;
+use json_rpc_types::{Error, ErrorCode, Id};
+use std::io;
+use std::str::FromStr;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// V1 job id of a V2 job, `<height as le hex>_<job id as 8 hex digits>`, so
+/// `utils::job_id::get_height` keeps working for legacy miners.
+pub fn job_id_to_v1(height: u32, job_id: u32) -> String {
+    format!("{}_{:08x}", hex::encode(height.to_le_bytes()), job_id)
+}
+
+/// Reverse of `job_id_to_v1`, returns `(height, job_id)`.
+pub fn job_id_to_v2(job_id: &str) -> io::Result<(u32, u32)> {
+    let height = get_height(job_id.to_string()).map_err(|e| invalid(&e.to_string()))?;
+    match job_id.split_once('_') {
+        Some((_, suffix)) if suffix.len() == 8 => {
+            let id = u32::from_str_radix(suffix, 16).map_err(|e| invalid(&e.to_string()))?;
+            Ok((height, id))
+        }
+        _ => Err(invalid("Invalid job_id")),
+    }
+}
+
+fn request_id(id: &Id) -> io::Result<u32> {
+    match id {
+        Id::Num(n) if *n <= u32::MAX as u64 => Ok(*n as u32),
+        _ => Err(invalid("Id does not fit a V2 request id")),
+    }
+}
+
+fn hex_32(s: &str) -> io::Result<[u8; 32]> {
+    let bytes = hex::decode(s).map_err(|e| invalid(&e.to_string()))?;
+    bytes
+        .try_into()
+        .map_err(|_| invalid("Expected 32 bytes of hex"))
+}
+
+/// V1 error for a V2 error code, `PoolError`s keep their id, anything else is reported as
+/// `InternalServerError` with the code as message.
+pub fn error_to_v1(error_code: &str) -> Error<()> {
+    match PoolError::from_str(error_code) {
+        Ok(e) => Error::with_custom_msg(ErrorCode::ServerError(e.id()), &e.to_string()),
+        Err(_) => Error::with_custom_msg(
+            ErrorCode::ServerError(PoolError::InternalServerError.id()),
+            error_code,
+        ),
+    }
+}
+
+/// Translates a V1 request, job or target for the given channel. `Response` is rejected,
+/// use `submit_result_to_v2`.
+pub fn to_v2(msg: StratumMessage, channel_id: u32) -> io::Result<V2Message> {
+    #[allow(deprecated)]
+    let msg = match msg {
+        StratumMessage::Subscribe(id, user_agent, _, _) => {
+            V2Message::SetupConnection(SetupConnection {
+                request_id: request_id(&id)?,
+                min_version: V2_PROTOCOL_VERSION,
+                max_version: V2_PROTOCOL_VERSION,
+                flags: 0,
+                user_agent,
+            })
+        }
+        StratumMessage::Authorize(id, account, worker, password) => {
+            V2Message::OpenMiningChannel(OpenMiningChannel {
+                request_id: request_id(&id)?,
+                account,
+                worker,
+                password: password.unwrap_or_default(),
+            })
+        }
+        StratumMessage::SetTarget(target) => V2Message::SetTarget(SetTarget { channel_id, target }),
+        StratumMessage::Notify(
+            job_id,
+            target,
+            block_header_root,
+            hashed_leaves_1,
+            hashed_leaves_2,
+            hashed_leaves_3,
+            hashed_leaves_4,
+            clean_jobs,
+            _,
+        ) => {
+            let (height, job_id) = job_id_to_v2(&job_id)?;
+            V2Message::NewMiningJob(NewMiningJob {
+                channel_id,
+                job_id,
+                height,
+                target,
+                clean_jobs,
+                header_root: hex_32(&block_header_root)?,
+                hashed_leaves: [
+                    hex_32(&hashed_leaves_1)?,
+                    hex_32(&hashed_leaves_2)?,
+                    hex_32(&hashed_leaves_3)?,
+                    hex_32(&hashed_leaves_4)?,
+                ],
+            })
+        }
+        StratumMessage::SetExtranonce(extranonce_prefix, extranonce_size) => {
+            let extranonce_prefix =
+                hex::decode(extranonce_prefix).map_err(|e| invalid(&e.to_string()))?;
+            let default_size = NONCE_LENGTH.saturating_sub(extranonce_prefix.len());
+            if extranonce_size.is_some_and(|size| size as usize != default_size) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "An extranonce size has no V2 counterpart",
+                ));
+            }
+            V2Message::SetExtranoncePrefix(SetExtranoncePrefix {
+                channel_id,
+                extranonce_prefix,
+            })
+        }
+        StratumMessage::Submit(id, job_id, nonce, proof, _) => {
+            V2Message::SubmitShares(SubmitShares {
+                channel_id,
+                sequence_number: request_id(&id)?,
+                job_id: job_id_to_v2(&job_id)?.1,
+                nonce: hex::decode(nonce).map_err(|e| invalid(&e.to_string()))?,
+                proof: hex::decode(proof).map_err(|e| invalid(&e.to_string()))?,
+            })
+        }
+        StratumMessage::PuzzleNotify(..)
+        | StratumMessage::SetEpoch(..)
+        | StratumMessage::SubmitSolution(..) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Coinbase puzzle messages have no V2 counterpart",
+            ))
+        }
+        StratumMessage::Response(..) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "A response needs its request to be translated",
+            ))
+        }
+    };
+    Ok(msg)
+}
+
+/// Translates a V1 submit result, reading it the way miners do: an error is a `PoolError`,
+/// `false` an invalid proof and anything else an accepted share.
+pub fn submit_result_to_v2(
+    channel_id: u32,
+    sequence_number: u32,
+    result: Option<ResponseMessage>,
+    error: Option<Error<()>>,
+) -> V2Message {
+    let error = match (error, result) {
+        (Some(error), _) => {
+            Some(PoolError::from_str(&error.message).unwrap_or(PoolError::InternalServerError))
+        }
+        (None, Some(ResponseMessage::Bool(false))) => Some(PoolError::InvalidProof(None)),
+        _ => None,
+    };
+    match error {
+        Some(error) => V2Message::SubmitSharesError(SubmitSharesError {
+            channel_id,
+            sequence_number,
+            error_code: error.to_string(),
+        }),
+        None => V2Message::SubmitSharesSuccess(SubmitSharesSuccess {
+            channel_id,
+            sequence_number,
+        }),
+    }
+}
+
+/// Translates a V2 message for a V1 peer. `SubmitShares` is rejected, use `submit_to_v1`.
+///
+/// Channel ids and the negotiated version have no V1 counterpart and are dropped, a
+/// `SetupConnection` is presented as a subscribe with `MIN_SUPPORTED_PROTOCOL_VERSION`.
+pub fn to_v1(msg: V2Message) -> io::Result<StratumMessage> {
+    let msg = match msg {
+        V2Message::SetupConnection(m) => StratumMessage::Subscribe(
+            Id::Num(m.request_id as u64),
+            m.user_agent,
+            MIN_SUPPORTED_PROTOCOL_VERSION.to_string(),
+            None,
+        ),
+        V2Message::SetupConnectionSuccess(m) => {
+            StratumMessage::Response(Id::Num(m.request_id as u64), None, None)
+        }
+        V2Message::SetupConnectionError(m) => StratumMessage::Response(
+            Id::Num(m.request_id as u64),
+            None,
+            Some(error_to_v1(&m.error_code)),
+        ),
+        V2Message::OpenMiningChannel(m) => StratumMessage::Authorize(
+            Id::Num(m.request_id as u64),
+            m.account,
+            m.worker,
+            Some(m.password).filter(|p| !p.is_empty()),
+        ),
+        V2Message::OpenMiningChannelSuccess(m) => StratumMessage::Response(
+            Id::Num(m.request_id as u64),
+            Some(ResponseMessage::Bool(true)),
+            None,
+        ),
+        V2Message::OpenMiningChannelError(m) => StratumMessage::Response(
+            Id::Num(m.request_id as u64),
+            None,
+            Some(error_to_v1(&m.error_code)),
+        ),
+        V2Message::NewMiningJob(m) => StratumMessage::Notify(
+            job_id_to_v1(m.height, m.job_id),
+            m.target,
+            hex::encode(m.header_root),
+            hex::encode(m.hashed_leaves[0]),
+            hex::encode(m.hashed_leaves[1]),
+            hex::encode(m.hashed_leaves[2]),
+            hex::encode(m.hashed_leaves[3]),
+            m.clean_jobs,
+            None,
+        ),
+        #[allow(deprecated)]
+        V2Message::SetTarget(m) => StratumMessage::SetTarget(m.target),
+        V2Message::SetExtranoncePrefix(m) => {
+            StratumMessage::SetExtranonce(hex::encode(m.extranonce_prefix), None)
+        }
+        V2Message::SubmitShares(..) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SubmitShares needs the job height to be translated",
+            ))
+        }
+        V2Message::SubmitSharesSuccess(m) => StratumMessage::Response(
+            Id::Num(m.sequence_number as u64),
+            Some(ResponseMessage::Bool(true)),
+            None,
+        ),
+        V2Message::SubmitSharesError(m) => StratumMessage::Response(
+            Id::Num(m.sequence_number as u64),
+            None,
+            Some(error_to_v1(&m.error_code)),
+        ),
+    };
+    Ok(msg)
+}
+
+/// `SubmitShares` as a V1 submit, given the height of the job it was mined on.
+pub fn submit_to_v1(m: SubmitShares, height: u32) -> StratumMessage {
+    StratumMessage::Submit(
+        Id::Num(m.sequence_number as u64),
+        job_id_to_v1(height, m.job_id),
+        hex::encode(m.nonce),
+        hex::encode(m.proof),
+        None,
+    )
+}
+
+#[test]
+fn test_job_id() {
+    let job_id = job_id_to_v1(685514, 0xdeadbeef);
+    assert_eq!(get_height(job_id.clone()).unwrap(), 685514);
+    assert_eq!(job_id_to_v2(&job_id).unwrap(), (685514, 0xdeadbeef));
+    assert!(job_id_to_v2("c275_0a").is_err());
+    assert!(job_id_to_v2(&format!("{}_xyz00000", hex::encode(1u32.to_le_bytes()))).is_err());
+}
+
+#[test]
+fn test_translation_roundtrip() {
+    use super::codec::all_messages;
+
+    for msg in all_messages() {
+        let channel_id = msg.channel_id().unwrap_or(7);
+        let expected = msg.clone();
+        let v1 = match msg {
+            V2Message::SubmitShares(m) => submit_to_v1(m, 685514),
+            msg => to_v1(msg).unwrap(),
+        };
+        let back = match v1 {
+            StratumMessage::Response(_, result, error) => {
+                let id = match &expected {
+                    V2Message::SubmitSharesSuccess(m) => m.sequence_number,
+                    V2Message::SubmitSharesError(m) => m.sequence_number,
+                    // connection and channel results need their request
+                    _ => continue,
+                };
+                submit_result_to_v2(channel_id, id, result, error)
+            }
+            v1 => to_v2(v1, channel_id).unwrap(),
+        };
+        assert_eq!(back, expected, "{}", expected.name());
+    }
+}
+
+#[test]
+fn test_translation_from_v1() {
+    let header_root = hex::encode([1u8; 32]);
+    let leaf = hex::encode([2u8; 32]);
+    let job_id = job_id_to_v1(685514, 3);
+    let notify = StratumMessage::Notify(
+        job_id.clone(),
+        u64::MAX / 4096,
+        header_root.clone(),
+        leaf.clone(),
+        leaf.clone(),
+        leaf.clone(),
+        leaf.clone(),
+        false,
+        None,
+    );
+    match to_v2(notify, 9).unwrap() {
+        V2Message::NewMiningJob(job) => {
+            assert_eq!(job.channel_id, 9);
+            assert_eq!(job.job_id, 3);
+            assert_eq!(job.height, 685514);
+            assert_eq!(job.header_root, [1u8; 32]);
+            assert_eq!(job.hashed_leaves, [[2u8; 32]; 4]);
+            assert!(!job.clean_jobs);
+        }
+        _ => panic!("expected a job"),
+    }
+
+    // job fields must be 32 bytes of hex
+    let notify = StratumMessage::Notify(
+        job_id.clone(),
+        0,
+        "block_header_root".to_string(),
+        leaf.clone(),
+        leaf.clone(),
+        leaf.clone(),
+        leaf,
+        true,
+        None,
+    );
+    assert!(to_v2(notify, 9).is_err());
+
+    // string ids have no V2 counterpart
+    let submit =
+        StratumMessage::Submit(Id::Str("a".into()), job_id, "00".into(), "00".into(), None);
+    assert!(to_v2(submit, 9).is_err());
+
+    let response = StratumMessage::Response(Id::Num(1), None, None);
+    assert!(to_v2(response, 9).is_err());
+
+    assert_eq!(
+        submit_result_to_v2(9, 4, Some(ResponseMessage::Bool(false)), None),
+        V2Message::SubmitSharesError(SubmitSharesError {
+            channel_id: 9,
+            sequence_number: 4,
+            error_code: PoolError::InvalidProof(None).to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_pool_errors_to_v1() {
+    let error = error_to_v1(&PoolError::InvalidProof(Some("bad nonce".to_string())).to_string());
+    assert_eq!(error.code, ErrorCode::ServerError(2));
+    assert_eq!(
+        PoolError::from_str(&error.message).unwrap(),
+        PoolError::InvalidProof(Some("bad nonce".to_string()))
+    );
+
+    let error = error_to_v1("unsupported-protocol");
+    assert_eq!(
+        error.code,
+        ErrorCode::ServerError(PoolError::InternalServerError.id())
+    );
+    assert_eq!(error.message.as_str(), "unsupported-protocol");
+}
@@ -0,0 +1,152 @@
+use super::error::PoolError;
+use super::response::ResponseMessage;
+use crate::utils::extension::{accepted_extensions, add_extension, has_extension};
+use std::collections::HashMap;
+
+/// Protocol extension for sessions carrying several workers. Each worker sends its own
+/// `mining.authorize`, and every `mining.submit` names the worker it is from, see
+/// `worker_name`. Without it a session has a single worker and submits name none.
+pub static WORKERS_PROTOCOL_EXTENSION: &str = "workers";
+
+pub fn advertise_workers(protocol_version: &str) -> String {
+    add_extension(protocol_version, WORKERS_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version offers several workers per session.
+pub fn supports_workers(protocol_version: &str) -> bool {
+    has_extension(protocol_version, WORKERS_PROTOCOL_EXTENSION)
+}
+
+/// Whether the pool accepts several workers on the session.
+pub fn workers_accepted(result: &Option<ResponseMessage>) -> bool {
+    accepted_extensions(result)
+        .iter()
+        .any(|e| e == WORKERS_PROTOCOL_EXTENSION)
+}
+
+/// The name a submit uses for the worker authorized as `account_name` and `miner_name`.
+pub fn worker_name(account_name: &str, miner_name: &str) -> String {
+    format!("{}.{}", account_name, miner_name)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WorkerShares {
+    pub submitted: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+}
+
+/// The workers authorized on one session, and the shares attributed to each.
+#[derive(Clone, Debug, Default)]
+pub struct WorkerSession {
+    workers: HashMap<String, WorkerShares>,
+}
+
+impl WorkerSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a worker to the session and returns its name.
+    pub fn authorize(&mut self, account_name: &str, miner_name: &str) -> String {
+        let name = worker_name(account_name, miner_name);
+        self.workers.entry(name.clone()).or_default();
+        name
+    }
+
+    pub fn is_authorized(&self, worker_name: &str) -> bool {
+        self.workers.contains_key(worker_name)
+    }
+
+    pub fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    pub fn shares(&self, worker_name: &str) -> Option<&WorkerShares> {
+        self.workers.get(worker_name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &WorkerShares)> {
+        self.workers.iter()
+    }
+
+    /// The worker a submit is attributed to: the one it names, or the only worker of a
+    /// session that has one. Counts the share as submitted.
+    pub fn submit(&mut self, worker_name: Option<&str>) -> Result<String, PoolError> {
+        let name = match worker_name {
+            Some(name) if self.is_authorized(name) => name.to_string(),
+            Some(_) => return Err(PoolError::Unauthorized(Some("unknown worker".to_string()))),
+            None if self.workers.len() == 1 => self.workers.keys().next().unwrap().clone(),
+            None if self.workers.is_empty() => return Err(PoolError::Unauthorized(None)),
+            None => return Err(PoolError::Unauthorized(Some("missing worker".to_string()))),
+        };
+        self.workers.get_mut(&name).unwrap().submitted += 1;
+        Ok(name)
+    }
+
+    /// Counts the pool's verdict on a share of `worker_name`.
+    pub fn record(&mut self, worker_name: &str, accepted: bool) {
+        if let Some(shares) = self.workers.get_mut(worker_name) {
+            if accepted {
+                shares.accepted += 1;
+            } else {
+                shares.rejected += 1;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_worker_session() {
+    let mut session = WorkerSession::new();
+    assert_eq!(session.submit(None), Err(PoolError::Unauthorized(None)));
+
+    // a single worker may leave its name out
+    let rig0 = session.authorize("account", "rig0");
+    assert_eq!(rig0, "account.rig0");
+    assert_eq!(session.submit(None).unwrap(), rig0);
+    session.record(&rig0, true);
+
+    // with several, submits must say whose they are
+    let rig1 = session.authorize("account", "rig1");
+    assert_eq!(session.authorize("account", "rig1"), rig1);
+    assert_eq!(session.len(), 2);
+    assert!(session.submit(None).is_err());
+    assert!(session.submit(Some("account.rig2")).is_err());
+    assert_eq!(session.submit(Some(&rig1)).unwrap(), rig1);
+    session.record(&rig1, false);
+
+    assert_eq!(
+        session.shares(&rig0),
+        Some(&WorkerShares {
+            submitted: 1,
+            accepted: 1,
+            rejected: 0
+        })
+    );
+    assert_eq!(
+        session.shares(&rig1),
+        Some(&WorkerShares {
+            submitted: 1,
+            accepted: 0,
+            rejected: 1
+        })
+    );
+}
+
+#[test]
+fn test_workers_negotiation() {
+    use crate::utils::extension::accept_extensions;
+
+    assert_eq!(advertise_workers("0.2.0+binary"), "0.2.0+binary.workers");
+    assert!(supports_workers(&advertise_workers("0.2.0")));
+    assert!(!supports_workers("0.2.0"));
+    assert!(workers_accepted(&Some(accept_extensions(&[
+        WORKERS_PROTOCOL_EXTENSION
+    ]))));
+    assert!(!workers_accepted(&None));
+}
@@ -0,0 +1,411 @@
+pub mod script;
+
+use crate::message::error::PoolError;
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::{StratumCodec, StratumMessage};
+use anyhow::anyhow;
+use futures_util::{SinkExt, StreamExt};
+use json_rpc_types::{Error, ErrorCode, Id};
+use script::{Script, Step};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task;
+use tokio::time::{sleep_until, Instant};
+use tokio_util::codec::Framed;
+
+#[derive(Clone, Debug, Default)]
+pub struct MockPoolConfig {
+    pub script: Script,
+    /// When set, every submit is appended to this file as a JSON line
+    pub submissions_file: Option<PathBuf>,
+}
+
+impl MockPoolConfig {
+    pub fn new(script: Script) -> Self {
+        Self {
+            script,
+            submissions_file: None,
+        }
+    }
+}
+
+/// A share a miner submitted to the mock pool, and how the pool answered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Submission {
+    pub peer: SocketAddr,
+    pub account_name: String,
+    pub miner_name: String,
+    pub worker_name: Option<String>,
+    pub job_id: String,
+    pub nonce: String,
+    pub proof: String,
+    pub result: Result<(), PoolError>,
+}
+
+impl Submission {
+    fn to_json_line(&self) -> String {
+        let result = match &self.result {
+            Ok(()) => "accepted".to_string(),
+            Err(e) => e.to_string(),
+        };
+        serde_json::json!({
+            "peer": self.peer.to_string(),
+            "account_name": self.account_name,
+            "miner_name": self.miner_name,
+            "worker_name": self.worker_name,
+            "job_id": self.job_id,
+            "nonce": self.nonce,
+            "proof": self.proof,
+            "result": result,
+        })
+        .to_string()
+    }
+}
+
+/// Per-connection state of a running script.
+struct Session {
+    peer: SocketAddr,
+    account_name: String,
+    miner_name: String,
+    submits: usize,
+    jobs: u64,
+    delay: Duration,
+    rejects: VecDeque<PoolError>,
+}
+
+/// A scripted pool for testing miners: every miner that subscribes and authorizes is
+/// played the script, which sends jobs and injects faults, while its submits are recorded.
+/// Submits are accepted unless a `reject` step queued an error for them.
+#[derive(Clone)]
+pub struct MockPool {
+    config: MockPoolConfig,
+    submissions: Arc<Mutex<Vec<Submission>>>,
+}
+
+impl MockPool {
+    pub fn new(config: MockPoolConfig) -> Self {
+        Self {
+            config,
+            submissions: Default::default(),
+        }
+    }
+
+    /// Serves miners from `listener`, each on its own run of the script.
+    pub async fn run(&self, listener: TcpListener) -> anyhow::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let pool = self.clone();
+            task::spawn(async move {
+                if let Err(e) = pool.serve(stream, peer).await {
+                    eprintln!("mock-pool: {} {}", peer, e);
+                }
+            });
+        }
+    }
+
+    /// Every submit so far, oldest first.
+    pub fn submissions(&self) -> Vec<Submission> {
+        self.submissions.lock().unwrap().clone()
+    }
+
+    async fn serve(&self, stream: TcpStream, peer: SocketAddr) -> anyhow::Result<()> {
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        let (account_name, miner_name) = handshake(&mut framed).await?;
+        let mut session = Session {
+            peer,
+            account_name,
+            miner_name,
+            submits: 0,
+            jobs: 0,
+            delay: Duration::ZERO,
+            rejects: VecDeque::new(),
+        };
+
+        let mut steps = self.config.script.0.iter();
+        let mut step = steps.next();
+        let mut resume_at = Instant::now();
+        loop {
+            let waiting = match step {
+                Some(Step::WaitSubmits(n)) => session.submits < *n,
+                Some(_) => false,
+                None => true,
+            };
+            tokio::select! {
+                _ = sleep_until(resume_at), if !waiting => {
+                    match step {
+                        Some(Step::Sleep(duration)) => resume_at = Instant::now() + *duration,
+                        Some(Step::Disconnect) => return Ok(()),
+                        Some(s) => run_step(&mut framed, &mut session, s).await?,
+                        None => {}
+                    }
+                    step = steps.next();
+                }
+                res = framed.next() => {
+                    match res {
+                        Some(Ok(msg)) => self.handle(&mut framed, &mut session, msg).await?,
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Ok(()),
+                    }
+                    // a satisfied wait moves on
+                    if let Some(Step::WaitSubmits(n)) = step {
+                        if session.submits >= *n {
+                            step = steps.next();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle(
+        &self,
+        framed: &mut Framed<TcpStream, StratumCodec>,
+        session: &mut Session,
+        msg: StratumMessage,
+    ) -> anyhow::Result<()> {
+        let (id, job_id, nonce, proof, worker_name) = match msg {
+            StratumMessage::Submit(id, job_id, nonce, proof, worker_name) => {
+                (id, job_id, nonce, proof, worker_name)
+            }
+            _ => return Ok(()),
+        };
+        session.submits += 1;
+        let result = match session.rejects.pop_front() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+        let submission = Submission {
+            peer: session.peer,
+            account_name: session.account_name.clone(),
+            miner_name: session.miner_name.clone(),
+            worker_name,
+            job_id,
+            nonce,
+            proof,
+            result: result.clone(),
+        };
+        self.record(submission)?;
+
+        if !session.delay.is_zero() {
+            tokio::time::sleep(session.delay).await;
+        }
+        let response = match result {
+            Ok(()) => StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None),
+            Err(e) => pool_error(id, e),
+        };
+        framed.send(response).await?;
+        Ok(())
+    }
+
+    fn record(&self, submission: Submission) -> anyhow::Result<()> {
+        if let Some(path) = &self.config.submissions_file {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", submission.to_json_line())?;
+        }
+        self.submissions.lock().unwrap().push(submission);
+        Ok(())
+    }
+}
+
+/// Answers the miner's subscribe and authorize, returning who authorized.
+async fn handshake(
+    framed: &mut Framed<TcpStream, StratumCodec>,
+) -> anyhow::Result<(String, String)> {
+    loop {
+        match framed.next().await {
+            Some(Ok(StratumMessage::Subscribe(id, ..))) => {
+                framed
+                    .send(StratumMessage::Response(id, None, None))
+                    .await?;
+            }
+            Some(Ok(StratumMessage::Authorize(id, account_name, miner_name, _))) => {
+                framed
+                    .send(StratumMessage::Response(
+                        id,
+                        Some(ResponseMessage::Bool(true)),
+                        None,
+                    ))
+                    .await?;
+                return Ok((account_name, miner_name));
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(e.into()),
+            None => return Err(anyhow!("miner disconnected")),
+        }
+    }
+}
+
+async fn run_step(
+    framed: &mut Framed<TcpStream, StratumCodec>,
+    session: &mut Session,
+    step: &Step,
+) -> anyhow::Result<()> {
+    match step {
+        Step::Notify {
+            height,
+            difficulty_target,
+            clean_jobs,
+        } => {
+            session.jobs += 1;
+            framed
+                .send(notify(
+                    *height,
+                    session.jobs,
+                    *difficulty_target,
+                    *clean_jobs,
+                ))
+                .await?;
+        }
+        Step::Delay(delay) => session.delay = *delay,
+        Step::Reject(e) => session.rejects.push_back(e.clone()),
+        Step::Malformed(text) => {
+            SinkExt::<StratumMessage>::flush(framed).await?;
+            let stream = framed.get_mut();
+            stream.write_all(text.as_bytes()).await?;
+            stream.write_all(b"\n").await?;
+        }
+        Step::Sleep(_) | Step::WaitSubmits(_) | Step::Disconnect => {}
+    }
+    Ok(())
+}
+
+/// A job with made up but well formed fields, the job id carrying the height.
+fn notify(height: u32, job: u64, difficulty_target: u64, clean_jobs: bool) -> StratumMessage {
+    let field = |index: u8| {
+        let mut hasher = Sha256::new();
+        hasher.update(height.to_le_bytes());
+        hasher.update(job.to_le_bytes());
+        hasher.update([index]);
+        hex::encode(hasher.finalize())
+    };
+    StratumMessage::Notify(
+        format!("{}_{:x}", hex::encode(height.to_le_bytes()), job),
+        difficulty_target,
+        field(0),
+        field(1),
+        field(2),
+        field(3),
+        field(4),
+        clean_jobs,
+        None,
+    )
+}
+
+fn pool_error(id: Id, e: PoolError) -> StratumMessage {
+    StratumMessage::Response(
+        id,
+        None,
+        Some(Error::with_custom_msg(
+            ErrorCode::ServerError(e.id()),
+            &e.to_string(),
+        )),
+    )
+}
+
+#[tokio::test]
+async fn test_mock_pool() {
+    use crate::utils::job_id::get_height;
+    use std::str::FromStr;
+
+    let script = "
+        notify 100 1000 clean
+        wait 1
+        reject StaleProof
+        notify 101 500 keep
+        wait 3
+        malformed
+        disconnect
+    "
+    .parse()
+    .unwrap();
+    let pool = MockPool::new(MockPoolConfig::new(script));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let runner = pool.clone();
+    task::spawn(async move { runner.run(listener).await });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut miner = Framed::new(stream, StratumCodec::default());
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            "0.2.0".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    miner
+        .send(StratumMessage::Authorize(
+            Id::Num(1),
+            "aleo1account".to_string(),
+            "rig0".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await.unwrap().unwrap(),
+        StratumMessage::Response(Id::Num(0), _, None)
+    ));
+    assert!(matches!(
+        miner.next().await.unwrap().unwrap(),
+        StratumMessage::Response(Id::Num(1), Some(ResponseMessage::Bool(true)), None)
+    ));
+    let job_id = match miner.next().await.unwrap().unwrap() {
+        StratumMessage::Notify(job_id, 1000, .., true, None) => job_id,
+        _ => panic!("expected the first job"),
+    };
+    assert_eq!(get_height(job_id.clone()).unwrap(), 100);
+
+    let submit = |id| {
+        StratumMessage::Submit(
+            Id::Num(id),
+            job_id.clone(),
+            "00".repeat(8),
+            "ab".to_string(),
+            None,
+        )
+    };
+    miner.send(submit(2)).await.unwrap();
+    assert!(matches!(
+        miner.next().await.unwrap().unwrap(),
+        StratumMessage::Response(Id::Num(2), Some(ResponseMessage::Bool(true)), None)
+    ));
+    assert!(matches!(
+        miner.next().await.unwrap().unwrap(),
+        StratumMessage::Notify(_, 500, .., false, None)
+    ));
+    miner.send(submit(3)).await.unwrap();
+    match miner.next().await.unwrap().unwrap() {
+        StratumMessage::Response(Id::Num(3), None, Some(error)) => {
+            assert_eq!(
+                PoolError::from_str(&error.message).unwrap(),
+                PoolError::StaleProof
+            )
+        }
+        _ => panic!("expected a rejection"),
+    }
+    miner.send(submit(4)).await.unwrap();
+    assert!(matches!(
+        miner.next().await.unwrap().unwrap(),
+        StratumMessage::Response(Id::Num(4), Some(ResponseMessage::Bool(true)), None)
+    ));
+    assert!(miner.next().await.unwrap().is_err());
+    assert!(miner.next().await.is_none());
+
+    let submissions = pool.submissions();
+    assert_eq!(submissions.len(), 3);
+    assert_eq!(submissions[0].account_name, "aleo1account");
+    assert_eq!(submissions[0].miner_name, "rig0");
+    assert_eq!(submissions[1].result, Err(PoolError::StaleProof));
+    assert_eq!(submissions[2].result, Ok(()));
+}
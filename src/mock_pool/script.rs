@@ -0,0 +1,142 @@
+use crate::message::error::PoolError;
+use anyhow::anyhow;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Malformed frame sent when a script does not give one: JSON cut off mid message.
+pub static DEFAULT_MALFORMED_FRAME: &str =
+    r#"{"jsonrpc":"2.0","method":"mining.notify","params":["#;
+
+/// JSON-RPC error messages hold at most 32 bytes.
+const MAX_ERROR_MESSAGE_LEN: usize = 32;
+
+/// One step of a mock pool script, run in order for every miner once it has authorized.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// `notify <height> <difficulty_target> clean|keep`: a new job, `clean` telling the
+    /// miner to drop earlier ones. A new height is a block switch, a new target at the same
+    /// height a difficulty change.
+    Notify {
+        height: u32,
+        difficulty_target: u64,
+        clean_jobs: bool,
+    },
+    /// `sleep <ms>`
+    Sleep(Duration),
+    /// `wait <n>`: waits until the miner has submitted `n` shares in total
+    WaitSubmits(usize),
+    /// `delay <ms>`: answers every later submit this much later
+    Delay(Duration),
+    /// `reject <error> [reason]`: rejects the next submit, e.g. `reject InvalidProof bad nonce`
+    Reject(PoolError),
+    /// `malformed [text]`: sends a line that is not a valid message
+    Malformed(String),
+    /// `disconnect`
+    Disconnect,
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (command, args) = s.split_once(' ').unwrap_or((s, ""));
+        let args = args.trim();
+        let words = args.split_whitespace().collect::<Vec<&str>>();
+        let step = match (command, words.as_slice()) {
+            ("notify", [height, difficulty_target, clean]) => Step::Notify {
+                height: height.parse()?,
+                difficulty_target: difficulty_target.parse()?,
+                clean_jobs: match *clean {
+                    "clean" => true,
+                    "keep" => false,
+                    _ => return Err(anyhow!("expected clean or keep, got {}", clean)),
+                },
+            },
+            ("sleep", [ms]) => Step::Sleep(Duration::from_millis(ms.parse()?)),
+            ("wait", [n]) => Step::WaitSubmits(n.parse()?),
+            ("delay", [ms]) => Step::Delay(Duration::from_millis(ms.parse()?)),
+            ("reject", [name, ..]) => {
+                let reason = args[name.len()..].trim();
+                let error = PoolError::from_str(&format!("{}{}", name, reason))?;
+                if error.to_string().len() > MAX_ERROR_MESSAGE_LEN {
+                    return Err(anyhow!("reject reason too long: {}", reason));
+                }
+                Step::Reject(error)
+            }
+            ("malformed", []) => Step::Malformed(DEFAULT_MALFORMED_FRAME.to_string()),
+            ("malformed", _) => Step::Malformed(args.to_string()),
+            ("disconnect", []) => Step::Disconnect,
+            _ => return Err(anyhow!("invalid step: {}", s)),
+        };
+        Ok(step)
+    }
+}
+
+/// The steps of a script, one per line; blank lines and lines starting with `#` are skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Script(pub Vec<Step>);
+
+impl FromStr for Script {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = line.parse().map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+            steps.push(step);
+        }
+        Ok(Self(steps))
+    }
+}
+
+#[test]
+fn test_script() {
+    let script: Script = "
+        # a block switch, then a difficulty change
+        notify 100 18446744073709551615 clean
+        wait 2
+        notify 101 1000 keep
+        sleep 250
+        delay 20
+        reject InvalidProof bad nonce
+        reject StaleProof
+        malformed
+        malformed {\"id\":
+        disconnect
+    "
+    .parse()
+    .unwrap();
+    assert_eq!(
+        script.0,
+        vec![
+            Step::Notify {
+                height: 100,
+                difficulty_target: u64::MAX,
+                clean_jobs: true
+            },
+            Step::WaitSubmits(2),
+            Step::Notify {
+                height: 101,
+                difficulty_target: 1000,
+                clean_jobs: false
+            },
+            Step::Sleep(Duration::from_millis(250)),
+            Step::Delay(Duration::from_millis(20)),
+            Step::Reject(PoolError::InvalidProof(Some("bad nonce".to_string()))),
+            Step::Reject(PoolError::StaleProof),
+            Step::Malformed(DEFAULT_MALFORMED_FRAME.to_string()),
+            Step::Malformed("{\"id\":".to_string()),
+            Step::Disconnect,
+        ]
+    );
+
+    let err = Script::from_str("sleep 1\nnotify 1 2 maybe").unwrap_err();
+    assert!(err.to_string().starts_with("line 2:"));
+    assert!(Script::from_str("reject Teapot").is_err());
+    assert!(Script::from_str("reject InvalidProof this reason does not fit").is_err());
+    assert!(Script::from_str("explode").is_err());
+}
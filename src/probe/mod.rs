@@ -0,0 +1,469 @@
+use crate::message::puzzle::PuzzleJob;
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::{StratumCodec, StratumMessage};
+use crate::utils::job_id::{get_epoch, get_height};
+use crate::utils::notify::{decode_block_header_root, decode_hash_leaves};
+use crate::CURRENT_PROTOCOL_VERSION;
+use futures_util::{SinkExt, StreamExt};
+use json_rpc_types::Id;
+use std::fmt;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time::{timeout_at, Instant};
+use tokio_util::codec::Framed;
+
+#[derive(Clone, Debug)]
+pub struct ProbeConfig {
+    /// Address of the pool to check
+    pub pool: String,
+    pub user_agent: String,
+    pub protocol_version: String,
+    pub account_name: String,
+    pub miner_name: String,
+    pub worker_password: Option<String>,
+    /// Jobs to wait for after authorizing
+    pub jobs: usize,
+    /// How long each step, connecting, subscribing, authorizing or getting the jobs, may take
+    pub timeout: Duration,
+}
+
+impl ProbeConfig {
+    pub fn new(pool: String, account_name: String, miner_name: String) -> Self {
+        Self {
+            pool,
+            user_agent: "ABMatrix_Stratum_Probe".to_string(),
+            protocol_version: CURRENT_PROTOCOL_VERSION.to_string(),
+            account_name,
+            miner_name,
+            worker_password: None,
+            jobs: 1,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Why a probe failed, each with its own exit code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeError {
+    /// The pool could not be reached, or closed the connection
+    Connect(String),
+    /// The pool refused the subscribe or the authorize
+    Rejected(String),
+    /// The pool sent something that does not decode, or a job with invalid fields
+    Malformed(String),
+    /// A step took longer than `ProbeConfig::timeout`
+    TimedOut(String),
+}
+
+impl ProbeError {
+    /// 2 is left for usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ProbeError::Connect(_) => 1,
+            ProbeError::Rejected(_) => 3,
+            ProbeError::Malformed(_) => 4,
+            ProbeError::TimedOut(_) => 5,
+        }
+    }
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::Connect(e) => write!(f, "connect failed: {}", e),
+            ProbeError::Rejected(e) => write!(f, "rejected: {}", e),
+            ProbeError::Malformed(e) => write!(f, "malformed traffic: {}", e),
+            ProbeError::TimedOut(e) => write!(f, "timed out: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+/// A job the pool sent, decoded and validated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeJob {
+    pub job_id: String,
+    pub height: Option<u32>,
+    pub epoch: Option<u32>,
+    pub difficulty_target: u64,
+    pub clean_jobs: bool,
+    pub signed: bool,
+}
+
+impl ProbeJob {
+    /// Checks the fields of a `Notify` or `PuzzleNotify`; other messages are not jobs.
+    pub fn from_message(msg: &StratumMessage) -> Option<Result<Self, ProbeError>> {
+        let malformed = |e: anyhow::Error| ProbeError::Malformed(e.to_string());
+        let job = match msg {
+            StratumMessage::Notify(
+                job_id,
+                difficulty_target,
+                block_header_root,
+                hashed_leaves_1,
+                hashed_leaves_2,
+                hashed_leaves_3,
+                hashed_leaves_4,
+                clean_jobs,
+                signature,
+            ) => {
+                let hashed_leaves = vec![
+                    hashed_leaves_1.clone(),
+                    hashed_leaves_2.clone(),
+                    hashed_leaves_3.clone(),
+                    hashed_leaves_4.clone(),
+                ];
+                get_height(job_id.clone())
+                    .and_then(|height| {
+                        decode_block_header_root(block_header_root)?;
+                        decode_hash_leaves(&hashed_leaves)?;
+                        Ok(Self {
+                            job_id: job_id.clone(),
+                            height: Some(height),
+                            epoch: get_epoch(job_id).ok(),
+                            difficulty_target: *difficulty_target,
+                            clean_jobs: *clean_jobs,
+                            signed: signature.is_some(),
+                        })
+                    })
+                    .map_err(malformed)
+            }
+            StratumMessage::PuzzleNotify(.., clean_jobs, signature) => PuzzleJob::from_message(msg)
+                .map_err(malformed)
+                .map(|job| Self {
+                    height: get_height(job.job_id.clone()).ok(),
+                    job_id: job.job_id,
+                    epoch: Some(job.epoch_number),
+                    difficulty_target: job.proof_target,
+                    clean_jobs: *clean_jobs,
+                    signed: signature.is_some(),
+                }),
+            _ => return None,
+        };
+        Some(job)
+    }
+}
+
+impl fmt::Display for ProbeJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job {}", self.job_id)?;
+        if let Some(height) = self.height {
+            write!(f, " height {}", height)?;
+        }
+        if let Some(epoch) = self.epoch {
+            write!(f, " epoch {}", epoch)?;
+        }
+        write!(
+            f,
+            " target {} {}",
+            self.difficulty_target,
+            if self.clean_jobs { "clean" } else { "keep" }
+        )?;
+        if self.signed {
+            write!(f, " signed")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeEvent {
+    Job(ProbeJob),
+    /// Any other message, as JSON
+    Message(String),
+}
+
+/// What a probe saw, times since it started. Kept up to a failure.
+#[derive(Clone, Debug, Default)]
+pub struct ProbeReport {
+    pub connected: Option<Duration>,
+    pub subscribed: Option<Duration>,
+    /// The subscribe result as JSON, e.g. with the accepted extensions
+    pub subscribe_result: Option<String>,
+    pub authorized: Option<Duration>,
+    pub events: Vec<(Duration, ProbeEvent)>,
+}
+
+impl ProbeReport {
+    pub fn jobs(&self) -> impl Iterator<Item = &ProbeJob> {
+        self.events.iter().filter_map(|(_, event)| match event {
+            ProbeEvent::Job(job) => Some(job),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = vec![];
+        if let Some(at) = self.connected {
+            lines.push(format!("connected in {:?}", at));
+        }
+        if let Some(at) = self.subscribed {
+            lines.push(format!(
+                "subscribed in {:?}, result: {}",
+                at,
+                self.subscribe_result.as_deref().unwrap_or("null")
+            ));
+        }
+        if let Some(at) = self.authorized {
+            lines.push(format!("authorized in {:?}", at));
+        }
+        for (at, event) in &self.events {
+            match event {
+                ProbeEvent::Job(job) => lines.push(format!("[{:?}] {}", at, job)),
+                ProbeEvent::Message(json) => lines.push(format!("[{:?}] {}", at, json)),
+            }
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Connects, subscribes, authorizes and waits for `config.jobs` jobs, recording what the
+/// pool sends in `report`.
+pub async fn probe(config: &ProbeConfig, report: &mut ProbeReport) -> Result<(), ProbeError> {
+    let start = Instant::now();
+    let stream = match timeout_at(start + config.timeout, TcpStream::connect(&config.pool)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => return Err(ProbeError::Connect(e.to_string())),
+        Err(_) => {
+            return Err(ProbeError::TimedOut(format!(
+                "connecting to {}",
+                config.pool
+            )))
+        }
+    };
+    report.connected = Some(start.elapsed());
+    let mut framed = Framed::new(stream, StratumCodec::default());
+
+    send(
+        &mut framed,
+        StratumMessage::Subscribe(
+            Id::Num(0),
+            config.user_agent.clone(),
+            config.protocol_version.clone(),
+            None,
+        ),
+    )
+    .await?;
+    let result = wait_response(&mut framed, config, start, report, "subscribe").await?;
+    report.subscribed = Some(start.elapsed());
+    report.subscribe_result = result.and_then(|result| serde_json::to_string(&result).ok());
+
+    send(
+        &mut framed,
+        StratumMessage::Authorize(
+            Id::Num(1),
+            config.account_name.clone(),
+            config.miner_name.clone(),
+            config.worker_password.clone(),
+        ),
+    )
+    .await?;
+    wait_response(&mut framed, config, start, report, "authorize").await?;
+    report.authorized = Some(start.elapsed());
+
+    let deadline = Instant::now() + config.timeout;
+    while report.jobs().count() < config.jobs {
+        let msg = next(&mut framed, deadline, "job").await?;
+        observe(report, start, msg)?;
+    }
+    Ok(())
+}
+
+async fn send(
+    framed: &mut Framed<TcpStream, StratumCodec>,
+    msg: StratumMessage,
+) -> Result<(), ProbeError> {
+    framed
+        .send(msg)
+        .await
+        .map_err(|e| ProbeError::Connect(e.to_string()))
+}
+
+async fn next(
+    framed: &mut Framed<TcpStream, StratumCodec>,
+    deadline: Instant,
+    waiting_for: &str,
+) -> Result<StratumMessage, ProbeError> {
+    match timeout_at(deadline, framed.next()).await {
+        Ok(Some(Ok(msg))) => Ok(msg),
+        Ok(Some(Err(e))) => Err(ProbeError::Malformed(e.to_string())),
+        Ok(None) => Err(ProbeError::Connect(format!(
+            "pool closed the connection while waiting for {}",
+            waiting_for
+        ))),
+        Err(_) => Err(ProbeError::TimedOut(format!("no {}", waiting_for))),
+    }
+}
+
+async fn wait_response(
+    framed: &mut Framed<TcpStream, StratumCodec>,
+    config: &ProbeConfig,
+    start: Instant,
+    report: &mut ProbeReport,
+    request: &str,
+) -> Result<Option<ResponseMessage>, ProbeError> {
+    let deadline = Instant::now() + config.timeout;
+    loop {
+        match next(framed, deadline, request).await? {
+            StratumMessage::Response(_, _, Some(error)) => {
+                return Err(ProbeError::Rejected(format!(
+                    "{}: {}",
+                    request, error.message
+                )));
+            }
+            StratumMessage::Response(_, Some(ResponseMessage::Bool(false)), None) => {
+                return Err(ProbeError::Rejected(request.to_string()));
+            }
+            StratumMessage::Response(_, result, None) => return Ok(result),
+            // pools may send the first job before answering
+            msg => observe(report, start, msg)?,
+        }
+    }
+}
+
+fn observe(
+    report: &mut ProbeReport,
+    start: Instant,
+    msg: StratumMessage,
+) -> Result<(), ProbeError> {
+    let event = match ProbeJob::from_message(&msg) {
+        Some(job) => ProbeEvent::Job(job?),
+        None => ProbeEvent::Message(String::from_utf8_lossy(&msg.to_json()).to_string()),
+    };
+    report.events.push((start.elapsed(), event));
+    Ok(())
+}
+
+#[cfg(test)]
+async fn probe_mock_pool(
+    script: &str,
+    config: impl FnOnce(&mut ProbeConfig),
+) -> (ProbeReport, Result<(), ProbeError>) {
+    use crate::mock_pool::{MockPool, MockPoolConfig};
+    use tokio::net::TcpListener;
+
+    let pool = MockPool::new(MockPoolConfig::new(script.parse().unwrap()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::task::spawn(async move { pool.run(listener).await });
+    let mut probe_config = ProbeConfig::new(
+        addr.to_string(),
+        "aleo1account".to_string(),
+        "probe".to_string(),
+    );
+    config(&mut probe_config);
+    let mut report = ProbeReport::default();
+    let result = probe(&probe_config, &mut report).await;
+    (report, result)
+}
+
+#[tokio::test]
+async fn test_probe() {
+    let (report, result) =
+        probe_mock_pool("notify 100 1000 clean\nnotify 101 500 keep", |config| {
+            config.jobs = 2
+        })
+        .await;
+    assert_eq!(result, Ok(()));
+    assert!(report.connected <= report.subscribed);
+    assert!(report.subscribed <= report.authorized);
+    let jobs: Vec<_> = report.jobs().collect();
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(jobs[0].height, Some(100));
+    assert!(jobs[0].clean_jobs);
+    assert_eq!(jobs[1].height, Some(101));
+    assert_eq!(jobs[1].difficulty_target, 500);
+    assert!(report.to_string().contains("height 101 target 500 keep"));
+
+    let (report, result) = probe_mock_pool("malformed", |_| {}).await;
+    assert!(report.authorized.is_some());
+    assert_eq!(result.unwrap_err().exit_code(), 4);
+
+    let (_, result) =
+        probe_mock_pool("", |config| config.timeout = Duration::from_millis(100)).await;
+    assert_eq!(result, Err(ProbeError::TimedOut("no job".to_string())));
+
+    let (_, result) = probe_mock_pool("disconnect", |_| {}).await;
+    assert_eq!(result.unwrap_err().exit_code(), 1);
+}
+
+#[tokio::test]
+async fn test_probe_failures() {
+    use crate::message::error::PoolError;
+    use json_rpc_types::{Error, ErrorCode};
+    use tokio::net::TcpListener;
+
+    // a pool refusing the miner
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::task::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, ..) => StratumMessage::Response(id, None, None),
+                StratumMessage::Authorize(id, ..) => {
+                    let e = PoolError::Unauthorized(None);
+                    StratumMessage::Response(
+                        id,
+                        None,
+                        Some(Error::with_custom_msg(
+                            ErrorCode::ServerError(e.id()),
+                            &e.to_string(),
+                        )),
+                    )
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+    });
+    let config = ProbeConfig::new(
+        addr.to_string(),
+        "aleo1account".to_string(),
+        "probe".to_string(),
+    );
+    let mut report = ProbeReport::default();
+    let error = probe(&config, &mut report).await.unwrap_err();
+    assert_eq!(
+        error,
+        ProbeError::Rejected("authorize: Unauthorized".to_string())
+    );
+    assert_eq!(error.exit_code(), 3);
+    assert!(report.subscribed.is_some());
+
+    // nothing listening
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    let config = ProbeConfig::new(
+        addr.to_string(),
+        "aleo1account".to_string(),
+        "probe".to_string(),
+    );
+    let error = probe(&config, &mut ProbeReport::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ProbeError::Connect(_)));
+    assert!(error.to_string().starts_with("connect failed"));
+
+    // job fields are checked
+    let bad_root = StratumMessage::Notify(
+        format!("{}_job", hex::encode(7u32.to_le_bytes())),
+        1000,
+        "zz".to_string(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        true,
+        None,
+    );
+    assert!(matches!(
+        ProbeJob::from_message(&bad_root),
+        Some(Err(ProbeError::Malformed(_)))
+    ));
+    assert!(ProbeJob::from_message(&StratumMessage::SetEpoch(1)).is_none());
+}
@@ -0,0 +1,1797 @@
+pub mod stats;
+
+use crate::message::challenge::{
+    challenge_extension, new_challenge, supports_challenge, ChallengeAuthorizer, CHALLENGE_LENGTH,
+    CHALLENGE_PROTOCOL_EXTENSION,
+};
+use crate::message::error::PoolError;
+use crate::message::extranonce::{
+    advertise_extranonce, extranonce_from_result, extranonce_result, supports_extranonce,
+    Extranonce, ExtranonceAllocator,
+};
+use crate::message::proof::{InvalidSubmit, SubmitCheckCodec, SubmitFormats};
+use crate::message::puzzle::{advertise_testnet3, JobFormat, TESTNET3_PROTOCOL_EXTENSION};
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::{EncodedFrame, StratumCodec, StratumMessage};
+use crate::message::token::{TokenAuthority, TokenClaims, SCOPE_SUBMIT};
+use crate::message::workers::{
+    advertise_workers, supports_workers, worker_name, workers_accepted, WorkerSession,
+    WORKERS_PROTOCOL_EXTENSION,
+};
+use crate::utils::extension::accept_extensions;
+use crate::CURRENT_PROTOCOL_VERSION;
+use anyhow::anyhow;
+use futures_util::{SinkExt, StreamExt};
+use json_rpc_types::{Error, ErrorCode, Id};
+use stats::DownstreamStats;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio_util::codec::Framed;
+
+/// Prefix bytes the proxy adds to its own range for each miner, allowing 65536 miners.
+const DOWNSTREAM_EXTRANONCE_BYTES: usize = 2;
+
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    /// Address of the upstream pool
+    pub upstream: String,
+    pub user_agent: String,
+    pub protocol_version: String,
+    /// Credentials used for the single upstream session
+    pub account_name: String,
+    pub miner_name: String,
+    pub worker_password: Option<String>,
+    /// When set, miners authorize with a token from this authority as their password
+    pub worker_tokens: Option<TokenAuthority>,
+    /// When set, miners must offer the challenge extension and authorize with a signature
+    /// of their account instead of a password. Both take the password, so this can't be
+    /// combined with `worker_tokens`.
+    pub challenge: Option<Arc<ChallengeAuthorizer>>,
+    /// Nonce and proof lengths miners' submits must have, by their protocol version
+    pub submit_formats: SubmitFormats,
+    /// Jobs the proxy takes from the pool, miners subscribing for the other format are
+    /// refused
+    pub job_format: JobFormat,
+}
+
+impl ProxyConfig {
+    pub fn new(upstream: String, account_name: String, miner_name: String) -> Self {
+        Self {
+            upstream,
+            user_agent: "ABMatrix_Stratum_Proxy".to_string(),
+            protocol_version: CURRENT_PROTOCOL_VERSION.to_string(),
+            account_name,
+            miner_name,
+            worker_password: None,
+            worker_tokens: None,
+            challenge: None,
+            submit_formats: SubmitFormats::default(),
+            job_format: JobFormat::BlockHeader,
+        }
+    }
+}
+
+enum Outbound {
+    Frame(EncodedFrame),
+    Message(StratumMessage),
+}
+
+struct Downstream {
+    tx: mpsc::UnboundedSender<Outbound>,
+    stats: DownstreamStats,
+    /// worker name -> claims of the token it authorized with
+    tokens: HashMap<String, TokenClaims>,
+    /// Index of the nonce range allocated to the miner, if it took one
+    extranonce: Option<u64>,
+    /// Whether the miner negotiated several workers on its connection
+    workers: bool,
+    /// Challenge the miner's authorize signs, if it negotiated one
+    challenge: Option<[u8; CHALLENGE_LENGTH]>,
+}
+
+impl Downstream {
+    /// Adds an authorized worker, the first one starts receiving jobs. Without the workers
+    /// extension the connection has a single worker, which a new authorize replaces.
+    fn add_worker(
+        &mut self,
+        account_name: String,
+        miner_name: String,
+        latest_job: &Option<EncodedFrame>,
+    ) -> anyhow::Result<()> {
+        let first = self.stats.workers.is_empty();
+        if !self.workers {
+            let name = worker_name(&account_name, &miner_name);
+            self.stats.workers = WorkerSession::new();
+            self.tokens.retain(|worker, _| *worker == name);
+        }
+        self.stats.workers.authorize(&account_name, &miner_name);
+        if let (true, Some(job)) = (first, latest_job) {
+            self.tx.send(Outbound::Frame(job.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Counts a share and resolves its worker, `None` once the share has been answered
+    /// because the worker may not submit.
+    fn share_worker(
+        &mut self,
+        id: &Id,
+        worker: Option<&str>,
+        tokens: bool,
+    ) -> anyhow::Result<Option<String>> {
+        self.stats.submitted += 1;
+        self.stats.last_share_at = Some(Instant::now());
+        let worker = match self.stats.workers.submit(worker) {
+            Ok(worker) => worker,
+            Err(e) => {
+                self.stats.rejected += 1;
+                self.tx.send(Outbound::Message(pool_error(id.clone(), e)))?;
+                return Ok(None);
+            }
+        };
+        if tokens {
+            let scope = match self.tokens.get(&worker) {
+                Some(claims) => claims.check_scope(SCOPE_SUBMIT),
+                None => Err(PoolError::Unauthorized(None)),
+            };
+            if let Err(e) = scope {
+                self.reject_share(id.clone(), &worker, e)?;
+                return Ok(None);
+            }
+        }
+        Ok(Some(worker))
+    }
+
+    fn reject_share(&mut self, id: Id, worker: &str, e: PoolError) -> anyhow::Result<()> {
+        self.stats.rejected += 1;
+        self.stats.workers.record(worker, false);
+        self.tx.send(Outbound::Message(pool_error(id, e)))?;
+        Ok(())
+    }
+}
+
+enum PendingKind {
+    /// (account_name, miner_name) waiting for the pool to authorize it
+    Authorize(String, String),
+    /// worker_name of the share
+    Submit(String),
+}
+
+struct PendingRequest {
+    downstream: SocketAddr,
+    id: Id,
+    kind: PendingKind,
+}
+
+#[derive(Default)]
+struct Shared {
+    downstreams: HashMap<SocketAddr, Downstream>,
+    /// upstream request id -> the downstream that sent it
+    pending: HashMap<u64, PendingRequest>,
+    latest_job: Option<EncodedFrame>,
+    next_id: u64,
+    /// Whether the pool accepted several workers on the upstream session, in which case
+    /// every miner is authorized upstream and submits name their worker.
+    upstream_workers: bool,
+    /// Splits the upstream session's nonce range between miners, set by the handshake
+    extranonces: Option<ExtranonceAllocator>,
+}
+
+impl Shared {
+    /// Tracks a share about to be forwarded upstream, returning its upstream id.
+    fn forward_share(&mut self, downstream: SocketAddr, id: Id, worker: String) -> u64 {
+        let upstream_id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(
+            upstream_id,
+            PendingRequest {
+                downstream,
+                id,
+                kind: PendingKind::Submit(worker),
+            },
+        );
+        upstream_id
+    }
+}
+
+/// Aggregates many downstream miners onto one upstream pool session.
+///
+/// Jobs from the pool are encoded once and relayed to every authorized miner, and
+/// submits are forwarded upstream under proxy-assigned ids so responses can be routed
+/// back to the miner that sent them. Miners may authorize several workers on their
+/// connection, shares are counted per worker in `DownstreamStats::workers`. Miners that
+/// offer the extranonce extension each get a slice of the proxy's nonce range, so they
+/// never search the same nonces, and their submits must stay inside it. Submits from
+/// miners without a slice are only accepted outside the slices in use. Epoch and target
+/// changes are relayed like jobs, and testnet3 sessions are served when
+/// `ProxyConfig::job_format` asks the pool for them. With `ProxyConfig::challenge`, miners
+/// prove they own their account by signing a per-connection challenge.
+#[derive(Clone)]
+pub struct Proxy {
+    config: ProxyConfig,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Proxy {
+    pub fn new(config: ProxyConfig) -> Self {
+        Self {
+            config,
+            shared: Arc::new(Mutex::new(Shared {
+                next_id: 2,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Connects to the upstream pool and serves miners from `listener` until the upstream
+    /// session ends.
+    pub async fn run(&self, listener: TcpListener) -> anyhow::Result<()> {
+        let stream = TcpStream::connect(&self.config.upstream).await?;
+        let mut upstream = Framed::new(stream, StratumCodec::default());
+        self.handshake(&mut upstream).await?;
+
+        let (upstream_tx, upstream_rx) = mpsc::unbounded_channel();
+        let proxy = self.clone();
+        let mut upstream_handle =
+            task::spawn(async move { proxy.serve_upstream(upstream, upstream_rx).await });
+
+        loop {
+            tokio::select! {
+                res = listener.accept() => {
+                    let (stream, addr) = res?;
+                    let proxy = self.clone();
+                    let upstream_tx = upstream_tx.clone();
+                    task::spawn(async move {
+                        proxy.serve_downstream(stream, addr, upstream_tx).await;
+                    });
+                }
+                res = &mut upstream_handle => {
+                    return match res {
+                        Ok(res) => res,
+                        Err(e) => Err(anyhow!(e)),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Snapshot of the counters of every connected miner.
+    pub fn stats(&self) -> Vec<(SocketAddr, DownstreamStats)> {
+        let shared = self.shared.lock().unwrap();
+        shared
+            .downstreams
+            .iter()
+            .map(|(addr, downstream)| (*addr, downstream.stats.clone()))
+            .collect()
+    }
+
+    async fn handshake(
+        &self,
+        upstream: &mut Framed<TcpStream, StratumCodec>,
+    ) -> anyhow::Result<()> {
+        let mut protocol_version =
+            advertise_extranonce(&advertise_workers(&self.config.protocol_version));
+        if self.config.job_format == JobFormat::CoinbasePuzzle {
+            protocol_version = advertise_testnet3(&protocol_version);
+        }
+        upstream
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                self.config.user_agent.clone(),
+                protocol_version,
+                None,
+            ))
+            .await?;
+        let result = self.wait_response(upstream).await?;
+        if JobFormat::from_result(&result) != self.config.job_format {
+            return Err(anyhow!(
+                "upstream does not serve {:?} jobs",
+                self.config.job_format
+            ));
+        }
+        let base = extranonce_from_result(&result).unwrap_or_default();
+        {
+            let mut shared = self.shared.lock().unwrap();
+            shared.upstream_workers = workers_accepted(&result);
+            shared.extranonces = Some(ExtranonceAllocator::new(base, DOWNSTREAM_EXTRANONCE_BYTES));
+        }
+
+        upstream
+            .send(StratumMessage::Authorize(
+                Id::Num(1),
+                self.config.account_name.clone(),
+                self.config.miner_name.clone(),
+                self.config.worker_password.clone(),
+            ))
+            .await?;
+        self.wait_response(upstream).await?;
+        Ok(())
+    }
+
+    async fn wait_response(
+        &self,
+        upstream: &mut Framed<TcpStream, StratumCodec>,
+    ) -> anyhow::Result<Option<ResponseMessage>> {
+        loop {
+            match upstream.next().await {
+                Some(Ok(StratumMessage::Response(_, _, Some(error)))) => {
+                    return Err(anyhow!("upstream rejected proxy: {}", error.message));
+                }
+                Some(Ok(StratumMessage::Response(_, result, None))) => return Ok(result),
+                Some(Ok(msg)) if is_broadcast(&msg) => self.relay(msg)?,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(anyhow!("upstream disconnected")),
+            }
+        }
+    }
+
+    async fn serve_upstream(
+        &self,
+        mut upstream: Framed<TcpStream, StratumCodec>,
+        mut upstream_rx: mpsc::UnboundedReceiver<StratumMessage>,
+    ) -> anyhow::Result<()> {
+        loop {
+            tokio::select! {
+                Some(msg) = upstream_rx.recv() => {
+                    upstream.send(msg).await?;
+                }
+                res = upstream.next() => {
+                    match res {
+                        Some(Ok(msg)) if is_broadcast(&msg) => self.relay(msg)?,
+                        Some(Ok(msg @ StratumMessage::SetExtranonce(..))) => {
+                            self.relay_extranonce(&msg)?
+                        }
+                        Some(Ok(StratumMessage::Response(Id::Num(id), result, error))) => {
+                            self.relay_response(id, result, error)
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Err(anyhow!("upstream disconnected")),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a message from the pool to every authorized miner, jobs are also kept for the
+    /// miners that authorize later.
+    fn relay(&self, msg: StratumMessage) -> anyhow::Result<()> {
+        let job = matches!(
+            msg,
+            StratumMessage::Notify(..) | StratumMessage::PuzzleNotify(..)
+        );
+        let frame = EncodedFrame::new(msg)?;
+        let mut shared = self.shared.lock().unwrap();
+        for downstream in shared.downstreams.values() {
+            if !downstream.stats.workers.is_empty() {
+                let _ = downstream.tx.send(Outbound::Frame(frame.clone()));
+            }
+        }
+        if job {
+            shared.latest_job = Some(frame);
+        }
+        Ok(())
+    }
+
+    /// Moves every miner's range under the one the pool moved the proxy to.
+    fn relay_extranonce(&self, msg: &StratumMessage) -> anyhow::Result<()> {
+        let base = match Extranonce::from_message(msg) {
+            Some(base) => base?,
+            None => return Ok(()),
+        };
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        let extranonces = match &mut shared.extranonces {
+            Some(extranonces) => extranonces,
+            None => return Ok(()),
+        };
+        extranonces.rebase(base);
+        for downstream in shared.downstreams.values() {
+            if let Some(extranonce) = downstream.extranonce.and_then(|i| extranonces.get(i)) {
+                let _ = downstream
+                    .tx
+                    .send(Outbound::Message(extranonce.to_message()));
+            }
+        }
+        Ok(())
+    }
+
+    fn relay_response(
+        &self,
+        id: u64,
+        result: Option<ResponseMessage>,
+        error: Option<json_rpc_types::Error<()>>,
+    ) {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        let pending = match shared.pending.remove(&id) {
+            Some(pending) => pending,
+            None => return,
+        };
+        if let Some(downstream) = shared.downstreams.get_mut(&pending.downstream) {
+            let accepted = error.is_none() && !matches!(result, Some(ResponseMessage::Bool(false)));
+            let _ = downstream
+                .tx
+                .send(Outbound::Message(StratumMessage::Response(
+                    pending.id, result, error,
+                )));
+            match pending.kind {
+                PendingKind::Authorize(account_name, miner_name) => {
+                    if accepted {
+                        let _ = downstream.add_worker(account_name, miner_name, &shared.latest_job);
+                    }
+                }
+                PendingKind::Submit(worker_name) => {
+                    if accepted {
+                        downstream.stats.accepted += 1;
+                    } else {
+                        downstream.stats.rejected += 1;
+                    }
+                    downstream.stats.workers.record(&worker_name, accepted);
+                }
+            }
+        }
+    }
+
+    async fn serve_downstream(
+        &self,
+        stream: TcpStream,
+        addr: SocketAddr,
+        upstream_tx: mpsc::UnboundedSender<StratumMessage>,
+    ) {
+        let mut framed = Framed::new(stream, SubmitCheckCodec::default());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        self.shared.lock().unwrap().downstreams.insert(
+            addr,
+            Downstream {
+                tx,
+                stats: DownstreamStats::new(),
+                tokens: HashMap::new(),
+                extranonce: None,
+                workers: false,
+                challenge: None,
+            },
+        );
+
+        loop {
+            tokio::select! {
+                Some(outbound) = rx.recv() => {
+                    let res = match outbound {
+                        Outbound::Frame(frame) => framed.send(frame).await,
+                        Outbound::Message(msg) => framed.send(msg).await,
+                    };
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                res = framed.next() => {
+                    let msg = match res {
+                        Some(Ok(Ok(msg))) => msg,
+                        Some(Ok(Err(invalid))) => {
+                            self.reject_submit(addr, invalid);
+                            continue;
+                        }
+                        _ => break,
+                    };
+                    if let StratumMessage::Subscribe(_, _, protocol_version, _) = &msg {
+                        framed.codec_mut().format =
+                            self.config.submit_formats.for_protocol_version(protocol_version);
+                    }
+                    if self.handle_downstream(addr, msg, &upstream_tx).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut shared = self.shared.lock().unwrap();
+        let index = shared.downstreams.remove(&addr).and_then(|d| d.extranonce);
+        if let (Some(index), Some(extranonces)) = (index, &mut shared.extranonces) {
+            extranonces.release(index);
+        }
+    }
+
+    /// Answers a submit with a malformed nonce or proof, without forwarding it.
+    fn reject_submit(&self, addr: SocketAddr, invalid: InvalidSubmit) {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(downstream) = shared.downstreams.get_mut(&addr) {
+            downstream.stats.submitted += 1;
+            downstream.stats.rejected += 1;
+            downstream.stats.last_share_at = Some(Instant::now());
+            let _ = downstream.tx.send(Outbound::Message(invalid.response()));
+        }
+    }
+
+    fn handle_downstream(
+        &self,
+        addr: SocketAddr,
+        msg: StratumMessage,
+        upstream_tx: &mpsc::UnboundedSender<StratumMessage>,
+    ) -> anyhow::Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        let downstream = shared
+            .downstreams
+            .get_mut(&addr)
+            .ok_or_else(|| anyhow!("unknown downstream {}", addr))?;
+        match msg {
+            StratumMessage::Subscribe(id, _, protocol_version, _) => {
+                if JobFormat::for_protocol_version(&protocol_version) != self.config.job_format {
+                    downstream
+                        .tx
+                        .send(Outbound::Message(StratumMessage::Response(
+                            id,
+                            None,
+                            Some(Error::with_custom_msg(
+                                ErrorCode::InvalidParams,
+                                &format!("{:?} jobs are not served", self.config.job_format),
+                            )),
+                        )))?;
+                    return Ok(());
+                }
+                let challenge;
+                let mut extensions = vec![];
+                if self.config.job_format == JobFormat::CoinbasePuzzle {
+                    extensions.push(TESTNET3_PROTOCOL_EXTENSION);
+                }
+                downstream.workers = supports_workers(&protocol_version);
+                if downstream.workers {
+                    extensions.push(WORKERS_PROTOCOL_EXTENSION);
+                }
+                downstream.challenge = None;
+                if self.config.challenge.is_some() && supports_challenge(&protocol_version) {
+                    let nonce = new_challenge();
+                    downstream.challenge = Some(nonce);
+                    challenge = challenge_extension(&nonce);
+                    extensions.push(CHALLENGE_PROTOCOL_EXTENSION);
+                    extensions.push(&challenge);
+                }
+                let allocation = match &mut shared.extranonces {
+                    Some(extranonces) => {
+                        // a repeated subscribe gives up the range of the previous one
+                        if let Some(index) = downstream.extranonce.take() {
+                            extranonces.release(index);
+                        }
+                        if supports_extranonce(&protocol_version) {
+                            extranonces.allocate()
+                        } else {
+                            None
+                        }
+                    }
+                    None => None,
+                };
+                let result = match allocation {
+                    Some((index, extranonce)) => {
+                        downstream.extranonce = Some(index);
+                        Some(extranonce_result(&extensions, &extranonce))
+                    }
+                    None if extensions.is_empty() => None,
+                    None => Some(accept_extensions(&extensions)),
+                };
+                downstream
+                    .tx
+                    .send(Outbound::Message(StratumMessage::Response(
+                        id, result, None,
+                    )))?;
+            }
+            StratumMessage::Authorize(id, account_name, miner_name, password) => {
+                let mut upstream_password = password.clone();
+                if let Some(authorizer) = &self.config.challenge {
+                    // the signature proves the account, the pool never sees it
+                    let verified = match &downstream.challenge {
+                        Some(challenge) => authorizer.verify_password(
+                            challenge,
+                            &account_name,
+                            &miner_name,
+                            password.as_deref(),
+                        ),
+                        None => Err(anyhow!("challenge not negotiated")),
+                    };
+                    if let Err(e) = verified {
+                        let e = PoolError::Unauthorized(Some(e.to_string()));
+                        downstream.tx.send(Outbound::Message(pool_error(id, e)))?;
+                        return Ok(());
+                    }
+                    upstream_password = None;
+                } else if let Some(authority) = &self.config.worker_tokens {
+                    let token = password
+                        .ok_or(PoolError::Unauthorized(Some("missing token".to_string())))
+                        .and_then(|token| authority.authorize(&token, &account_name, &miner_name));
+                    match token {
+                        Ok(claims) => {
+                            let name = worker_name(&account_name, &miner_name);
+                            downstream.tokens.insert(name, claims);
+                            upstream_password = None;
+                        }
+                        Err(e) => {
+                            downstream.tx.send(Outbound::Message(pool_error(id, e)))?;
+                            return Ok(());
+                        }
+                    }
+                }
+                if shared.upstream_workers {
+                    // the pool authorizes every worker itself
+                    let upstream_id = shared.next_id;
+                    shared.next_id += 1;
+                    shared.pending.insert(
+                        upstream_id,
+                        PendingRequest {
+                            downstream: addr,
+                            id,
+                            kind: PendingKind::Authorize(account_name.clone(), miner_name.clone()),
+                        },
+                    );
+                    upstream_tx.send(StratumMessage::Authorize(
+                        Id::Num(upstream_id),
+                        account_name,
+                        miner_name,
+                        upstream_password,
+                    ))?;
+                    return Ok(());
+                }
+                downstream
+                    .tx
+                    .send(Outbound::Message(StratumMessage::Response(
+                        id,
+                        Some(ResponseMessage::Bool(true)),
+                        None,
+                    )))?;
+                downstream.add_worker(account_name, miner_name, &shared.latest_job)?;
+            }
+            StratumMessage::Submit(id, job_id, nonce, proof, worker) => {
+                let tokens = self.config.worker_tokens.is_some();
+                let worker = match downstream.share_worker(&id, worker.as_deref(), tokens)? {
+                    Some(worker) => worker,
+                    None => return Ok(()),
+                };
+                // miners without a range search the whole space, keep them out of the
+                // slices handed to the others
+                let range = match (downstream.extranonce, &shared.extranonces) {
+                    (Some(index), Some(extranonces)) => match extranonces.get(index) {
+                        Some(extranonce) => extranonce.validate(&nonce),
+                        None => Ok(()),
+                    },
+                    (None, Some(extranonces)) => extranonces.validate_unallocated(&nonce),
+                    _ => Ok(()),
+                };
+                if let Err(e) = range {
+                    return downstream.reject_share(id, &worker, e);
+                }
+                let upstream_worker = shared.upstream_workers.then(|| worker.clone());
+                let upstream_id = shared.forward_share(addr, id, worker);
+                upstream_tx.send(StratumMessage::Submit(
+                    Id::Num(upstream_id),
+                    job_id,
+                    nonce,
+                    proof,
+                    upstream_worker,
+                ))?;
+            }
+            StratumMessage::SubmitSolution(id, job_id, prover_solution, worker) => {
+                let tokens = self.config.worker_tokens.is_some();
+                let worker = match downstream.share_worker(&id, worker.as_deref(), tokens)? {
+                    Some(worker) => worker,
+                    None => return Ok(()),
+                };
+                let upstream_worker = shared.upstream_workers.then(|| worker.clone());
+                let upstream_id = shared.forward_share(addr, id, worker);
+                upstream_tx.send(StratumMessage::SubmitSolution(
+                    Id::Num(upstream_id),
+                    job_id,
+                    prover_solution,
+                    upstream_worker,
+                ))?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Pool messages every authorized miner gets as they are: jobs, epochs and targets.
+fn is_broadcast(msg: &StratumMessage) -> bool {
+    #[allow(deprecated)]
+    let broadcast = matches!(
+        msg,
+        StratumMessage::Notify(..)
+            | StratumMessage::PuzzleNotify(..)
+            | StratumMessage::SetEpoch(..)
+            | StratumMessage::SetTarget(..)
+    );
+    broadcast
+}
+
+fn pool_error(id: Id, e: PoolError) -> StratumMessage {
+    StratumMessage::Response(
+        id,
+        None,
+        Some(Error::with_custom_msg(
+            ErrorCode::ServerError(e.id()),
+            &e.to_string(),
+        )),
+    )
+}
+
+#[tokio::test]
+async fn test_proxy() {
+    use std::str::FromStr;
+
+    // mock pool
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    let pool_handle = task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        let mut submits = vec![];
+        while let Some(Ok(msg)) = framed.next().await {
+            match msg {
+                StratumMessage::Subscribe(id, ..) => {
+                    framed
+                        .send(StratumMessage::Response(id, None, None))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Authorize(id, account_name, ..) => {
+                    assert_eq!(account_name, "proxy_account");
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    framed
+                        .send(StratumMessage::Notify(
+                            "job_id".to_string(),
+                            u64::MAX,
+                            "block_header_root".to_string(),
+                            "hashed_leaves_1".to_string(),
+                            "hashed_leaves_2".to_string(),
+                            "hashed_leaves_3".to_string(),
+                            "hashed_leaves_4".to_string(),
+                            true,
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Submit(id, job_id, nonce, ..) => {
+                    assert_eq!(job_id, "job_id");
+                    submits.push(id.clone());
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(nonce == "00000000000000aa")),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    if submits.len() == 2 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        submits
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    ));
+    let runner = proxy.clone();
+    task::spawn(async move { runner.run(listener).await });
+
+    let mut miners = vec![];
+    for (i, nonce) in ["00000000000000aa", "00000000000000bb"].iter().enumerate() {
+        let stream = TcpStream::connect(proxy_addr).await.unwrap();
+        let mut miner = Framed::new(stream, StratumCodec::default());
+        miner
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                "0.2.0".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            miner.next().await,
+            Some(Ok(StratumMessage::Response(_, _, None)))
+        ));
+        miner
+            .send(StratumMessage::Authorize(
+                Id::Num(1),
+                "account".to_string(),
+                format!("rig{}", i),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            miner.next().await,
+            Some(Ok(StratumMessage::Response(_, _, None)))
+        ));
+        assert!(matches!(
+            miner.next().await,
+            Some(Ok(StratumMessage::Notify(..)))
+        ));
+
+        // a malformed nonce is answered by the proxy, the pool never sees it
+        if i == 0 {
+            miner
+                .send(StratumMessage::Submit(
+                    Id::Num(6),
+                    "job_id".to_string(),
+                    "01".to_string(),
+                    "abcd".to_string(),
+                    None,
+                ))
+                .await
+                .unwrap();
+            match miner.next().await {
+                Some(Ok(StratumMessage::Response(Id::Num(6), None, Some(error)))) => {
+                    assert_eq!(
+                        PoolError::from_str(&error.message).unwrap(),
+                        PoolError::InvalidProof(Some("bad nonce length".to_string()))
+                    );
+                }
+                _ => panic!("expected an invalid submit"),
+            }
+        }
+
+        // both miners use the same id, the proxy must keep them apart upstream
+        miner
+            .send(StratumMessage::Submit(
+                Id::Num(7),
+                "job_id".to_string(),
+                nonce.to_string(),
+                "abcd".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(id, Some(ResponseMessage::Bool(accepted)), None))) => {
+                assert_eq!(id, Id::Num(7));
+                assert_eq!(accepted, *nonce == "00000000000000aa");
+            }
+            _ => panic!("expected submit response"),
+        }
+        miners.push(miner);
+    }
+
+    let submits = pool_handle.await.unwrap();
+    assert_eq!(submits.len(), 2);
+    assert_ne!(submits[0], submits[1]);
+
+    let mut stats = proxy.stats();
+    stats.sort_by_key(|(_, s)| s.workers.shares("account.rig0").is_none());
+    assert_eq!(stats.len(), 2);
+    assert!(stats[0].1.workers.is_authorized("account.rig0"));
+    assert_eq!(
+        (
+            stats[0].1.submitted,
+            stats[0].1.accepted,
+            stats[0].1.rejected
+        ),
+        (2, 1, 1)
+    );
+    assert_eq!(
+        (
+            stats[1].1.submitted,
+            stats[1].1.accepted,
+            stats[1].1.rejected
+        ),
+        (1, 0, 1)
+    );
+    assert_eq!(stats[1].1.pending(), 0);
+}
+
+#[tokio::test]
+async fn test_proxy_worker_tokens() {
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    // mock pool accepting every share
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, ..) => StratumMessage::Response(id, None, None),
+                StratumMessage::Authorize(id, ..) | StratumMessage::Submit(id, ..) => {
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+    });
+
+    let authority = TokenAuthority::generate();
+    let mut config = ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    );
+    config.worker_tokens = Some(authority.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(config);
+    task::spawn(async move { proxy.run(listener).await });
+
+    let claims = TokenClaims::new(
+        "account".to_string(),
+        "rig*".to_string(),
+        Duration::from_secs(3600),
+    );
+    let mut expired = claims.clone().with_scope(SCOPE_SUBMIT);
+    expired.expires_at = 0;
+    let cases = [
+        (None, Some(PoolError::Unauthorized(None)), None),
+        (
+            Some(authority.issue(&expired)),
+            Some(PoolError::TokenExpired),
+            None,
+        ),
+        (
+            Some(authority.issue(&claims)),
+            None,
+            Some(PoolError::Unauthorized(None)),
+        ),
+        (
+            Some(authority.issue(&claims.clone().with_scope(SCOPE_SUBMIT))),
+            None,
+            None,
+        ),
+    ];
+    for (token, authorize_error, submit_error) in cases {
+        let stream = TcpStream::connect(proxy_addr).await.unwrap();
+        let mut miner = Framed::new(stream, StratumCodec::default());
+        miner
+            .send(StratumMessage::Authorize(
+                Id::Num(1),
+                "account".to_string(),
+                "rig0".to_string(),
+                token,
+            ))
+            .await
+            .unwrap();
+        let error = match miner.next().await {
+            Some(Ok(StratumMessage::Response(_, _, error))) => error,
+            _ => panic!("expected authorize response"),
+        };
+        let error = error.map(|e| PoolError::from_str(&e.message).unwrap());
+        assert_eq!(
+            error.as_ref().map(|e| e.id()),
+            authorize_error.as_ref().map(|e| e.id())
+        );
+        if authorize_error.is_some() {
+            continue;
+        }
+
+        miner
+            .send(StratumMessage::Submit(
+                Id::Num(2),
+                "job_id".to_string(),
+                "0000000000000000".to_string(),
+                "abcd".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(id, result, error))) => {
+                assert_eq!(id, Id::Num(2));
+                let error = error.map(|e| PoolError::from_str(&e.message).unwrap());
+                assert_eq!(
+                    error.as_ref().map(|e| e.id()),
+                    submit_error.as_ref().map(|e| e.id())
+                );
+                if submit_error.is_none() {
+                    assert!(matches!(result, Some(ResponseMessage::Bool(true))));
+                }
+            }
+            _ => panic!("expected submit response"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_reauthorize() {
+    // mock pool accepting every share
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, ..) => StratumMessage::Response(id, None, None),
+                StratumMessage::Authorize(id, ..) | StratumMessage::Submit(id, ..) => {
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    ));
+    let runner = proxy.clone();
+    task::spawn(async move { runner.run(listener).await });
+
+    // a legacy rig authorizing again replaces its worker, and its submits still count
+    let stream = TcpStream::connect(proxy_addr).await.unwrap();
+    let mut miner = Framed::new(stream, StratumCodec::default());
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            "0.2.0".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(_, _, None)))
+    ));
+    for (i, miner_name) in ["rig0", "rig1"].iter().enumerate() {
+        miner
+            .send(StratumMessage::Authorize(
+                Id::Num(1 + i as u64),
+                "account".to_string(),
+                miner_name.to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            miner.next().await,
+            Some(Ok(StratumMessage::Response(
+                _,
+                Some(ResponseMessage::Bool(true)),
+                None
+            )))
+        ));
+    }
+    miner
+        .send(StratumMessage::Submit(
+            Id::Num(3),
+            "job_id".to_string(),
+            "0000000000000000".to_string(),
+            "abcd".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(3),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+
+    let stats = proxy.stats();
+    let workers = &stats[0].1.workers;
+    assert_eq!(workers.len(), 1);
+    assert_eq!(workers.shares("account.rig1").unwrap().accepted, 1);
+}
+
+#[tokio::test]
+async fn test_proxy_multiple_workers() {
+    // mock pool taking several workers on the proxy's session
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    let pool_handle = task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        let mut authorized = vec![];
+        let mut submits = vec![];
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, _, protocol_version, _) => {
+                    assert!(supports_workers(&protocol_version));
+                    let result = accept_extensions(&[WORKERS_PROTOCOL_EXTENSION]);
+                    StratumMessage::Response(id, Some(result), None)
+                }
+                StratumMessage::Authorize(id, _, miner_name, _) => {
+                    let accepted = miner_name != "banned";
+                    if accepted {
+                        authorized.push(miner_name);
+                    }
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(accepted)), None)
+                }
+                StratumMessage::Submit(id, _, nonce, _, worker_name) => {
+                    submits.push(worker_name.unwrap());
+                    let response = StratumMessage::Response(
+                        id,
+                        Some(ResponseMessage::Bool(nonce == "00000000000000aa")),
+                        None,
+                    );
+                    if submits.len() == 3 {
+                        framed.send(response).await.unwrap();
+                        break;
+                    }
+                    response
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+        (authorized, submits)
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    ));
+    let runner = proxy.clone();
+    task::spawn(async move { runner.run(listener).await });
+
+    let stream = TcpStream::connect(proxy_addr).await.unwrap();
+    let mut miner = Framed::new(stream, StratumCodec::default());
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            advertise_workers("0.2.0"),
+            None,
+        ))
+        .await
+        .unwrap();
+    match miner.next().await {
+        Some(Ok(StratumMessage::Response(_, result, None))) => assert!(workers_accepted(&result)),
+        _ => panic!("expected subscribe response"),
+    }
+    for (i, miner_name) in ["rig0", "rig1", "banned"].iter().enumerate() {
+        miner
+            .send(StratumMessage::Authorize(
+                Id::Num(1 + i as u64),
+                "account".to_string(),
+                miner_name.to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(_, Some(ResponseMessage::Bool(accepted)), None))) => {
+                assert_eq!(accepted, *miner_name != "banned")
+            }
+            _ => panic!("expected authorize response"),
+        }
+    }
+
+    let submits = [
+        ("account.rig0", "00000000000000aa"),
+        ("account.rig1", "00000000000000bb"),
+        ("account.banned", "00000000000000aa"),
+        ("account.rig1", "00000000000000aa"),
+    ];
+    for (i, (worker, nonce)) in submits.iter().enumerate() {
+        miner
+            .send(StratumMessage::Submit(
+                Id::Num(10 + i as u64),
+                "job_id".to_string(),
+                nonce.to_string(),
+                "abcd".to_string(),
+                Some(worker.to_string()),
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(id, result, error))) => {
+                assert_eq!(id, Id::Num(10 + i as u64));
+                if *worker == "account.banned" {
+                    assert!(error.is_some());
+                } else {
+                    assert!(
+                        matches!(result, Some(ResponseMessage::Bool(b)) if b == (*nonce == "00000000000000aa"))
+                    );
+                }
+            }
+            _ => panic!("expected submit response"),
+        }
+    }
+
+    let (authorized, upstream_submits) = pool_handle.await.unwrap();
+    assert_eq!(authorized, vec!["proxy", "rig0", "rig1"]);
+    assert_eq!(
+        upstream_submits,
+        vec!["account.rig0", "account.rig1", "account.rig1"]
+    );
+
+    let stats = proxy.stats();
+    assert_eq!(stats.len(), 1);
+    let stats = &stats[0].1;
+    assert_eq!((stats.submitted, stats.accepted, stats.rejected), (4, 2, 2));
+    assert_eq!(stats.workers.len(), 2);
+    let rig1 = stats.workers.shares("account.rig1").unwrap();
+    assert_eq!((rig1.submitted, rig1.accepted, rig1.rejected), (2, 1, 1));
+}
+
+#[tokio::test]
+async fn test_proxy_extranonce() {
+    use crate::message::extranonce::EXTRANONCE_PROTOCOL_EXTENSION;
+    use std::str::FromStr;
+
+    // mock pool assigning the proxy a range, then moving it after the first share
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            match msg {
+                StratumMessage::Subscribe(id, _, protocol_version, _) => {
+                    assert!(supports_extranonce(&protocol_version));
+                    let extranonce = Extranonce::new(vec![0xaa], None).unwrap();
+                    let result = extranonce_result(&[], &extranonce);
+                    framed
+                        .send(StratumMessage::Response(id, Some(result), None))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Authorize(id, ..) => {
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                }
+                StratumMessage::Submit(id, _, nonce, ..) => {
+                    assert!(nonce.starts_with("aa0000"));
+                    framed
+                        .send(StratumMessage::Response(
+                            id,
+                            Some(ResponseMessage::Bool(true)),
+                            None,
+                        ))
+                        .await
+                        .unwrap();
+                    let moved = Extranonce::new(vec![0xbb], None).unwrap();
+                    framed.send(moved.to_message()).await.unwrap();
+                }
+                _ => {}
+            }
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    ));
+    task::spawn(async move { proxy.run(listener).await });
+
+    let mut miners = vec![];
+    let mut ranges = vec![];
+    for i in 0..2 {
+        let stream = TcpStream::connect(proxy_addr).await.unwrap();
+        let mut miner = Framed::new(stream, StratumCodec::default());
+        miner
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                advertise_extranonce("0.2.0"),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) => {
+                let accepted = crate::utils::extension::accepted_extensions(&result);
+                assert_eq!(accepted[0], EXTRANONCE_PROTOCOL_EXTENSION);
+                ranges.push(extranonce_from_result(&result).unwrap());
+            }
+            _ => panic!("expected subscribe response"),
+        }
+        miner
+            .send(StratumMessage::Authorize(
+                Id::Num(1),
+                "account".to_string(),
+                format!("rig{}", i),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            miner.next().await,
+            Some(Ok(StratumMessage::Response(
+                _,
+                Some(ResponseMessage::Bool(true)),
+                None
+            )))
+        ));
+        miners.push(miner);
+    }
+    assert_eq!(ranges[0], Extranonce::new(vec![0xaa, 0, 0], None).unwrap());
+    assert_eq!(ranges[1].prefix, vec![0xaa, 0, 1]);
+
+    // a nonce from another miner's range is refused by the proxy
+    for (i, nonce) in [ranges[1].nonce(0), ranges[0].nonce(0)].iter().enumerate() {
+        miners[0]
+            .send(StratumMessage::Submit(
+                Id::Num(2 + i as u64),
+                "job_id".to_string(),
+                nonce.clone(),
+                "abcd".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miners[0].next().await {
+            Some(Ok(StratumMessage::Response(_, result, error))) if i == 0 => {
+                assert!(result.is_none());
+                assert_eq!(
+                    PoolError::from_str(&error.unwrap().message).unwrap(),
+                    PoolError::InvalidProof(Some("nonce out of range".to_string()))
+                );
+            }
+            Some(Ok(StratumMessage::Response(_, result, None))) => {
+                assert!(matches!(result, Some(ResponseMessage::Bool(true))));
+            }
+            _ => panic!("expected submit response"),
+        }
+    }
+
+    // every miner follows when the pool moves the proxy
+    for (i, miner) in miners.iter_mut().enumerate() {
+        match miner.next().await {
+            Some(Ok(msg @ StratumMessage::SetExtranonce(..))) => {
+                let moved = Extranonce::from_message(&msg).unwrap().unwrap();
+                assert_eq!(moved.prefix, vec![0xbb, 0, i as u8]);
+            }
+            _ => panic!("expected set_extranonce"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_extranonce_resubscribe() {
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, ..) => {
+                    let extranonce = Extranonce::new(vec![0xaa], None).unwrap();
+                    let result = extranonce_result(&[], &extranonce);
+                    StratumMessage::Response(id, Some(result), None)
+                }
+                StratumMessage::Authorize(id, ..) => {
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    ));
+    task::spawn(async move { proxy.run(listener).await });
+
+    async fn subscribe(miner: &mut Framed<TcpStream, StratumCodec>) -> Extranonce {
+        miner
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                advertise_extranonce("0.2.0"),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) => {
+                extranonce_from_result(&result).unwrap()
+            }
+            _ => panic!("expected subscribe response"),
+        }
+    }
+
+    // subscribing again hands back the same range instead of leaking it
+    let mut first = Framed::new(
+        TcpStream::connect(proxy_addr).await.unwrap(),
+        StratumCodec::default(),
+    );
+    let range = subscribe(&mut first).await;
+    assert_eq!(subscribe(&mut first).await, range);
+    assert_eq!(subscribe(&mut first).await, range);
+
+    let mut second = Framed::new(
+        TcpStream::connect(proxy_addr).await.unwrap(),
+        StratumCodec::default(),
+    );
+    assert_eq!(subscribe(&mut second).await.prefix, vec![0xaa, 0, 1]);
+}
+
+#[tokio::test]
+async fn test_proxy_extranonce_legacy() {
+    use std::str::FromStr;
+
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, ..) => {
+                    let extranonce = Extranonce::new(vec![0xaa], None).unwrap();
+                    let result = extranonce_result(&[], &extranonce);
+                    StratumMessage::Response(id, Some(result), None)
+                }
+                StratumMessage::Authorize(id, ..) | StratumMessage::Submit(id, ..) => {
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let proxy = Proxy::new(ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    ));
+    task::spawn(async move { proxy.run(listener).await });
+
+    // one miner taking a slice, one searching the whole range
+    let mut miners = vec![];
+    let mut range = None;
+    for protocol_version in [advertise_extranonce("0.2.0"), "0.2.0".to_string()] {
+        let stream = TcpStream::connect(proxy_addr).await.unwrap();
+        let mut miner = Framed::new(stream, StratumCodec::default());
+        miner
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                protocol_version,
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) => {
+                range = range.or_else(|| extranonce_from_result(&result));
+            }
+            _ => panic!("expected subscribe response"),
+        }
+        miner
+            .send(StratumMessage::Authorize(
+                Id::Num(1),
+                "account".to_string(),
+                "rig".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(
+            miner.next().await,
+            Some(Ok(StratumMessage::Response(
+                _,
+                Some(ResponseMessage::Bool(true)),
+                None
+            )))
+        ));
+        miners.push(miner);
+    }
+    let range = range.unwrap();
+    assert_eq!(range.prefix, vec![0xaa, 0, 0]);
+
+    // the legacy miner may not land in the slice, nor outside the proxy's range
+    let legacy = &mut miners[1];
+    for (i, nonce) in [
+        range.nonce(7),
+        "bb00000000000000".to_string(),
+        "aa00010000000000".to_string(),
+    ]
+    .iter()
+    .enumerate()
+    {
+        legacy
+            .send(StratumMessage::Submit(
+                Id::Num(2 + i as u64),
+                "job_id".to_string(),
+                nonce.clone(),
+                "abcd".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        match legacy.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) if i == 2 => {
+                assert!(matches!(result, Some(ResponseMessage::Bool(true))));
+            }
+            Some(Ok(StratumMessage::Response(_, None, Some(error)))) if i < 2 => {
+                assert_eq!(
+                    PoolError::from_str(&error.message).unwrap(),
+                    PoolError::InvalidProof(Some("nonce out of range".to_string()))
+                );
+            }
+            _ => panic!("unexpected submit response"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_proxy_testnet3() {
+    use crate::message::puzzle::{supports_testnet3, PuzzleJob};
+    use crate::utils::extension::accepted_extensions;
+
+    let job = PuzzleJob {
+        job_id: "job".to_string(),
+        epoch_number: 1,
+        epoch_challenge: vec![1; 32],
+        address: "aleo1pool".to_string(),
+        proof_target: 100,
+    };
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    let pool_job = job.clone();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            #[allow(deprecated)]
+            let replies = match msg {
+                StratumMessage::Subscribe(id, _, protocol_version, _) => {
+                    assert!(supports_testnet3(&protocol_version));
+                    let result = accept_extensions(&[TESTNET3_PROTOCOL_EXTENSION]);
+                    vec![StratumMessage::Response(id, Some(result), None)]
+                }
+                StratumMessage::Authorize(id, ..) => vec![
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None),
+                    pool_job.to_message(true),
+                ],
+                StratumMessage::SubmitSolution(id, job_id, ..) => {
+                    assert_eq!(job_id, "job");
+                    vec![
+                        StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None),
+                        StratumMessage::SetEpoch(2),
+                        StratumMessage::SetTarget(200),
+                    ]
+                }
+                _ => continue,
+            };
+            for reply in replies {
+                framed.send(reply).await.unwrap();
+            }
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let mut config = ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    );
+    config.job_format = JobFormat::CoinbasePuzzle;
+    let proxy = Proxy::new(config);
+    task::spawn(async move { proxy.run(listener).await });
+
+    // block header miners have nothing to work on
+    let stream = TcpStream::connect(proxy_addr).await.unwrap();
+    let mut miner = Framed::new(stream, StratumCodec::default());
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            "0.2.0".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(_, None, Some(_))))
+    ));
+
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            advertise_testnet3("0.2.0"),
+            None,
+        ))
+        .await
+        .unwrap();
+    match miner.next().await {
+        Some(Ok(StratumMessage::Response(_, result, None))) => {
+            assert!(accepted_extensions(&result).contains(&TESTNET3_PROTOCOL_EXTENSION.to_string()));
+        }
+        _ => panic!("expected subscribe response"),
+    }
+    miner
+        .send(StratumMessage::Authorize(
+            Id::Num(1),
+            "account".to_string(),
+            "rig".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(
+            _,
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+    match miner.next().await {
+        Some(Ok(msg)) => assert_eq!(PuzzleJob::from_message(&msg).unwrap(), job),
+        _ => panic!("expected a puzzle job"),
+    }
+
+    miner
+        .send(StratumMessage::SubmitSolution(
+            Id::Num(2),
+            "job".to_string(),
+            "abcd".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(2),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::SetEpoch(2)))
+    ));
+    #[allow(deprecated)]
+    let target = matches!(miner.next().await, Some(Ok(StratumMessage::SetTarget(200))));
+    assert!(target);
+}
+
+#[tokio::test]
+async fn test_proxy_challenge() {
+    use crate::message::challenge::{
+        advertise_challenge, challenge_from_result, signed_authorize, AccountSigner,
+        Ed25519AccountSigner, Ed25519AccountVerifier,
+    };
+    use std::str::FromStr;
+
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        while let Some(Ok(msg)) = framed.next().await {
+            let response = match msg {
+                StratumMessage::Subscribe(id, ..) => StratumMessage::Response(id, None, None),
+                StratumMessage::Authorize(id, ..) => {
+                    StratumMessage::Response(id, Some(ResponseMessage::Bool(true)), None)
+                }
+                _ => continue,
+            };
+            framed.send(response).await.unwrap();
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_addr = listener.local_addr().unwrap();
+    let mut config = ProxyConfig::new(
+        pool_addr.to_string(),
+        "proxy_account".to_string(),
+        "proxy".to_string(),
+    );
+    config.challenge = Some(Arc::new(
+        ChallengeAuthorizer::new().with_verifier(Box::new(Ed25519AccountVerifier)),
+    ));
+    let proxy = Proxy::new(config);
+    task::spawn(async move { proxy.run(listener).await });
+
+    let account = Ed25519AccountSigner::generate();
+    let mut miners = vec![];
+    let mut challenges = vec![];
+    for protocol_version in [advertise_challenge("0.2.0"), "0.2.0".to_string()] {
+        let stream = TcpStream::connect(proxy_addr).await.unwrap();
+        let mut miner = Framed::new(stream, StratumCodec::default());
+        miner
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                protocol_version,
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) => {
+                challenges.push(challenge_from_result(&result));
+            }
+            _ => panic!("expected subscribe response"),
+        }
+        miners.push(miner);
+    }
+    let challenge = challenges[0].clone().unwrap();
+    assert!(challenges[1].is_none());
+
+    // a password, another connection's challenge, or no challenge at all is refused
+    let refused = [
+        StratumMessage::Authorize(
+            Id::Num(1),
+            account.account_name(),
+            "rig".to_string(),
+            Some("password".to_string()),
+        ),
+        signed_authorize(Id::Num(2), &account, "rig".to_string(), &new_challenge()),
+    ];
+    for authorize in refused {
+        miners[0].send(authorize).await.unwrap();
+    }
+    let authorize = signed_authorize(Id::Num(1), &account, "rig".to_string(), &challenge);
+    miners[1].send(authorize).await.unwrap();
+    for miner in [0, 0, 1] {
+        match miners[miner].next().await {
+            Some(Ok(StratumMessage::Response(_, None, Some(error)))) => {
+                assert!(matches!(
+                    PoolError::from_str(&error.message),
+                    Ok(PoolError::Unauthorized(Some(_)))
+                ));
+            }
+            _ => panic!("expected a refused authorize"),
+        }
+    }
+
+    let authorize = signed_authorize(Id::Num(3), &account, "rig".to_string(), &challenge);
+    miners[0].send(authorize).await.unwrap();
+    assert!(matches!(
+        miners[0].next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(3),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+}
@@ -0,0 +1,38 @@
+use crate::message::workers::WorkerSession;
+use std::time::Instant;
+
+/// Counters kept by the proxy for every connected downstream miner.
+#[derive(Clone, Debug)]
+pub struct DownstreamStats {
+    pub connected_at: Instant,
+    /// Every worker authorized on the connection, with its share counts
+    pub workers: WorkerSession,
+    pub submitted: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub last_share_at: Option<Instant>,
+}
+
+impl DownstreamStats {
+    pub fn new() -> Self {
+        Self {
+            connected_at: Instant::now(),
+            workers: WorkerSession::new(),
+            submitted: 0,
+            accepted: 0,
+            rejected: 0,
+            last_share_at: None,
+        }
+    }
+
+    /// Shares forwarded upstream that have not been answered yet.
+    pub fn pending(&self) -> u64 {
+        self.submitted.saturating_sub(self.accepted + self.rejected)
+    }
+}
+
+impl Default for DownstreamStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
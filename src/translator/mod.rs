@@ -0,0 +1,690 @@
+use crate::message::error::PoolError;
+use crate::message::extranonce::{supports_extranonce, EXTRANONCE_PROTOCOL_EXTENSION};
+use crate::message::proof::{SubmitCheckCodec, SubmitFormats};
+use crate::message::puzzle::supports_testnet3;
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::StratumMessage;
+use crate::message::v2::translate::{error_to_v1, to_v1, to_v2};
+use crate::message::v2::{
+    NewMiningJob, OpenMiningChannel, SetupConnection, V2Codec, V2Message, V2_PROTOCOL_VERSION,
+};
+use crate::utils::extension::accept_extensions;
+use anyhow::anyhow;
+use futures_util::{SinkExt, StreamExt};
+use json_rpc_types::{Error, ErrorCode, Id};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio_util::codec::Framed;
+
+#[derive(Clone, Debug)]
+pub struct TranslatorConfig {
+    /// Address of the upstream pool speaking `message::v2`
+    pub upstream: String,
+    pub user_agent: String,
+    /// Nonce and proof lengths miners' submits must have, by their protocol version
+    pub submit_formats: SubmitFormats,
+}
+
+impl TranslatorConfig {
+    pub fn new(upstream: String) -> Self {
+        Self {
+            upstream,
+            user_agent: "ABMatrix_Stratum_Translator".to_string(),
+            submit_formats: SubmitFormats::default(),
+        }
+    }
+}
+
+struct Downstream {
+    tx: mpsc::UnboundedSender<StratumMessage>,
+    channel_id: Option<u32>,
+    /// Last job of the channel, sent again when the target changes
+    latest_job: Option<NewMiningJob>,
+    /// Whether the miner takes `mining.set_extranonce`
+    extranonce: bool,
+}
+
+struct PendingRequest {
+    downstream: SocketAddr,
+    id: Id,
+}
+
+#[derive(Default)]
+struct Shared {
+    downstreams: HashMap<SocketAddr, Downstream>,
+    channels: HashMap<u32, SocketAddr>,
+    /// OpenMiningChannel request id -> the downstream that authorized
+    opening: HashMap<u32, PendingRequest>,
+    /// SubmitShares sequence number -> the downstream that submitted
+    pending: HashMap<u32, PendingRequest>,
+    next_id: u32,
+}
+
+/// Serves legacy miners over `StratumCodec` and speaks the binary `message::v2` protocol
+/// upstream.
+///
+/// All miners share one upstream connection, every authorized miner gets its own mining
+/// channel, and a miner that already has one can't authorize again. Jobs and targets of a channel are relayed to its miner as `Notify`, submits are
+/// forwarded as `SubmitShares` under translator-assigned sequence numbers, and submit
+/// results, including `PoolError`s, are mapped back to the miner's request id. V2 jobs are
+/// block headers, so miners subscribing for testnet3 puzzle jobs are refused.
+#[derive(Clone)]
+pub struct Translator {
+    config: TranslatorConfig,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Translator {
+    pub fn new(config: TranslatorConfig) -> Self {
+        Self {
+            config,
+            shared: Arc::new(Mutex::new(Shared {
+                next_id: 1,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Connects to the upstream pool and serves miners from `listener` until the upstream
+    /// connection ends.
+    pub async fn run(&self, listener: TcpListener) -> anyhow::Result<()> {
+        let stream = TcpStream::connect(&self.config.upstream).await?;
+        let mut upstream = Framed::new(stream, V2Codec);
+        self.setup_connection(&mut upstream).await?;
+
+        let (upstream_tx, upstream_rx) = mpsc::unbounded_channel();
+        let translator = self.clone();
+        let mut upstream_handle =
+            task::spawn(async move { translator.serve_upstream(upstream, upstream_rx).await });
+
+        loop {
+            tokio::select! {
+                res = listener.accept() => {
+                    let (stream, addr) = res?;
+                    let translator = self.clone();
+                    let upstream_tx = upstream_tx.clone();
+                    task::spawn(async move {
+                        translator.serve_downstream(stream, addr, upstream_tx).await;
+                    });
+                }
+                res = &mut upstream_handle => {
+                    return match res {
+                        Ok(res) => res,
+                        Err(e) => Err(anyhow!(e)),
+                    };
+                }
+            }
+        }
+    }
+
+    async fn setup_connection(
+        &self,
+        upstream: &mut Framed<TcpStream, V2Codec>,
+    ) -> anyhow::Result<()> {
+        upstream
+            .send(V2Message::SetupConnection(SetupConnection {
+                request_id: 0,
+                min_version: V2_PROTOCOL_VERSION,
+                max_version: V2_PROTOCOL_VERSION,
+                flags: 0,
+                user_agent: self.config.user_agent.clone(),
+            }))
+            .await?;
+        match upstream.next().await {
+            Some(Ok(V2Message::SetupConnectionSuccess(..))) => Ok(()),
+            Some(Ok(V2Message::SetupConnectionError(e))) => {
+                Err(anyhow!("upstream rejected translator: {}", e.error_code))
+            }
+            Some(Ok(msg)) => Err(anyhow!("unexpected {} from upstream", msg.name())),
+            Some(Err(e)) => Err(e.into()),
+            None => Err(anyhow!("upstream disconnected")),
+        }
+    }
+
+    async fn serve_upstream(
+        &self,
+        mut upstream: Framed<TcpStream, V2Codec>,
+        mut upstream_rx: mpsc::UnboundedReceiver<V2Message>,
+    ) -> anyhow::Result<()> {
+        loop {
+            tokio::select! {
+                Some(msg) = upstream_rx.recv() => {
+                    upstream.send(msg).await?;
+                }
+                res = upstream.next() => {
+                    match res {
+                        Some(Ok(msg)) => self.handle_upstream(msg)?,
+                        Some(Err(e)) => return Err(e.into()),
+                        None => return Err(anyhow!("upstream disconnected")),
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_upstream(&self, msg: V2Message) -> anyhow::Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        match msg {
+            V2Message::OpenMiningChannelSuccess(m) => {
+                let opening = match shared.opening.remove(&m.request_id) {
+                    Some(opening) => opening,
+                    None => return Ok(()),
+                };
+                if let Some(downstream) = shared.downstreams.get_mut(&opening.downstream) {
+                    downstream.channel_id = Some(m.channel_id);
+                    shared.channels.insert(m.channel_id, opening.downstream);
+                    let _ = downstream.tx.send(StratumMessage::Response(
+                        opening.id,
+                        Some(ResponseMessage::Bool(true)),
+                        None,
+                    ));
+                }
+            }
+            V2Message::OpenMiningChannelError(m) => {
+                if let Some(opening) = shared.opening.remove(&m.request_id) {
+                    respond(shared, opening, V2Message::OpenMiningChannelError(m))?;
+                }
+            }
+            V2Message::NewMiningJob(job) => {
+                if let Some(downstream) = channel_downstream(shared, job.channel_id) {
+                    downstream.latest_job = Some(job.clone());
+                    let _ = downstream.tx.send(to_v1(V2Message::NewMiningJob(job))?);
+                }
+            }
+            V2Message::SetTarget(m) => {
+                // V1 miners take the target from Notify, so the current job is sent again
+                if let Some(downstream) = channel_downstream(shared, m.channel_id) {
+                    if let Some(job) = &mut downstream.latest_job {
+                        job.target = m.target;
+                        job.clean_jobs = false;
+                        let notify = to_v1(V2Message::NewMiningJob(job.clone()))?;
+                        let _ = downstream.tx.send(notify);
+                    }
+                }
+            }
+            V2Message::SetExtranoncePrefix(m) => {
+                if let Some(downstream) = channel_downstream(shared, m.channel_id) {
+                    if downstream.extranonce {
+                        let _ = downstream
+                            .tx
+                            .send(to_v1(V2Message::SetExtranoncePrefix(m))?);
+                    }
+                }
+            }
+            V2Message::SubmitSharesSuccess(ref m) => {
+                if let Some(pending) = shared.pending.remove(&m.sequence_number) {
+                    respond(shared, pending, msg)?;
+                }
+            }
+            V2Message::SubmitSharesError(ref m) => {
+                if let Some(pending) = shared.pending.remove(&m.sequence_number) {
+                    respond(shared, pending, msg)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn serve_downstream(
+        &self,
+        stream: TcpStream,
+        addr: SocketAddr,
+        upstream_tx: mpsc::UnboundedSender<V2Message>,
+    ) {
+        let mut framed = Framed::new(stream, SubmitCheckCodec::default());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        self.shared.lock().unwrap().downstreams.insert(
+            addr,
+            Downstream {
+                tx,
+                channel_id: None,
+                latest_job: None,
+                extranonce: false,
+            },
+        );
+
+        loop {
+            tokio::select! {
+                Some(msg) = rx.recv() => {
+                    if framed.send(msg).await.is_err() {
+                        break;
+                    }
+                }
+                res = framed.next() => {
+                    let msg = match res {
+                        Some(Ok(Ok(msg))) => msg,
+                        // a malformed nonce or proof is answered without reaching the pool
+                        Some(Ok(Err(invalid))) => {
+                            if framed.send(invalid.response()).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                        _ => break,
+                    };
+                    if let StratumMessage::Subscribe(_, _, protocol_version, _) = &msg {
+                        framed.codec_mut().format =
+                            self.config.submit_formats.for_protocol_version(protocol_version);
+                    }
+                    if self.handle_downstream(addr, msg, &upstream_tx).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(Downstream {
+            channel_id: Some(channel_id),
+            ..
+        }) = shared.downstreams.remove(&addr)
+        {
+            shared.channels.remove(&channel_id);
+        }
+    }
+
+    fn handle_downstream(
+        &self,
+        addr: SocketAddr,
+        msg: StratumMessage,
+        upstream_tx: &mpsc::UnboundedSender<V2Message>,
+    ) -> anyhow::Result<()> {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        let next_id = shared.next_id;
+        let downstream = shared
+            .downstreams
+            .get_mut(&addr)
+            .ok_or_else(|| anyhow!("unknown downstream {}", addr))?;
+        match msg {
+            StratumMessage::Subscribe(id, _, protocol_version, _) => {
+                if supports_testnet3(&protocol_version) {
+                    downstream.tx.send(StratumMessage::Response(
+                        id,
+                        None,
+                        Some(Error::with_custom_msg(
+                            ErrorCode::InvalidParams,
+                            "CoinbasePuzzle jobs are not served",
+                        )),
+                    ))?;
+                    return Ok(());
+                }
+                // the prefix follows in `mining.set_extranonce` once the channel is open
+                downstream.extranonce = supports_extranonce(&protocol_version);
+                let result = if downstream.extranonce {
+                    Some(accept_extensions(&[EXTRANONCE_PROTOCOL_EXTENSION]))
+                } else {
+                    None
+                };
+                downstream
+                    .tx
+                    .send(StratumMessage::Response(id, result, None))?;
+            }
+            StratumMessage::Authorize(id, account, worker, password) => {
+                // V2 can't close a channel, so a miner keeps the first one it opened
+                let opening = shared.opening.values().any(|p| p.downstream == addr);
+                if downstream.channel_id.is_some() || opening {
+                    let error = PoolError::Unauthorized(Some("channel already open".to_string()));
+                    downstream.tx.send(StratumMessage::Response(
+                        id,
+                        None,
+                        Some(error_to_v1(&error.to_string())),
+                    ))?;
+                    return Ok(());
+                }
+                shared.next_id = next_id.wrapping_add(1);
+                shared.opening.insert(
+                    next_id,
+                    PendingRequest {
+                        downstream: addr,
+                        id,
+                    },
+                );
+                upstream_tx.send(V2Message::OpenMiningChannel(OpenMiningChannel {
+                    request_id: next_id,
+                    account,
+                    worker,
+                    password: password.unwrap_or_default(),
+                }))?;
+            }
+            StratumMessage::Submit(id, job_id, nonce, proof, _) => {
+                let channel_id = match downstream.channel_id {
+                    Some(channel_id) => channel_id,
+                    None => {
+                        downstream.tx.send(StratumMessage::Response(
+                            id,
+                            None,
+                            Some(error_to_v1(&PoolError::Unauthorized(None).to_string())),
+                        ))?;
+                        return Ok(());
+                    }
+                };
+                let submit =
+                    StratumMessage::Submit(Id::Num(next_id as u64), job_id, nonce, proof, None);
+                match to_v2(submit, channel_id) {
+                    Ok(submit) => {
+                        shared.next_id = next_id.wrapping_add(1);
+                        shared.pending.insert(
+                            next_id,
+                            PendingRequest {
+                                downstream: addr,
+                                id,
+                            },
+                        );
+                        upstream_tx.send(submit)?;
+                    }
+                    // a job id this translator did not hand out, or fields that are not hex
+                    Err(e) => {
+                        let error = PoolError::InvalidProof(Some(e.to_string())).to_string();
+                        downstream.tx.send(StratumMessage::Response(
+                            id,
+                            None,
+                            Some(error_to_v1(&error)),
+                        ))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+fn channel_downstream(shared: &mut Shared, channel_id: u32) -> Option<&mut Downstream> {
+    let addr = shared.channels.get(&channel_id)?;
+    shared.downstreams.get_mut(addr)
+}
+
+/// Sends a V2 result to the downstream that made the request, under its own id.
+fn respond(shared: &mut Shared, pending: PendingRequest, msg: V2Message) -> anyhow::Result<()> {
+    let response = match to_v1(msg)? {
+        StratumMessage::Response(_, result, error) => {
+            StratumMessage::Response(pending.id, result, error)
+        }
+        _ => return Ok(()),
+    };
+    if let Some(downstream) = shared.downstreams.get(&pending.downstream) {
+        let _ = downstream.tx.send(response);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_translator() {
+    use crate::message::stratum::StratumCodec;
+    use crate::message::v2::translate::job_id_to_v1;
+    use crate::message::v2::{
+        OpenMiningChannelError, OpenMiningChannelSuccess, SetTarget, SetupConnectionSuccess,
+        SubmitSharesError, SubmitSharesSuccess,
+    };
+    use std::str::FromStr;
+
+    // mock V2 pool
+    let pool = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let pool_addr = pool.local_addr().unwrap();
+    task::spawn(async move {
+        let (stream, _) = pool.accept().await.unwrap();
+        let mut framed = Framed::new(stream, V2Codec);
+        let mut next_channel = 10;
+        while let Some(Ok(msg)) = framed.next().await {
+            let replies = match msg {
+                V2Message::SetupConnection(m) => {
+                    vec![V2Message::SetupConnectionSuccess(SetupConnectionSuccess {
+                        request_id: m.request_id,
+                        used_version: V2_PROTOCOL_VERSION,
+                        flags: 0,
+                    })]
+                }
+                V2Message::OpenMiningChannel(m) if m.worker == "bad" => {
+                    vec![V2Message::OpenMiningChannelError(OpenMiningChannelError {
+                        request_id: m.request_id,
+                        error_code: "unknown-user".to_string(),
+                    })]
+                }
+                V2Message::OpenMiningChannel(m) => {
+                    next_channel += 1;
+                    let job = NewMiningJob {
+                        channel_id: next_channel,
+                        job_id: 5,
+                        height: 685514,
+                        target: 1000,
+                        clean_jobs: true,
+                        header_root: [1; 32],
+                        hashed_leaves: [[2; 32]; 4],
+                    };
+                    vec![
+                        V2Message::OpenMiningChannelSuccess(OpenMiningChannelSuccess {
+                            request_id: m.request_id,
+                            channel_id: next_channel,
+                            target: 1000,
+                        }),
+                        V2Message::NewMiningJob(job),
+                        V2Message::SetTarget(SetTarget {
+                            channel_id: next_channel,
+                            target: 500,
+                        }),
+                    ]
+                }
+                V2Message::SubmitShares(m) if m.nonce[0] == 1 => {
+                    vec![V2Message::SubmitSharesSuccess(SubmitSharesSuccess {
+                        channel_id: m.channel_id,
+                        sequence_number: m.sequence_number,
+                    })]
+                }
+                V2Message::SubmitShares(m) => {
+                    vec![V2Message::SubmitSharesError(SubmitSharesError {
+                        channel_id: m.channel_id,
+                        sequence_number: m.sequence_number,
+                        error_code: PoolError::StaleProof.to_string(),
+                    })]
+                }
+                _ => vec![],
+            };
+            for reply in replies {
+                framed.send(reply).await.unwrap();
+            }
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let translator_addr = listener.local_addr().unwrap();
+    let translator = Translator::new(TranslatorConfig::new(pool_addr.to_string()));
+    task::spawn(async move { translator.run(listener).await });
+
+    let stream = TcpStream::connect(translator_addr).await.unwrap();
+    let mut miner = Framed::new(stream, StratumCodec::default());
+    // there are no testnet3 jobs to translate
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            crate::message::puzzle::advertise_testnet3("0.2.0"),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(Id::Num(0), None, Some(_))))
+    ));
+    miner
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "miner".to_string(),
+            "0.2.0".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(Id::Num(0), _, None)))
+    ));
+    miner
+        .send(StratumMessage::Authorize(
+            Id::Num(1),
+            "account".to_string(),
+            "rig".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(1),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+
+    let job_id = job_id_to_v1(685514, 5);
+    for (target, clean) in [(1000, true), (500, false)] {
+        match miner.next().await {
+            Some(Ok(StratumMessage::Notify(id, t, root, leaf, .., clean_jobs, _))) => {
+                assert_eq!(id, job_id);
+                assert_eq!(t, target);
+                assert_eq!(root, hex::encode([1u8; 32]));
+                assert_eq!(leaf, hex::encode([2u8; 32]));
+                assert_eq!(clean_jobs, clean);
+            }
+            _ => panic!("expected a job"),
+        }
+    }
+
+    for (id, nonce) in [(7, "0100000000000000"), (8, "0200000000000000")] {
+        miner
+            .send(StratumMessage::Submit(
+                Id::Num(id),
+                job_id.clone(),
+                nonce.to_string(),
+                "abcd".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+    }
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(7),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+    match miner.next().await {
+        Some(Ok(StratumMessage::Response(Id::Num(8), None, Some(error)))) => {
+            assert_eq!(
+                PoolError::from_str(&error.message).unwrap(),
+                PoolError::StaleProof
+            );
+        }
+        _ => panic!("expected a rejected share"),
+    }
+
+    // a job id the translator never handed out, and a nonce of the wrong length
+    for (id, job_id, nonce) in [
+        (9, "job_id".to_string(), "0100000000000000"),
+        (10, job_id.clone(), "01"),
+    ] {
+        miner
+            .send(StratumMessage::Submit(
+                Id::Num(id),
+                job_id,
+                nonce.to_string(),
+                "abcd".to_string(),
+                None,
+            ))
+            .await
+            .unwrap();
+        match miner.next().await {
+            Some(Ok(StratumMessage::Response(Id::Num(i), None, Some(error)))) if i == id => {
+                assert!(matches!(
+                    PoolError::from_str(&error.message),
+                    Ok(PoolError::InvalidProof(_))
+                ));
+            }
+            _ => panic!("expected an invalid share"),
+        }
+    }
+
+    // authorizing again keeps the channel already open
+    miner
+        .send(StratumMessage::Authorize(
+            Id::Num(11),
+            "account".to_string(),
+            "rig2".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    match miner.next().await {
+        Some(Ok(StratumMessage::Response(Id::Num(11), None, Some(error)))) => {
+            assert_eq!(
+                PoolError::from_str(&error.message).unwrap(),
+                PoolError::Unauthorized(Some("channel already open".to_string()))
+            );
+        }
+        _ => panic!("expected a refused authorize"),
+    }
+    miner
+        .send(StratumMessage::Submit(
+            Id::Num(12),
+            job_id.clone(),
+            "0100000000000000".to_string(),
+            "abcd".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        miner.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(12),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+
+    // a worker the pool does not know
+    let stream = TcpStream::connect(translator_addr).await.unwrap();
+    let mut other = Framed::new(stream, StratumCodec::default());
+    other
+        .send(StratumMessage::Authorize(
+            Id::Num(1),
+            "account".to_string(),
+            "bad".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        other.next().await,
+        Some(Ok(StratumMessage::Response(Id::Num(1), None, Some(_))))
+    ));
+    other
+        .send(StratumMessage::Submit(
+            Id::Num(2),
+            job_id,
+            "0100000000000000".to_string(),
+            "abcd".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    match other.next().await {
+        Some(Ok(StratumMessage::Response(Id::Num(2), None, Some(error)))) => {
+            assert_eq!(
+                PoolError::from_str(&error.message).unwrap(),
+                PoolError::Unauthorized(None)
+            );
+        }
+        _ => panic!("expected an unauthorized share"),
+    }
+}
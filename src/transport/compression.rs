@@ -0,0 +1,380 @@
+use crate::message::response::ResponseMessage;
+use crate::message::stratum::{StratumCodec, StratumMessage, MAX_STRATUM_LINE_LENGTH};
+use crate::utils::extension::{accepted_extensions, add_extension, has_extension};
+use bytes::{Buf, BufMut, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use zstd::stream::raw::{
+    Decoder as ZstdDecoder, Encoder as ZstdEncoder, InBuffer, Operation, OutBuffer,
+};
+
+/// Protocol extension offered in `mining.subscribe` and accepted in its result.
+pub static COMPRESSION_PROTOCOL_EXTENSION: &str = "zstd";
+
+/// Largest compressed chunk accepted, excluding the length prefix.
+pub const MAX_COMPRESSED_CHUNK_LENGTH: usize = 1 << 20;
+
+/// Most decompressed bytes buffered at once. A chunk carries one message, so anything past
+/// a full `StratumCodec` line is a decompression bomb.
+pub const MAX_DECOMPRESSED_LENGTH: usize = 2 * MAX_STRATUM_LINE_LENGTH;
+
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
+
+/// Shared zstd dictionary of the JSON that makes up most Stratum traffic. Both peers must
+/// use the same bytes, changing it needs a new extension name.
+pub static STRATUM_DICTIONARY: &[u8] = br#"{"jsonrpc":"2.0","result":null,"id":0}
+{"jsonrpc":"2.0","error":{"code":-32602,"message":"InvalidProof"},"id":1}
+{"jsonrpc":"2.0","error":{"code":-32000,"message":"StaleProof"},"id":2}
+{"jsonrpc":"2.0","error":{"code":-32603,"message":"ServerNotReady"},"id":3}
+{"jsonrpc":"2.0","method":"mining.subscribe","params":["ABMatrix_Aleo_Miner","0.2.0",null],"id":0}
+{"jsonrpc":"2.0","method":"mining.authorize","params":["aleo1","worker",null],"id":1}
+{"jsonrpc":"2.0","method":"mining.submit","params":["0a0b0c0d_","",""],"id":2}
+{"jsonrpc":"2.0","result":true,"id":2}
+{"jsonrpc":"2.0","result":false,"id":3}
+{"jsonrpc":"2.0","method":"mining.notify","params":["0a0b0c0d_",18446744073709551615,"","","","","",true]}
+{"jsonrpc":"2.0","method":"mining.notify","params":["0a0b0c0d_",9223372036854775807,"","","","","",false]}
+"#;
+
+/// `StratumCodec` wrapped in a per-connection zstd stream.
+///
+/// Each encoded message is flushed as one length-prefixed chunk (`u32` big endian), so a
+/// message is never delayed, while the stream keeps its history across messages: repeated
+/// job fields and the JSON-RPC envelope compress to a few bytes after the first `Notify`.
+///
+/// Negotiated like the binary framing: the miner offers `COMPRESSION_PROTOCOL_EXTENSION`
+/// with `advertise_compression`, and a pool that accepts lists it in its subscribe result
+/// (see `utils::extension::accept_extensions`). Peers that do not offer it stay on plain
+/// `StratumCodec`.
+pub struct CompressedStratumCodec {
+    inner: StratumCodec,
+    encoder: ZstdEncoder<'static>,
+    decoder: ZstdDecoder<'static>,
+    /// Decompressed bytes not yet framed by `inner`.
+    plain: BytesMut,
+}
+
+impl CompressedStratumCodec {
+    pub fn new(level: i32, dictionary: &[u8]) -> io::Result<Self> {
+        Ok(Self {
+            inner: StratumCodec::default(),
+            encoder: ZstdEncoder::with_dictionary(level, dictionary)?,
+            decoder: ZstdDecoder::with_dictionary(dictionary)?,
+            plain: BytesMut::new(),
+        })
+    }
+
+    pub fn with_stratum_dictionary() -> io::Result<Self> {
+        Self::new(DEFAULT_COMPRESSION_LEVEL, STRATUM_DICTIONARY)
+    }
+}
+
+pub fn advertise_compression(protocol_version: &str) -> String {
+    add_extension(protocol_version, COMPRESSION_PROTOCOL_EXTENSION)
+}
+
+/// Whether a `mining.subscribe` protocol version offers compression.
+pub fn supports_compression(protocol_version: &str) -> bool {
+    has_extension(protocol_version, COMPRESSION_PROTOCOL_EXTENSION)
+}
+
+/// Whether the pool accepted compression in its `mining.subscribe` result.
+pub fn compression_accepted(result: &Option<ResponseMessage>) -> bool {
+    accepted_extensions(result)
+        .iter()
+        .any(|e| e == COMPRESSION_PROTOCOL_EXTENSION)
+}
+
+/// Swaps the codec of a negotiated connection, keeping any buffered bytes.
+pub fn switch_to_compressed<T>(
+    framed: Framed<T, StratumCodec>,
+) -> io::Result<Framed<T, CompressedStratumCodec>> {
+    let codec = CompressedStratumCodec::with_stratum_dictionary()?;
+    Ok(framed.map_codec(|_| codec))
+}
+
+impl Encoder<StratumMessage> for CompressedStratumCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: StratumMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut line = BytesMut::new();
+        self.inner.encode(item, &mut line)?;
+
+        let mut compressed = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let mut input = InBuffer::around(&line);
+        while input.pos() < line.len() {
+            let mut output = OutBuffer::around(&mut chunk[..]);
+            self.encoder.run(&mut input, &mut output)?;
+            compressed.extend_from_slice(output.as_slice());
+        }
+        loop {
+            let mut output = OutBuffer::around(&mut chunk[..]);
+            let remaining = self.encoder.flush(&mut output)?;
+            compressed.extend_from_slice(output.as_slice());
+            if remaining == 0 {
+                break;
+            }
+        }
+
+        if compressed.len() > MAX_COMPRESSED_CHUNK_LENGTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Chunk too long"));
+        }
+        dst.reserve(4 + compressed.len());
+        dst.put_u32(compressed.len() as u32);
+        dst.extend_from_slice(&compressed);
+        Ok(())
+    }
+}
+
+impl Decoder for CompressedStratumCodec {
+    type Item = StratumMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            if let Some(msg) = self.inner.decode(&mut self.plain)? {
+                return Ok(Some(msg));
+            }
+            if src.len() < 4 {
+                return Ok(None);
+            }
+            let len = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+            if len > MAX_COMPRESSED_CHUNK_LENGTH {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Chunk too long"));
+            }
+            if src.len() < 4 + len {
+                src.reserve(4 + len - src.len());
+                return Ok(None);
+            }
+            src.advance(4);
+            let compressed = src.split_to(len);
+
+            let mut chunk = [0u8; 4096];
+            let mut input = InBuffer::around(&compressed);
+            loop {
+                let mut output = OutBuffer::around(&mut chunk[..]);
+                self.decoder.run(&mut input, &mut output)?;
+                let written = output.as_slice();
+                if self.plain.len() + written.len() > MAX_DECOMPRESSED_LENGTH {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Decompressed chunk too long",
+                    ));
+                }
+                self.plain.extend_from_slice(written);
+                // the encoder flushed after every message, so once the input is consumed
+                // and the output has room to spare everything has been produced
+                if input.pos() == compressed.len() && written.len() < chunk.len() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn realistic_messages(count: usize) -> Vec<StratumMessage> {
+    use json_rpc_types::Id;
+
+    // xorshift, job fields are hashes and look random
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut random_hex = |len: usize| {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            bytes.extend_from_slice(&state.to_le_bytes());
+        }
+        bytes.truncate(len);
+        hex::encode(bytes)
+    };
+
+    let mut messages = vec![];
+    for i in 0..count {
+        let job_id = format!(
+            "{}_{}",
+            hex::encode((685514 + i as u32 / 10).to_le_bytes()),
+            i
+        );
+        if i % 10 == 0 {
+            messages.push(StratumMessage::Notify(
+                job_id,
+                u64::MAX / 1000,
+                random_hex(32),
+                random_hex(32),
+                random_hex(32),
+                random_hex(32),
+                random_hex(32),
+                true,
+                None,
+            ));
+        } else if i % 2 == 0 {
+            messages.push(StratumMessage::Submit(
+                Id::Num(i as u64),
+                job_id,
+                random_hex(8),
+                random_hex(691),
+                None,
+            ));
+        } else {
+            messages.push(StratumMessage::Response(
+                Id::Num(i as u64 - 1),
+                Some(ResponseMessage::Bool(true)),
+                None,
+            ));
+        }
+    }
+    messages
+}
+
+#[test]
+fn test_compressed_roundtrip() {
+    let mut sender = CompressedStratumCodec::with_stratum_dictionary().unwrap();
+    let mut receiver = CompressedStratumCodec::with_stratum_dictionary().unwrap();
+
+    let mut wire = BytesMut::new();
+    let mut plain = BytesMut::new();
+    for (msg, again) in realistic_messages(200)
+        .into_iter()
+        .zip(realistic_messages(200))
+    {
+        sender.encode(msg, &mut wire).unwrap();
+        StratumCodec::default().encode(again, &mut plain).unwrap();
+    }
+    assert!(wire.len() < plain.len());
+
+    // feed the receiver in small pieces
+    let mut src = BytesMut::new();
+    let mut decoded = vec![];
+    for piece in wire.chunks(7) {
+        src.extend_from_slice(piece);
+        while let Some(msg) = receiver.decode(&mut src).unwrap() {
+            decoded.push(msg.to_json());
+        }
+    }
+    let expected = realistic_messages(200)
+        .into_iter()
+        .map(|m| m.to_json())
+        .collect::<Vec<_>>();
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn test_decompression_bomb() {
+    let mut sender = CompressedStratumCodec::with_stratum_dictionary().unwrap();
+    let mut receiver = CompressedStratumCodec::with_stratum_dictionary().unwrap();
+
+    // 64 MiB of spaces compresses to a few KiB, well under MAX_COMPRESSED_CHUNK_LENGTH
+    let line = vec![b' '; 64 << 20];
+    let mut compressed = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let mut input = InBuffer::around(&line);
+    while input.pos() < line.len() {
+        let mut output = OutBuffer::around(&mut chunk[..]);
+        sender.encoder.run(&mut input, &mut output).unwrap();
+        compressed.extend_from_slice(output.as_slice());
+    }
+    loop {
+        let mut output = OutBuffer::around(&mut chunk[..]);
+        let remaining = sender.encoder.flush(&mut output).unwrap();
+        compressed.extend_from_slice(output.as_slice());
+        if remaining == 0 {
+            break;
+        }
+    }
+    assert!(compressed.len() <= MAX_COMPRESSED_CHUNK_LENGTH);
+
+    let mut src = BytesMut::new();
+    src.put_u32(compressed.len() as u32);
+    src.extend_from_slice(&compressed);
+    match receiver.decode(&mut src) {
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+        _ => panic!("expected the chunk to be rejected"),
+    }
+    assert!(receiver.plain.len() <= MAX_DECOMPRESSED_LENGTH);
+}
+
+#[test]
+fn test_dictionary_helps_small_messages() {
+    use json_rpc_types::Id;
+
+    let encoded_len = |dictionary: &[u8]| {
+        let mut codec = CompressedStratumCodec::new(DEFAULT_COMPRESSION_LEVEL, dictionary).unwrap();
+        let mut dst = BytesMut::new();
+        let msg = StratumMessage::Response(Id::Num(17), Some(ResponseMessage::Bool(true)), None);
+        codec.encode(msg, &mut dst).unwrap();
+        dst.len()
+    };
+    assert!(encoded_len(STRATUM_DICTIONARY) < encoded_len(&[]));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_negotiation_and_fallback() {
+    use crate::utils::extension::accept_extensions;
+    use futures_util::{SinkExt, StreamExt};
+    use json_rpc_types::Id;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(stream, StratumCodec::default());
+            let (id, protocol_version) = match framed.next().await {
+                Some(Ok(StratumMessage::Subscribe(id, _, protocol_version, _))) => {
+                    (id, protocol_version)
+                }
+                _ => continue,
+            };
+            let notify = realistic_messages(1).remove(0);
+            if supports_compression(&protocol_version) {
+                let accept = accept_extensions(&[COMPRESSION_PROTOCOL_EXTENSION]);
+                framed
+                    .send(StratumMessage::Response(id, Some(accept), None))
+                    .await
+                    .unwrap();
+                let mut framed = switch_to_compressed(framed).unwrap();
+                framed.send(notify).await.unwrap();
+            } else {
+                framed
+                    .send(StratumMessage::Response(id, None, None))
+                    .await
+                    .unwrap();
+                framed.send(notify).await.unwrap();
+            }
+        }
+    });
+
+    for offer in [true, false] {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        let version = if offer {
+            advertise_compression("0.2.0")
+        } else {
+            "0.2.0".to_string()
+        };
+        framed
+            .send(StratumMessage::Subscribe(
+                Id::Num(0),
+                "miner".to_string(),
+                version,
+                None,
+            ))
+            .await
+            .unwrap();
+        let accepted = match framed.next().await {
+            Some(Ok(StratumMessage::Response(_, result, None))) => compression_accepted(&result),
+            _ => panic!("expected subscribe response"),
+        };
+        assert_eq!(accepted, offer);
+        let job = if accepted {
+            switch_to_compressed(framed).unwrap().next().await
+        } else {
+            framed.next().await
+        };
+        assert!(matches!(job, Some(Ok(StratumMessage::Notify(..)))));
+    }
+}
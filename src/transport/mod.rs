@@ -0,0 +1,10 @@
+#[cfg(feature = "tools")]
+pub mod recording;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "compression")]
+pub mod compression;
+#[cfg(feature = "noise")]
+pub mod noise;
@@ -0,0 +1,294 @@
+use crate::message::stratum::{StratumCodec, StratumMessage};
+use bytes::{Buf, BufMut, BytesMut};
+use snow::params::NoiseParams;
+use snow::{Builder, HandshakeState, Keypair, TransportState};
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+/// The pool sends its static key during the handshake, the miner sends none.
+pub static NOISE_PARAMS: &str = "Noise_NX_25519_ChaChaPoly_BLAKE2s";
+
+/// Upper bound of one Noise message, the length prefix is a `u16`.
+const MAX_NOISE_MESSAGE_LENGTH: usize = 65535;
+const TAG_LENGTH: usize = 16;
+
+pub type NoiseFramed<S> = Framed<S, NoiseStratumCodec>;
+
+/// Generates the pool's static keypair. The public half is published out of band (e.g. hex
+/// encoded next to the pool address) and pinned by miners in `connect`.
+pub fn generate_keypair() -> io::Result<Keypair> {
+    builder()?.generate_keypair().map_err(to_io_error)
+}
+
+fn builder() -> io::Result<Builder<'static>> {
+    let params: NoiseParams = NOISE_PARAMS.parse().map_err(to_io_error)?;
+    Ok(Builder::new(params))
+}
+
+fn to_io_error(e: snow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+/// `StratumCodec` inside an established Noise session.
+///
+/// Every encoded message is encrypted as one or more Noise messages, each prefixed with its
+/// length as a `u16` big endian. A frame that fails authentication is an error, so a middlebox
+/// can neither read nor alter jobs and shares.
+pub struct NoiseStratumCodec {
+    inner: StratumCodec,
+    transport: TransportState,
+    /// Decrypted bytes not yet framed by `inner`.
+    plain: BytesMut,
+}
+
+impl NoiseStratumCodec {
+    pub fn new(transport: TransportState) -> Self {
+        Self {
+            inner: StratumCodec::default(),
+            transport,
+            plain: BytesMut::new(),
+        }
+    }
+
+    /// The peer's static key, the pool's key on the miner side.
+    pub fn remote_static(&self) -> Option<&[u8]> {
+        self.transport.get_remote_static()
+    }
+}
+
+/// Runs the miner side of the handshake and rejects the pool unless it proves ownership of
+/// `pool_key`.
+pub async fn connect<S>(mut stream: S, pool_key: &[u8]) -> io::Result<NoiseFramed<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut handshake = builder()?.build_initiator().map_err(to_io_error)?;
+    // -> e
+    write_handshake(&mut stream, &mut handshake).await?;
+    // <- e, ee, s, es
+    read_handshake(&mut stream, &mut handshake).await?;
+    if handshake.get_remote_static() != Some(pool_key) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "pool key does not match the pinned key",
+        ));
+    }
+    let transport = handshake.into_transport_mode().map_err(to_io_error)?;
+    Ok(Framed::new(stream, NoiseStratumCodec::new(transport)))
+}
+
+/// Runs the pool side of the handshake with the pool's static private key.
+pub async fn accept<S>(mut stream: S, private_key: &[u8]) -> io::Result<NoiseFramed<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut handshake = builder()?
+        .local_private_key(private_key)
+        .build_responder()
+        .map_err(to_io_error)?;
+    read_handshake(&mut stream, &mut handshake).await?;
+    write_handshake(&mut stream, &mut handshake).await?;
+    let transport = handshake.into_transport_mode().map_err(to_io_error)?;
+    Ok(Framed::new(stream, NoiseStratumCodec::new(transport)))
+}
+
+async fn write_handshake<S>(stream: &mut S, handshake: &mut HandshakeState) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    let len = handshake
+        .write_message(&[], &mut buf)
+        .map_err(to_io_error)?;
+    stream.write_u16(len as u16).await?;
+    stream.write_all(&buf[..len]).await?;
+    stream.flush().await
+}
+
+async fn read_handshake<S>(stream: &mut S, handshake: &mut HandshakeState) -> io::Result<()>
+where
+    S: AsyncRead + Unpin,
+{
+    let len = stream.read_u16().await? as usize;
+    let mut msg = vec![0u8; len];
+    stream.read_exact(&mut msg).await?;
+    let mut payload = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    handshake
+        .read_message(&msg, &mut payload)
+        .map_err(to_io_error)?;
+    Ok(())
+}
+
+impl Encoder<StratumMessage> for NoiseStratumCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: StratumMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let mut line = BytesMut::new();
+        self.inner.encode(item, &mut line)?;
+
+        let mut buf = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+        for chunk in line.chunks(MAX_NOISE_MESSAGE_LENGTH - TAG_LENGTH) {
+            let len = self
+                .transport
+                .write_message(chunk, &mut buf)
+                .map_err(to_io_error)?;
+            dst.reserve(2 + len);
+            dst.put_u16(len as u16);
+            dst.extend_from_slice(&buf[..len]);
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for NoiseStratumCodec {
+    type Item = StratumMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let mut buf = vec![];
+        loop {
+            if let Some(msg) = self.inner.decode(&mut self.plain)? {
+                return Ok(Some(msg));
+            }
+            if src.len() < 2 {
+                return Ok(None);
+            }
+            let len = u16::from_be_bytes([src[0], src[1]]) as usize;
+            if src.len() < 2 + len {
+                src.reserve(2 + len - src.len());
+                return Ok(None);
+            }
+            src.advance(2);
+            let encrypted = src.split_to(len);
+            buf.resize(len, 0);
+            let len = self
+                .transport
+                .read_message(&encrypted, &mut buf)
+                .map_err(to_io_error)?;
+            self.plain.extend_from_slice(&buf[..len]);
+        }
+    }
+}
+
+#[cfg(test)]
+fn codec_pair(pool: &Keypair) -> (NoiseStratumCodec, NoiseStratumCodec) {
+    let mut miner = builder().unwrap().build_initiator().unwrap();
+    let mut pool = builder()
+        .unwrap()
+        .local_private_key(&pool.private)
+        .build_responder()
+        .unwrap();
+    let mut msg = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    let mut payload = vec![0u8; MAX_NOISE_MESSAGE_LENGTH];
+    let len = miner.write_message(&[], &mut msg).unwrap();
+    pool.read_message(&msg[..len], &mut payload).unwrap();
+    let len = pool.write_message(&[], &mut msg).unwrap();
+    miner.read_message(&msg[..len], &mut payload).unwrap();
+    (
+        NoiseStratumCodec::new(miner.into_transport_mode().unwrap()),
+        NoiseStratumCodec::new(pool.into_transport_mode().unwrap()),
+    )
+}
+
+#[cfg(test)]
+fn notify() -> StratumMessage {
+    StratumMessage::Notify(
+        "job_id".to_string(),
+        u64::MAX,
+        "block_header_root".to_string(),
+        "hashed_leaves_1".to_string(),
+        "hashed_leaves_2".to_string(),
+        "hashed_leaves_3".to_string(),
+        "hashed_leaves_4".to_string(),
+        true,
+        None,
+    )
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_noise_pinned_pool_key() {
+    use crate::message::response::ResponseMessage;
+    use futures_util::{SinkExt, StreamExt};
+    use json_rpc_types::Id;
+    use tokio::net::{TcpListener, TcpStream};
+
+    let pool_key = generate_keypair().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let private_key = pool_key.private.clone();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut framed = match accept(stream, &private_key).await {
+                Ok(framed) => framed,
+                Err(_) => continue,
+            };
+            if let Some(Ok(StratumMessage::Authorize(id, ..))) = framed.next().await {
+                framed
+                    .send(StratumMessage::Response(
+                        id,
+                        Some(ResponseMessage::Bool(true)),
+                        None,
+                    ))
+                    .await
+                    .unwrap();
+                framed.send(notify()).await.unwrap();
+            }
+        }
+    });
+
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let mut framed = connect(stream, &pool_key.public).await.unwrap();
+    assert_eq!(framed.codec().remote_static(), Some(&pool_key.public[..]));
+    framed
+        .send(StratumMessage::Authorize(
+            Id::Num(1),
+            "account_name".to_string(),
+            "miner_name".to_string(),
+            None,
+        ))
+        .await
+        .unwrap();
+    assert!(matches!(
+        framed.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(1),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+    assert!(matches!(
+        framed.next().await,
+        Some(Ok(StratumMessage::Notify(..)))
+    ));
+
+    // an impostor pool, or a pool with a rotated key, is rejected before any message is sent
+    let other = generate_keypair().unwrap();
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let e = connect(stream, &other.public).await.err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn test_noise_tampered_frame() {
+    let pool_key = generate_keypair().unwrap();
+    let (mut miner, mut pool) = codec_pair(&pool_key);
+
+    let mut wire = BytesMut::new();
+    pool.encode(notify(), &mut wire).unwrap();
+    // nothing of the job is visible on the wire
+    assert!(!wire.windows(6).any(|w| w == b"job_id"));
+
+    assert!(matches!(
+        miner.decode(&mut wire).unwrap(),
+        Some(StratumMessage::Notify(..))
+    ));
+
+    let mut wire = BytesMut::new();
+    pool.encode(notify(), &mut wire).unwrap();
+    let last = wire.len() - 1;
+    wire[last] ^= 1;
+    assert!(miner.decode(&mut wire).is_err());
+}
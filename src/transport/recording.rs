@@ -0,0 +1,400 @@
+use bytes::BytesMut;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio_util::codec::{Decoder, Encoder, Framed, FramedRead};
+
+/// Which way a frame went, seen from the recorded side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Read from the peer
+    Inbound,
+    /// Sent to the peer
+    Outbound,
+}
+
+impl Direction {
+    fn name(&self) -> &'static str {
+        match self {
+            Direction::Inbound => "in",
+            Direction::Outbound => "out",
+        }
+    }
+}
+
+/// The exact bytes of one frame and when they went, since the recording started.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedFrame {
+    pub elapsed: Duration,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+/// One recording line.
+#[derive(Serialize, Deserialize)]
+struct FrameLine {
+    elapsed_us: u64,
+    direction: String,
+    data: String,
+}
+
+impl RecordedFrame {
+    fn to_line(&self) -> io::Result<String> {
+        serde_json::to_string(&FrameLine {
+            elapsed_us: self.elapsed.as_micros() as u64,
+            direction: self.direction.name().to_string(),
+            data: hex::encode(&self.data),
+        })
+        .map_err(io::Error::from)
+    }
+
+    fn from_line(line: &str) -> io::Result<Self> {
+        let line: FrameLine = serde_json::from_str(line)?;
+        let direction = match line.direction.as_str() {
+            "in" => Direction::Inbound,
+            "out" => Direction::Outbound,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid frame direction",
+                ))
+            }
+        };
+        Ok(Self {
+            elapsed: Duration::from_micros(line.elapsed_us),
+            direction,
+            data: hex::decode(&line.data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+        })
+    }
+}
+
+/// Writes frames to a recording, one JSON line each with the bytes in hex. Every frame is
+/// flushed, so a recording survives the crash it is meant to explain.
+pub struct Recorder {
+    writer: Box<dyn Write + Send>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Box::new(writer),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn record(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let frame = RecordedFrame {
+            elapsed: self.start.elapsed(),
+            direction,
+            data: data.to_vec(),
+        };
+        writeln!(self.writer, "{}", frame.to_line()?)?;
+        self.writer.flush()
+    }
+}
+
+/// Reads back the frames of a recording.
+pub fn read_recording(reader: impl BufRead) -> io::Result<Vec<RecordedFrame>> {
+    let mut frames = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        if !line.is_empty() {
+            frames.push(RecordedFrame::from_line(&line)?);
+        }
+    }
+    Ok(frames)
+}
+
+/// Records the bytes of every frame `inner` decodes or encodes, including frames that fail
+/// to decode.
+pub struct RecordingCodec<C> {
+    inner: C,
+    recorder: Recorder,
+    /// Copy of the bytes in the read buffer `inner` has not consumed yet. Each byte is
+    /// copied once, when it is first seen, instead of the whole buffer on every decode.
+    unconsumed: BytesMut,
+}
+
+impl<C> RecordingCodec<C> {
+    pub fn new(inner: C, recorder: Recorder) -> Self {
+        Self {
+            inner,
+            recorder,
+            unconsumed: BytesMut::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+/// Starts recording a connection, keeping any buffered bytes.
+pub fn record<T, C>(framed: Framed<T, C>, recorder: Recorder) -> Framed<T, RecordingCodec<C>> {
+    framed.map_codec(|codec| RecordingCodec::new(codec, recorder))
+}
+
+impl<C: Decoder> Decoder for RecordingCodec<C> {
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // the read buffer only grows at the back and `inner` consumes from the front, so
+        // `unconsumed` is always a prefix of it
+        let seen = self.unconsumed.len();
+        self.unconsumed.extend_from_slice(&src[seen..]);
+        let before = src.len();
+        let result = self.inner.decode(src);
+        let consumed = before - src.len();
+        if consumed > 0 {
+            let frame = self.unconsumed.split_to(consumed);
+            self.recorder.record(Direction::Inbound, &frame)?;
+        }
+        result
+    }
+}
+
+impl<I, C: Encoder<I>> Encoder<I> for RecordingCodec<C> {
+    type Error = C::Error;
+
+    fn encode(&mut self, item: I, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let start = dst.len();
+        self.inner.encode(item, dst)?;
+        self.recorder.record(Direction::Outbound, &dst[start..])?;
+        Ok(())
+    }
+}
+
+/// How fast a replay goes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    /// The recorded gaps between frames
+    Original,
+    /// The recorded gaps divided by the factor
+    Accelerated(u32),
+    /// No gaps
+    Immediate,
+}
+
+impl Timing {
+    pub fn scale(&self, gap: Duration) -> Duration {
+        match self {
+            Timing::Original => gap,
+            Timing::Accelerated(factor) => gap / (*factor).max(1),
+            Timing::Immediate => Duration::ZERO,
+        }
+    }
+}
+
+/// Plays one side of a recording to a client or server under test.
+///
+/// Frames stay in their recorded order: before sending a frame, the replayer waits for as
+/// many frames from the peer as were recorded before it, so a replayed pool never answers a
+/// request that has not come yet.
+pub struct Replayer {
+    frames: Vec<RecordedFrame>,
+    timing: Timing,
+    /// How long to wait for each frame from the peer
+    pub peer_timeout: Duration,
+}
+
+impl Replayer {
+    pub fn new(frames: Vec<RecordedFrame>) -> Self {
+        Self {
+            frames,
+            timing: Timing::Original,
+            peer_timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(read_recording(BufReader::new(File::open(
+            path,
+        )?))?))
+    }
+
+    pub fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    pub fn frames(&self) -> &[RecordedFrame] {
+        &self.frames
+    }
+
+    /// Sends the frames recorded in `direction` to `io`, e.g. `Direction::Inbound` of a
+    /// client's recording to act as its pool, and returns what the peer sent, decoded with
+    /// `codec`.
+    ///
+    /// Fails with `TimedOut` or `UnexpectedEof` if the peer stops sending before the last
+    /// frame; after it, the peer's remaining frames are awaited up to `peer_timeout`.
+    pub async fn play<T, C>(
+        &self,
+        direction: Direction,
+        io: T,
+        codec: C,
+    ) -> io::Result<Vec<C::Item>>
+    where
+        T: AsyncRead + AsyncWrite,
+        C: Decoder<Error = io::Error>,
+    {
+        let (reader, mut writer) = tokio::io::split(io);
+        let mut reader = FramedRead::new(reader, codec);
+        let mut received = vec![];
+        let mut peer_frames = 0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            if frame.direction != direction {
+                peer_frames += 1;
+                continue;
+            }
+            while received.len() < peer_frames {
+                match tokio::time::timeout(self.peer_timeout, reader.next()).await {
+                    Ok(Some(item)) => received.push(item?),
+                    Ok(None) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                    Err(_) => return Err(io::ErrorKind::TimedOut.into()),
+                }
+            }
+            if i > 0 {
+                let gap = frame.elapsed.saturating_sub(self.frames[i - 1].elapsed);
+                tokio::time::sleep(self.timing.scale(gap)).await;
+            }
+            writer.write_all(&frame.data).await?;
+            writer.flush().await?;
+        }
+        while received.len() < peer_frames {
+            match tokio::time::timeout(self.peer_timeout, reader.next()).await {
+                Ok(Some(item)) => received.push(item?),
+                _ => break,
+            }
+        }
+        Ok(received)
+    }
+}
+
+#[test]
+fn test_timing() {
+    let gap = Duration::from_millis(100);
+    assert_eq!(Timing::Original.scale(gap), gap);
+    assert_eq!(Timing::Accelerated(4).scale(gap), Duration::from_millis(25));
+    assert_eq!(Timing::Accelerated(0).scale(gap), gap);
+    assert_eq!(Timing::Immediate.scale(gap), Duration::ZERO);
+}
+
+#[test]
+fn test_recording_partial_reads() {
+    use crate::message::stratum::{StratumCodec, StratumMessage};
+    use std::io::BufReader;
+
+    let lines: Vec<Vec<u8>> = (1..4)
+        .map(|epoch| {
+            let mut line = StratumMessage::SetEpoch(epoch).to_json();
+            line.push(b'\n');
+            line
+        })
+        .collect();
+    let path = std::env::temp_dir().join(format!("recording-partial-{}.jsonl", std::process::id()));
+    let mut codec = RecordingCodec::new(StratumCodec::default(), Recorder::create(&path).unwrap());
+
+    // the read buffer fills a few bytes at a time, frames end mid-read
+    let mut src = BytesMut::new();
+    let mut decoded = 0;
+    for piece in lines.concat().chunks(5) {
+        src.extend_from_slice(piece);
+        while codec.decode(&mut src).unwrap().is_some() {
+            decoded += 1;
+        }
+    }
+    assert_eq!(decoded, 3);
+    assert!(codec.unconsumed.is_empty());
+    drop(codec);
+
+    let frames = read_recording(BufReader::new(File::open(&path).unwrap())).unwrap();
+    let data: Vec<_> = frames.into_iter().map(|f| f.data).collect();
+    assert_eq!(data, lines);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_record_and_replay() {
+    use crate::message::response::ResponseMessage;
+    use crate::message::stratum::{StratumCodec, StratumMessage};
+    use futures_util::SinkExt;
+    use json_rpc_types::Id;
+
+    fn subscribe() -> StratumMessage {
+        StratumMessage::Subscribe(
+            Id::Num(0),
+            "ABMatrix_Aleo_Miner".to_string(),
+            "0.2.0".to_string(),
+            None,
+        )
+    }
+
+    let path = std::env::temp_dir().join(format!("recording-{}.jsonl", std::process::id()));
+    let (client, pool) = tokio::io::duplex(4096);
+    let mut client = record(
+        Framed::new(client, StratumCodec::default()),
+        Recorder::create(&path).unwrap(),
+    );
+    let mut pool = Framed::new(pool, StratumCodec::default());
+    client.send(subscribe()).await.unwrap();
+    assert!(matches!(
+        pool.next().await.unwrap().unwrap(),
+        StratumMessage::Subscribe(..)
+    ));
+    pool.send(StratumMessage::Response(
+        Id::Num(0),
+        Some(ResponseMessage::Bool(true)),
+        None,
+    ))
+    .await
+    .unwrap();
+    pool.send(StratumMessage::SetEpoch(3)).await.unwrap();
+    client.next().await.unwrap().unwrap();
+    client.next().await.unwrap().unwrap();
+    drop(client);
+
+    let replayer = Replayer::open(&path)
+        .unwrap()
+        .with_timing(Timing::Accelerated(10));
+    let directions: Vec<_> = replayer.frames().iter().map(|f| f.direction).collect();
+    assert_eq!(
+        directions,
+        vec![Direction::Outbound, Direction::Inbound, Direction::Inbound]
+    );
+    let mut subscribe_frame = subscribe().to_json().to_vec();
+    subscribe_frame.push(b'\n');
+    assert_eq!(replayer.frames()[0].data, subscribe_frame);
+
+    // replay the pool's side to a new client
+    let (client, pool) = tokio::io::duplex(4096);
+    let pool = tokio::spawn(async move {
+        replayer
+            .play(Direction::Inbound, pool, StratumCodec::default())
+            .await
+    });
+    let mut client = Framed::new(client, StratumCodec::default());
+    client.send(subscribe()).await.unwrap();
+    assert!(matches!(
+        client.next().await.unwrap().unwrap(),
+        StratumMessage::Response(Id::Num(0), Some(ResponseMessage::Bool(true)), None)
+    ));
+    assert!(matches!(
+        client.next().await.unwrap().unwrap(),
+        StratumMessage::SetEpoch(3)
+    ));
+    let mut received = pool.await.unwrap().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received.remove(0).to_json(), subscribe().to_json());
+    std::fs::remove_file(&path).unwrap();
+}
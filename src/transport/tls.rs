@@ -0,0 +1,203 @@
+use crate::message::stratum::StratumCodec;
+use anyhow::anyhow;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName};
+use std::io;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+use tokio_util::codec::Framed;
+
+pub type ClientTlsFramed = Framed<client::TlsStream<TcpStream>, StratumCodec>;
+pub type ServerTlsFramed = Framed<server::TlsStream<TcpStream>, StratumCodec>;
+
+/// Reads every certificate from a PEM file's contents.
+pub fn load_pem_certs(pem: &[u8]) -> anyhow::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut &pem[..])?;
+    if certs.is_empty() {
+        return Err(anyhow!("no certificate found"));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Reads the first PKCS#8, RSA or SEC1 private key from a PEM file's contents.
+pub fn load_pem_key(pem: &[u8]) -> anyhow::Result<PrivateKey> {
+    for item in rustls_pemfile::read_all(&mut &pem[..])? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(anyhow!("no private key found"))
+}
+
+/// Client config trusting only the given CA certificates, e.g. a private pool's own CA.
+pub fn client_config(ca_certs: &[Certificate]) -> anyhow::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in ca_certs {
+        roots.add(cert)?;
+    }
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// Client config that accepts exactly one server certificate, for pools using a
+/// self-signed certificate. Hostname and expiry are not checked, the pin is the trust.
+pub fn pinned_client_config(cert: Certificate) -> Arc<ClientConfig> {
+    let config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier(cert)))
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+pub fn server_config(
+    cert_chain: Vec<Certificate>,
+    key: PrivateKey,
+) -> anyhow::Result<Arc<ServerConfig>> {
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)?;
+    Ok(Arc::new(config))
+}
+
+struct PinnedCertVerifier(Certificate);
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity == &self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "server certificate does not match the pinned certificate".to_string(),
+            ))
+        }
+    }
+}
+
+/// Connects to a pool over TLS and frames the stream with `StratumCodec`.
+pub async fn connect<A: ToSocketAddrs>(
+    addr: A,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> io::Result<ClientTlsFramed> {
+    let server_name = ServerName::try_from(server_name)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let stream = TcpStream::connect(addr).await?;
+    let stream = TlsConnector::from(config)
+        .connect(server_name, stream)
+        .await?;
+    Ok(Framed::new(stream, StratumCodec::default()))
+}
+
+/// Runs the server side of the TLS handshake on an accepted connection.
+pub async fn accept(stream: TcpStream, config: Arc<ServerConfig>) -> io::Result<ServerTlsFramed> {
+    let stream = TlsAcceptor::from(config).accept(stream).await?;
+    Ok(Framed::new(stream, StratumCodec::default()))
+}
+
+#[cfg(test)]
+async fn roundtrip(
+    server: Arc<ServerConfig>,
+    client: Arc<ClientConfig>,
+    server_name: &str,
+) -> io::Result<()> {
+    use crate::message::stratum::StratumMessage;
+    use futures_util::{SinkExt, StreamExt};
+    use json_rpc_types::Id;
+    use tokio::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        if let Ok(mut framed) = accept(stream, server).await {
+            if let Some(Ok(StratumMessage::Subscribe(id, ..))) = framed.next().await {
+                let _ = framed.send(StratumMessage::Response(id, None, None)).await;
+            }
+        }
+    });
+
+    let mut framed = connect(addr, server_name, client).await?;
+    framed
+        .send(StratumMessage::Subscribe(
+            Id::Num(0),
+            "user_agent".to_string(),
+            "0.2.0".to_string(),
+            Some("password sent over tls".to_string()),
+        ))
+        .await?;
+    match framed.next().await {
+        Some(Ok(StratumMessage::Response(Id::Num(0), _, None))) => Ok(()),
+        Some(Err(e)) => Err(e),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected reply",
+        )),
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_tls_custom_ca() {
+    use rcgen::{BasicConstraints, CertificateParams, IsCa};
+
+    let mut ca_params = CertificateParams::new(vec![]);
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+    let leaf = rcgen::generate_simple_self_signed(vec!["pool.local".to_string()]).unwrap();
+    let leaf_pem = leaf.serialize_pem_with_signer(&ca).unwrap();
+    let ca_pem = ca.serialize_pem().unwrap();
+
+    let server = server_config(
+        load_pem_certs(leaf_pem.as_bytes()).unwrap(),
+        load_pem_key(leaf.serialize_private_key_pem().as_bytes()).unwrap(),
+    )
+    .unwrap();
+    let client = client_config(&load_pem_certs(ca_pem.as_bytes()).unwrap()).unwrap();
+    roundtrip(server.clone(), client.clone(), "pool.local")
+        .await
+        .unwrap();
+    // the certificate is not valid for another name
+    assert!(roundtrip(server, client, "other.local").await.is_err());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_tls_pinned_self_signed() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let der = Certificate(cert.serialize_der().unwrap());
+    let key = PrivateKey(cert.serialize_private_key_der());
+    let server = server_config(vec![der.clone()], key).unwrap();
+
+    // not trusted by a CA based config
+    let client = client_config(&[]).unwrap();
+    assert!(roundtrip(server.clone(), client, "localhost")
+        .await
+        .is_err());
+
+    roundtrip(server.clone(), pinned_client_config(der), "localhost")
+        .await
+        .unwrap();
+
+    let other = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let other = Certificate(other.serialize_der().unwrap());
+    assert!(roundtrip(server, pinned_client_config(other), "localhost")
+        .await
+        .is_err());
+}
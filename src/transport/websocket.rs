@@ -0,0 +1,223 @@
+use crate::message::stratum::{StratumMessage, MAX_STRATUM_LINE_LENGTH};
+use futures_util::{Sink, Stream};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+/// Carries one `StratumMessage` per WebSocket text frame, using the same JSON as
+/// `StratumCodec` without the newline delimiter.
+///
+/// Implements the same `Sink`/`Stream` interface as `Framed<_, StratumCodec>`.
+pub struct StratumWebSocket<S> {
+    inner: WebSocketStream<S>,
+}
+
+impl<S> StratumWebSocket<S> {
+    pub fn new(inner: WebSocketStream<S>) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.inner
+    }
+}
+
+/// Limits messages and frames to the longest line `StratumCodec` accepts, instead of
+/// tungstenite's 64 MiB default.
+fn config() -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(MAX_STRATUM_LINE_LENGTH),
+        max_frame_size: Some(MAX_STRATUM_LINE_LENGTH),
+        ..Default::default()
+    }
+}
+
+/// Connects to a `ws://` or `wss://` pool endpoint.
+pub async fn connect(url: &str) -> io::Result<StratumWebSocket<MaybeTlsStream<TcpStream>>> {
+    let (stream, _) = tokio_tungstenite::connect_async_with_config(url, Some(config()), false)
+        .await
+        .map_err(to_io_error)?;
+    Ok(StratumWebSocket::new(stream))
+}
+
+/// Runs the server side of the WebSocket handshake on an accepted connection.
+pub async fn accept<S>(stream: S) -> io::Result<StratumWebSocket<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let stream = tokio_tungstenite::accept_async_with_config(stream, Some(config()))
+        .await
+        .map_err(to_io_error)?;
+    Ok(StratumWebSocket::new(stream))
+}
+
+fn to_io_error(e: tokio_tungstenite::tungstenite::Error) -> io::Error {
+    match e {
+        tokio_tungstenite::tungstenite::Error::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+impl<S> Sink<StratumMessage> for StratumWebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_ready(cx)
+            .map_err(to_io_error)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: StratumMessage) -> Result<(), Self::Error> {
+        let text = String::from_utf8(item.to_json())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::Text(text))
+            .map_err(to_io_error)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(to_io_error)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(to_io_error)
+    }
+}
+
+impl<S> Stream for StratumWebSocket<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    type Item = Result<StratumMessage, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let msg = match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(msg))) => msg,
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(to_io_error(e)))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            match msg {
+                Message::Text(text) => {
+                    return Poll::Ready(Some(StratumMessage::from_json(text.as_bytes())))
+                }
+                Message::Binary(bytes) => {
+                    return Poll::Ready(Some(StratumMessage::from_json(&bytes)))
+                }
+                Message::Close(_) => return Poll::Ready(None),
+                // pings are answered by tungstenite itself
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_websocket_loopback() {
+    use crate::message::response::ResponseMessage;
+    use crate::message::stratum::StratumCodec;
+    use bytes::BytesMut;
+    use futures_util::{SinkExt, StreamExt};
+    use json_rpc_types::Id;
+    use tokio::net::TcpListener;
+    use tokio_util::codec::Encoder;
+
+    let notify = || {
+        StratumMessage::Notify(
+            "job_id".to_string(),
+            u64::MAX,
+            "block_header_root".to_string(),
+            "hashed_leaves_1".to_string(),
+            "hashed_leaves_2".to_string(),
+            "hashed_leaves_3".to_string(),
+            "hashed_leaves_4".to_string(),
+            true,
+            None,
+        )
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept(stream).await.unwrap();
+        while let Some(Ok(msg)) = ws.next().await {
+            if let StratumMessage::Authorize(id, ..) = msg {
+                ws.send(StratumMessage::Response(
+                    id,
+                    Some(ResponseMessage::Bool(true)),
+                    None,
+                ))
+                .await
+                .unwrap();
+                ws.send(notify()).await.unwrap();
+            }
+        }
+    });
+
+    let mut ws = connect(&format!("ws://{}", addr)).await.unwrap();
+    ws.send(StratumMessage::Authorize(
+        Id::Num(1),
+        "account_name".to_string(),
+        "miner_name".to_string(),
+        None,
+    ))
+    .await
+    .unwrap();
+    assert!(matches!(
+        ws.next().await,
+        Some(Ok(StratumMessage::Response(
+            Id::Num(1),
+            Some(ResponseMessage::Bool(true)),
+            None
+        )))
+    ));
+
+    // the text frame is exactly the StratumCodec line without its delimiter
+    let mut line = BytesMut::new();
+    StratumCodec::default().encode(notify(), &mut line).unwrap();
+    match ws.into_inner().next().await {
+        Some(Ok(Message::Text(text))) => {
+            assert_eq!(format!("{}\n", text).as_bytes(), &line[..]);
+        }
+        _ => panic!("expected a text frame"),
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_websocket_message_limit() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut ws = accept(stream).await.unwrap();
+        ws.next().await
+    });
+
+    let mut ws = connect(&format!("ws://{}", addr))
+        .await
+        .unwrap()
+        .into_inner();
+    ws.send(Message::Text("x".repeat(MAX_STRATUM_LINE_LENGTH + 1)))
+        .await
+        .unwrap();
+    assert!(matches!(server.await.unwrap(), Some(Err(_))));
+}
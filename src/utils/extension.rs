@@ -0,0 +1,80 @@
+//! Protocol extensions are offered as semver build metadata on the `mining.subscribe`
+//! protocol version, e.g. `0.2.0+binary.zstd`.
+
+use crate::message::response::ResponseMessage;
+use erased_serde::Serialize as ErasedSerialize;
+use serde_json::Value;
+
+/// Adds an extension to a protocol version, e.g. `0.2.0` -> `0.2.0+binary`.
+pub fn add_extension(protocol_version: &str, extension: &str) -> String {
+    if has_extension(protocol_version, extension) {
+        protocol_version.to_string()
+    } else if protocol_version.contains('+') {
+        format!("{}.{}", protocol_version, extension)
+    } else {
+        format!("{}+{}", protocol_version, extension)
+    }
+}
+
+/// Whether a protocol version offers the extension.
+pub fn has_extension(protocol_version: &str, extension: &str) -> bool {
+    match protocol_version.split_once('+') {
+        Some((_, build)) => build.split('.').any(|b| b == extension),
+        None => false,
+    }
+}
+
+/// Extensions a pool accepted, listed as strings in its `mining.subscribe` result.
+pub fn accepted_extensions(result: &Option<ResponseMessage>) -> Vec<String> {
+    match result {
+        Some(ResponseMessage::Array(items)) => items
+            .iter()
+            .filter_map(|item| match serde_json::to_value(item) {
+                Ok(Value::String(s)) => Some(s),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// `mining.subscribe` result a pool sends to accept the given extensions.
+pub fn accept_extensions(extensions: &[&str]) -> ResponseMessage {
+    ResponseMessage::Array(
+        extensions
+            .iter()
+            .map(|e| Box::new(e.to_string()) as Box<dyn ErasedSerialize + Send + Sync>)
+            .collect(),
+    )
+}
+
+#[test]
+fn test_extensions() {
+    let v = add_extension("0.2.0", "binary");
+    assert_eq!(v, "0.2.0+binary");
+    assert_eq!(add_extension(&v, "binary"), "0.2.0+binary");
+    let v = add_extension(&v, "zstd");
+    assert_eq!(v, "0.2.0+binary.zstd");
+    assert!(has_extension(&v, "binary"));
+    assert!(has_extension(&v, "zstd"));
+    assert!(!has_extension("0.2.0", "zstd"));
+    assert!(!has_extension("0.2.0+binaryx", "binary"));
+    assert!(semver::Version::parse(&v).is_ok());
+}
+
+#[test]
+fn test_accepted_extensions() {
+    use crate::message::stratum::StratumMessage;
+    use json_rpc_types::Id;
+
+    let response = StratumMessage::Response(Id::Num(0), Some(accept_extensions(&["zstd"])), None);
+    let decoded = StratumMessage::from_json(&response.to_json()).unwrap();
+    match decoded {
+        StratumMessage::Response(_, result, None) => {
+            assert_eq!(accepted_extensions(&result), vec!["zstd".to_string()]);
+        }
+        _ => panic!("expected response"),
+    }
+    assert!(accepted_extensions(&Some(ResponseMessage::Null)).is_empty());
+    assert!(accepted_extensions(&None).is_empty());
+}
@@ -10,7 +10,12 @@ pub fn get_height(job_id: String) -> anyhow::Result<u32> {
             if height_bytes.len() != 4 {
                 return Err(anyhow!("Invalid job_id"));
             }
-            u32::from_le_bytes([height_bytes[0], height_bytes[1], height_bytes[2], height_bytes[3]])
+            u32::from_le_bytes([
+                height_bytes[0],
+                height_bytes[1],
+                height_bytes[2],
+                height_bytes[3],
+            ])
         }
         Err(_) => {
             return Err(anyhow!("Invalid job_id"));
@@ -19,6 +24,31 @@ pub fn get_height(job_id: String) -> anyhow::Result<u32> {
     Ok(step2)
 }
 
+/// Job id that also carries the epoch, `<height>_<epoch>.<suffix>` with both numbers as
+/// little endian hex. `get_height` reads it like any other job id.
+pub fn epoch_job_id(height: u32, epoch: u32, suffix: &str) -> String {
+    format!(
+        "{}_{}.{}",
+        hex::encode(height.to_le_bytes()),
+        hex::encode(epoch.to_le_bytes()),
+        suffix
+    )
+}
+
+pub fn get_epoch(job_id: &str) -> anyhow::Result<u32> {
+    let (_, rest) = job_id
+        .split_once('_')
+        .ok_or_else(|| anyhow!("Invalid job_id"))?;
+    let (epoch, _) = rest
+        .split_once('.')
+        .ok_or_else(|| anyhow!("job_id has no epoch"))?;
+    let epoch: [u8; 4] = hex::decode(epoch)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| anyhow!("Invalid job_id"))?;
+    Ok(u32::from_le_bytes(epoch))
+}
+
 #[test]
 fn test_get_height() {
     let height_raw = 685514u32;
@@ -27,4 +57,13 @@ fn test_get_height() {
     println!("{}", &job_id);
     let height = get_height(job_id).unwrap();
     assert_eq!(height, height_raw)
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_epoch_job_id() {
+    let job_id = epoch_job_id(685514, 42, "3f");
+    assert_eq!(get_height(job_id.clone()).unwrap(), 685514);
+    assert_eq!(get_epoch(&job_id).unwrap(), 42);
+    assert!(get_epoch(&format!("{}_a4d8e", hex::encode(1u32.to_le_bytes()))).is_err());
+    assert!(get_epoch("nonsense").is_err());
+}
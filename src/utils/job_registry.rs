@@ -0,0 +1,185 @@
+use crate::message::error::PoolError;
+use crate::message::stratum::StratumMessage;
+use crate::utils::job_id::{epoch_job_id, get_epoch};
+use std::collections::VecDeque;
+
+/// A job the pool handed out, with the block height and puzzle epoch it belongs to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisteredJob<T> {
+    pub job_id: String,
+    pub height: u32,
+    pub epoch: u32,
+    pub job: T,
+}
+
+/// What changed with a newly registered job.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JobUpdate {
+    /// Same block, e.g. a new target
+    NewJob,
+    /// A new block in the same epoch, earlier jobs of the epoch stay valid
+    NewBlock,
+    /// A new epoch, every earlier job is stale
+    NewEpoch,
+}
+
+impl JobUpdate {
+    /// Whether miners should drop their current work.
+    pub fn clean_jobs(&self) -> bool {
+        *self == JobUpdate::NewEpoch
+    }
+
+    /// `mining.set_epoch` to send before the new job, on a new epoch.
+    pub fn epoch_signal(&self, epoch: u32) -> Option<StratumMessage> {
+        match self {
+            JobUpdate::NewEpoch => Some(StratumMessage::SetEpoch(epoch)),
+            _ => None,
+        }
+    }
+}
+
+/// How a share's job relates to the current one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShareStatus {
+    Current,
+    /// An earlier job of the current epoch, usually of a previous block
+    PreviousBlock,
+    /// A job of an earlier epoch
+    PreviousEpoch,
+    /// Never registered, or dropped for room
+    Unknown,
+}
+
+impl ShareStatus {
+    /// Shares are accepted until their epoch ends.
+    pub fn check(&self) -> Result<(), PoolError> {
+        match self {
+            ShareStatus::Current | ShareStatus::PreviousBlock => Ok(()),
+            ShareStatus::PreviousEpoch | ShareStatus::Unknown => Err(PoolError::StaleProof),
+        }
+    }
+}
+
+/// The jobs of the current epoch, newest last, for matching shares to their job.
+#[derive(Clone, Debug)]
+pub struct JobRegistry<T> {
+    jobs: VecDeque<RegisteredJob<T>>,
+    capacity: usize,
+    next_suffix: u64,
+}
+
+impl<T> JobRegistry<T> {
+    /// Keeps at most `capacity` jobs, dropping the oldest first.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            jobs: VecDeque::new(),
+            capacity: capacity.max(1),
+            next_suffix: 0,
+        }
+    }
+
+    pub fn current(&self) -> Option<&RegisteredJob<T>> {
+        self.jobs.back()
+    }
+
+    pub fn current_epoch(&self) -> Option<u32> {
+        self.current().map(|job| job.epoch)
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<&RegisteredJob<T>> {
+        self.jobs.iter().find(|job| job.job_id == job_id)
+    }
+
+    /// Registers the pool's latest job under a new epoch job id. Jobs of earlier epochs are
+    /// dropped on a new epoch.
+    pub fn register(&mut self, height: u32, epoch: u32, job: T) -> (&RegisteredJob<T>, JobUpdate) {
+        let update = match self.current() {
+            Some(current) if current.epoch != epoch => JobUpdate::NewEpoch,
+            Some(current) if current.height == height => JobUpdate::NewJob,
+            Some(_) => JobUpdate::NewBlock,
+            None => JobUpdate::NewEpoch,
+        };
+        if update == JobUpdate::NewEpoch {
+            self.jobs.clear();
+        }
+        if self.jobs.len() == self.capacity {
+            self.jobs.pop_front();
+        }
+        let job_id = epoch_job_id(height, epoch, &format!("{:x}", self.next_suffix));
+        self.next_suffix += 1;
+        self.jobs.push_back(RegisteredJob {
+            job_id,
+            height,
+            epoch,
+            job,
+        });
+        (self.jobs.back().unwrap(), update)
+    }
+
+    pub fn classify(&self, job_id: &str) -> ShareStatus {
+        let current = match self.current() {
+            Some(current) => current,
+            None => return ShareStatus::Unknown,
+        };
+        if current.job_id == job_id {
+            return ShareStatus::Current;
+        }
+        if self.get(job_id).is_some() {
+            return ShareStatus::PreviousBlock;
+        }
+        // jobs are dropped once their epoch ends, the id still tells which it was
+        match get_epoch(job_id) {
+            Ok(epoch) if epoch < current.epoch => ShareStatus::PreviousEpoch,
+            _ => ShareStatus::Unknown,
+        }
+    }
+
+    /// The job a share answers, `PoolError::StaleProof` once its epoch has ended.
+    pub fn check(&self, job_id: &str) -> Result<&RegisteredJob<T>, PoolError> {
+        self.classify(job_id).check()?;
+        self.get(job_id).ok_or(PoolError::StaleProof)
+    }
+}
+
+#[test]
+fn test_job_registry() {
+    let mut registry = JobRegistry::new(3);
+    assert_eq!(registry.classify("any"), ShareStatus::Unknown);
+
+    let (first, update) = registry.register(100, 1, "a");
+    let first = first.job_id.clone();
+    assert_eq!(update, JobUpdate::NewEpoch);
+    assert!(matches!(
+        update.epoch_signal(1),
+        Some(StratumMessage::SetEpoch(1))
+    ));
+    let (_, update) = registry.register(100, 1, "b");
+    assert_eq!(update, JobUpdate::NewJob);
+    let (second, update) = registry.register(101, 1, "c");
+    let second = second.job_id.clone();
+    assert_eq!(update, JobUpdate::NewBlock);
+    assert!(!update.clean_jobs());
+    assert!(update.epoch_signal(1).is_none());
+
+    // a new block keeps the epoch's shares
+    assert_eq!(registry.classify(&second), ShareStatus::Current);
+    assert_eq!(registry.classify(&first), ShareStatus::PreviousBlock);
+    assert_eq!(registry.check(&first).unwrap().job, "a");
+
+    // a new epoch does not
+    let (_, update) = registry.register(102, 2, "d");
+    assert_eq!(update, JobUpdate::NewEpoch);
+    assert!(update.clean_jobs());
+    assert_eq!(registry.current_epoch(), Some(2));
+    assert_eq!(registry.classify(&first), ShareStatus::PreviousEpoch);
+    assert_eq!(registry.check(&second).unwrap_err(), PoolError::StaleProof);
+    assert_eq!(registry.classify("unknown"), ShareStatus::Unknown);
+
+    // old jobs of the epoch make room
+    for job in ["e", "f", "g"] {
+        registry.register(103, 2, job);
+    }
+    assert_eq!(registry.current().unwrap().job, "g");
+    assert!(registry.get(&registry.current().unwrap().job_id).is_some());
+    assert_eq!(registry.jobs.len(), 3);
+}
@@ -1,2 +1,4 @@
+pub mod extension;
 pub mod job_id;
+pub mod job_registry;
 pub mod notify;
\ No newline at end of file
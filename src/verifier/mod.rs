@@ -0,0 +1,204 @@
+#[cfg(feature = "snarkvm")]
+pub mod snarkvm;
+
+use crate::message::error::PoolError;
+use crate::message::proof::{Nonce, ProofBytes, SubmitFormat};
+use crate::message::stratum::StratumMessage;
+use crate::utils::job_id::get_height;
+use crate::utils::notify::{decode_block_header_root, decode_hash_leaves};
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+
+/// A job as sent in `mining.notify`, decoded for verification.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub job_id: String,
+    /// Taken from the job id, see `utils::job_id`
+    pub height: u32,
+    pub difficulty_target: u64,
+    pub block_header_root: Vec<u8>,
+    pub hashed_leaves: Vec<Vec<u8>>,
+}
+
+impl Job {
+    pub fn from_notify(notify: &StratumMessage) -> anyhow::Result<Self> {
+        match notify {
+            StratumMessage::Notify(
+                job_id,
+                difficulty_target,
+                block_header_root,
+                hashed_leaves_1,
+                hashed_leaves_2,
+                hashed_leaves_3,
+                hashed_leaves_4,
+                ..,
+            ) => Ok(Self {
+                job_id: job_id.clone(),
+                height: get_height(job_id.clone())?,
+                difficulty_target: *difficulty_target,
+                block_header_root: decode_block_header_root(block_header_root)?,
+                hashed_leaves: decode_hash_leaves(&vec![
+                    hashed_leaves_1.clone(),
+                    hashed_leaves_2.clone(),
+                    hashed_leaves_3.clone(),
+                    hashed_leaves_4.clone(),
+                ])?,
+            }),
+            _ => Err(anyhow!("not a notify")),
+        }
+    }
+}
+
+/// The share of a `mining.submit`, decoded for verification.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Share {
+    pub job_id: String,
+    pub nonce: Nonce,
+    pub proof: ProofBytes,
+}
+
+impl Share {
+    /// Decodes a submit, `PoolError::InvalidProof` if it does not match `format`.
+    pub fn from_submit(submit: &StratumMessage, format: &SubmitFormat) -> Result<Self, PoolError> {
+        match submit {
+            StratumMessage::Submit(_, job_id, nonce, proof, _) => {
+                let (nonce, proof) = format.validate(nonce, proof)?;
+                Ok(Self {
+                    job_id: job_id.clone(),
+                    nonce,
+                    proof,
+                })
+            }
+            _ => Err(PoolError::InvalidProof(Some("not a submit".to_string()))),
+        }
+    }
+}
+
+/// Checks submitted shares against the job they answer. Implementations plug in a proof
+/// system, the `snarkvm` feature provides Aleo's.
+pub trait ProofVerifier: Send + Sync {
+    /// `Ok` accepts the share; shares for another job are `PoolError::StaleProof`.
+    fn verify(&self, job: &Job, share: &Share) -> Result<(), PoolError>;
+}
+
+/// Deterministic verifier for offline tests: the only valid proof of a nonce is
+/// `MockVerifier::prove`, and its difficulty is read from the proof's first 8 bytes.
+#[derive(Clone, Debug, Default)]
+pub struct MockVerifier;
+
+impl MockVerifier {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The proof this verifier accepts for `nonce`, if it meets the job's target.
+    pub fn prove(job: &Job, nonce: &Nonce) -> ProofBytes {
+        let mut hasher = Sha256::new();
+        hasher.update(job.job_id.as_bytes());
+        hasher.update(&job.block_header_root);
+        hasher.update(nonce.as_bytes());
+        ProofBytes::new(hasher.finalize().to_vec())
+    }
+
+    /// Lower is harder, as with Aleo proofs.
+    pub fn difficulty(proof: &ProofBytes) -> u64 {
+        let mut bytes = [0u8; 8];
+        let len = proof.as_bytes().len().min(8);
+        bytes[..len].copy_from_slice(&proof.as_bytes()[..len]);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl ProofVerifier for MockVerifier {
+    fn verify(&self, job: &Job, share: &Share) -> Result<(), PoolError> {
+        if share.job_id != job.job_id {
+            return Err(PoolError::StaleProof);
+        }
+        if share.proof != Self::prove(job, &share.nonce) {
+            return Err(PoolError::InvalidProof(Some("proof mismatch".to_string())));
+        }
+        if Self::difficulty(&share.proof) > job.difficulty_target {
+            return Err(PoolError::InvalidProof(Some(
+                "difficulty too low".to_string(),
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn test_job(difficulty_target: u64) -> Job {
+    let notify = StratumMessage::Notify(
+        format!("{}_job", hex::encode(7u32.to_le_bytes())),
+        difficulty_target,
+        "aa".repeat(32),
+        "01".repeat(32),
+        "02".repeat(32),
+        "03".repeat(32),
+        "04".repeat(32),
+        true,
+        None,
+    );
+    Job::from_notify(&notify).unwrap()
+}
+
+#[test]
+fn test_job_and_share() {
+    let job = test_job(u64::MAX);
+    assert_eq!(job.height, 7);
+    assert_eq!(job.block_header_root, vec![0xaa; 32]);
+    assert_eq!(job.hashed_leaves[3], vec![4; 32]);
+    assert!(Job::from_notify(&StratumMessage::SetExtranonce("".to_string(), None)).is_err());
+
+    let submit = StratumMessage::Submit(
+        json_rpc_types::Id::Num(1),
+        job.job_id.clone(),
+        "0001020304050607".to_string(),
+        "abcd".to_string(),
+        None,
+    );
+    let share = Share::from_submit(&submit, &SubmitFormat::default()).unwrap();
+    assert_eq!(share.nonce.as_bytes(), &[0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(share.proof.as_bytes(), &[0xab, 0xcd]);
+    let strict = SubmitFormat {
+        proof_length: Some(32),
+        ..Default::default()
+    };
+    assert!(matches!(
+        Share::from_submit(&submit, &strict),
+        Err(PoolError::InvalidProof(Some(_)))
+    ));
+}
+
+#[test]
+fn test_mock_verifier() {
+    let verifier: Box<dyn ProofVerifier> = Box::new(MockVerifier::new());
+    let job = test_job(u64::MAX);
+    let nonce = Nonce::new(vec![1; 8]);
+    let share = Share {
+        job_id: job.job_id.clone(),
+        proof: MockVerifier::prove(&job, &nonce),
+        nonce,
+    };
+    assert_eq!(verifier.verify(&job, &share), Ok(()));
+
+    let mut stale = share.clone();
+    stale.job_id = "other".to_string();
+    assert_eq!(verifier.verify(&job, &stale), Err(PoolError::StaleProof));
+
+    let mut forged = share.clone();
+    forged.proof = ProofBytes::new(vec![0; 32]);
+    assert_eq!(
+        verifier.verify(&job, &forged),
+        Err(PoolError::InvalidProof(Some("proof mismatch".to_string())))
+    );
+
+    // the same share misses a target below its difficulty
+    let hard = test_job(MockVerifier::difficulty(&share.proof) - 1);
+    assert_eq!(
+        verifier.verify(&hard, &share),
+        Err(PoolError::InvalidProof(Some(
+            "difficulty too low".to_string()
+        )))
+    );
+}
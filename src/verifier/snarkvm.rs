@@ -0,0 +1,61 @@
+use super::{Job, ProofVerifier, Share};
+use crate::message::error::PoolError;
+use snarkvm_dpc::prelude::*;
+use snarkvm_dpc::testnet2::Testnet2;
+use snarkvm_utilities::FromBytes;
+use std::marker::PhantomData;
+
+/// Verifies shares as Aleo PoSW proofs of the job's block header root.
+///
+/// The submitted nonce is the little endian low bytes of the PoSW nonce, the proof is a
+/// serialized `PoSWProof`.
+pub struct SnarkVmVerifier<N: Network = Testnet2> {
+    _network: PhantomData<N>,
+}
+
+impl<N: Network> Default for SnarkVmVerifier<N> {
+    fn default() -> Self {
+        Self {
+            _network: PhantomData,
+        }
+    }
+}
+
+impl<N: Network> SnarkVmVerifier<N> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn invalid(reason: &str) -> PoolError {
+    PoolError::InvalidProof(Some(reason.to_string()))
+}
+
+/// Reads a field element from fewer little endian bytes than it takes, zero extended.
+fn read_field<F: FromBytes>(bytes: &[u8], size: usize) -> Option<F> {
+    let mut bytes = bytes.to_vec();
+    if bytes.len() > size {
+        return None;
+    }
+    bytes.resize(size, 0);
+    F::read_le(&bytes[..]).ok()
+}
+
+impl<N: Network> ProofVerifier for SnarkVmVerifier<N> {
+    fn verify(&self, job: &Job, share: &Share) -> Result<(), PoolError> {
+        if share.job_id != job.job_id {
+            return Err(PoolError::StaleProof);
+        }
+        let root: N::InnerScalarField =
+            read_field(&job.block_header_root, 32).ok_or(PoolError::InternalServerError)?;
+        let nonce: N::InnerScalarField =
+            read_field(share.nonce.as_bytes(), 32).ok_or_else(|| invalid("bad nonce"))?;
+        let proof = N::PoSWProof::read_le(share.proof.as_bytes())
+            .map_err(|_| invalid("bad proof encoding"))?;
+        if N::posw().verify(job.height, job.difficulty_target, &[root, nonce], &proof) {
+            Ok(())
+        } else {
+            Err(PoolError::InvalidProof(None))
+        }
+    }
+}